@@ -0,0 +1,213 @@
+use std::io;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+/// Limits the number of directories read concurrently, adjusting the limit
+/// to the latency of reads.
+///
+/// The limit starts at its minimum and grows by one after as many reads as
+/// it allows completed without their latency per entry rising well above
+/// its long term average. Rising latency shrinks it by one, and running out
+/// of file descriptors halves it.
+pub(crate) struct AdaptiveLimiter {
+    min: usize,
+    max: usize,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+struct State {
+    limit: usize,
+    active_reads: usize,
+    // reads completed since the limit last changed
+    completed_reads: usize,
+    // latency per entry, averaged over the last few and many reads
+    recent_latency: Option<Duration>,
+    average_latency: Option<Duration>,
+}
+
+/// Permission to read a directory, released when dropped.
+pub(crate) struct AdaptivePermit<'a> {
+    limiter: &'a AdaptiveLimiter,
+    started: Instant,
+    entries: usize,
+    overloaded: bool,
+}
+
+impl AdaptiveLimiter {
+    pub(crate) fn new(min: usize, max: usize) -> AdaptiveLimiter {
+        let min = min.max(1);
+        AdaptiveLimiter {
+            min,
+            max: max.max(min),
+            state: Mutex::new(State {
+                limit: min,
+                active_reads: 0,
+                completed_reads: 0,
+                recent_latency: None,
+                average_latency: None,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until the current limit allows another read.
+    pub(crate) fn acquire(&self) -> AdaptivePermit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.active_reads >= state.limit {
+            state = self.released.wait(state).unwrap();
+        }
+        state.active_reads += 1;
+        AdaptivePermit {
+            limiter: self,
+            started: Instant::now(),
+            entries: 0,
+            overloaded: false,
+        }
+    }
+
+    fn release(&self, latency: Duration, overloaded: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.active_reads -= 1;
+        let recent_latency = average(state.recent_latency, latency, 4);
+        let average_latency = average(state.average_latency, latency, 32);
+        state.recent_latency = Some(recent_latency);
+        state.average_latency = Some(average_latency);
+
+        let limit = if overloaded {
+            (state.limit / 2).max(self.min)
+        } else if recent_latency > average_latency * 2 {
+            (state.limit - 1).max(self.min)
+        } else if state.completed_reads + 1 >= state.limit {
+            (state.limit + 1).min(self.max)
+        } else {
+            state.limit
+        };
+        if limit == state.limit {
+            state.completed_reads += 1;
+        } else {
+            state.limit = limit;
+            state.completed_reads = 0;
+        }
+        self.released.notify_all();
+    }
+}
+
+impl AdaptivePermit<'_> {
+    /// Record the number of entries read, latencies are compared per entry.
+    pub(crate) fn set_entries(&mut self, entries: usize) {
+        self.entries = entries;
+    }
+
+    /// Record the error reading the directory, errors signalling overload
+    /// shrink the limit.
+    pub(crate) fn set_error(&mut self, err: &Error) {
+        self.overloaded = err.io_error().is_some_and(is_overload);
+    }
+}
+
+impl Drop for AdaptivePermit<'_> {
+    fn drop(&mut self) {
+        let latency = self.started.elapsed()
+            / u32::try_from(self.entries)
+                .unwrap_or(u32::MAX)
+                .saturating_add(1);
+        self.limiter.release(latency, self.overloaded);
+    }
+}
+
+// Exponential moving average over about `window` samples.
+fn average(average: Option<Duration>, sample: Duration, window: u32) -> Duration {
+    match average {
+        Some(average) => (average * (window - 1) + sample) / window,
+        None => sample,
+    }
+}
+
+// Too many open files, for the process or the system.
+#[cfg(unix)]
+fn is_overload(err: &io::Error) -> bool {
+    const ENFILE: i32 = 23;
+    const EMFILE: i32 = 24;
+    matches!(err.raw_os_error(), Some(ENFILE | EMFILE))
+}
+
+#[cfg(windows)]
+fn is_overload(err: &io::Error) -> bool {
+    const ERROR_TOO_MANY_OPEN_FILES: i32 = 4;
+    err.raw_os_error() == Some(ERROR_TOO_MANY_OPEN_FILES)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_overload(_err: &io::Error) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Complete a read taking `latency`.
+    fn read(limiter: &AdaptiveLimiter, latency: Duration) {
+        std::mem::forget(limiter.acquire());
+        limiter.release(latency, false);
+    }
+
+    fn limit(limiter: &AdaptiveLimiter) -> usize {
+        limiter.state.lock().unwrap().limit
+    }
+
+    #[test]
+    fn grows_with_low_latency() {
+        let limiter = AdaptiveLimiter::new(2, 4);
+        assert_eq!(2, limit(&limiter));
+        for _ in 0..2 {
+            read(&limiter, Duration::from_millis(1));
+        }
+        assert_eq!(3, limit(&limiter));
+        for _ in 0..20 {
+            read(&limiter, Duration::from_millis(1));
+        }
+        assert_eq!(4, limit(&limiter));
+    }
+
+    #[test]
+    fn shrinks_with_rising_latency() {
+        let limiter = AdaptiveLimiter::new(2, 4);
+        for _ in 0..20 {
+            read(&limiter, Duration::from_millis(1));
+        }
+        assert_eq!(4, limit(&limiter));
+        read(&limiter, Duration::from_millis(100));
+        assert_eq!(3, limit(&limiter));
+        for _ in 0..5 {
+            read(&limiter, Duration::from_millis(1000));
+        }
+        assert_eq!(2, limit(&limiter));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn shrinks_on_overload() {
+        use crate::Operation;
+
+        const EMFILE: i32 = 24;
+        let overload = || {
+            let err = io::Error::from_raw_os_error(EMFILE);
+            Error::from_path(1, "dir".into(), Operation::ReadDir, err)
+        };
+        let limiter = AdaptiveLimiter::new(2, 8);
+        for _ in 0..40 {
+            read(&limiter, Duration::from_millis(1));
+        }
+        assert_eq!(8, limit(&limiter));
+        limiter.acquire().set_error(&overload());
+        assert_eq!(4, limit(&limiter));
+        for _ in 0..2 {
+            limiter.acquire().set_error(&overload());
+        }
+        assert_eq!(2, limit(&limiter));
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+
+use super::*;
+use crate::Result;
+
+/// Iterator returned by [`DirEntryIter::aggregate`].
+///
+/// Yields entries contents first, that is each directory is yielded after
+/// all of its descendants were folded into it.
+pub struct Aggregate<C: ClientState, F> {
+    iter: DirEntryIter<C>,
+    fold: F,
+    // entries whose descendants might not have been seen yet, by depth
+    pending: Vec<DirEntry<C>>,
+    // entries ready to be yielded
+    ready: VecDeque<Result<DirEntry<C>>>,
+    done: bool,
+    // entries arrive depth first, otherwise they are passed through
+    depth_first: bool,
+}
+
+impl<C, F> Aggregate<C, F>
+where
+    C: ClientState,
+    F: FnMut(&mut DirEntry<C>, &DirEntry<C>),
+{
+    pub(crate) fn new(iter: DirEntryIter<C>, fold: F, depth_first: bool) -> Self {
+        Aggregate {
+            iter,
+            fold,
+            pending: Vec::new(),
+            ready: VecDeque::new(),
+            done: false,
+            depth_first,
+        }
+    }
+
+    // Complete all pending entries at `depth` or deeper by folding them into
+    // their parent.
+    fn complete_pending(&mut self, depth: usize) {
+        while self.pending.last().is_some_and(|last| last.depth >= depth) {
+            let dir_entry = self.pending.pop().unwrap();
+            if let Some(parent) = self.pending.last_mut() {
+                if parent.depth + 1 == dir_entry.depth {
+                    (self.fold)(parent, &dir_entry);
+                }
+            }
+            self.ready.push_back(Ok(dir_entry));
+        }
+    }
+}
+
+impl<C, F> Iterator for Aggregate<C, F>
+where
+    C: ClientState,
+    F: FnMut(&mut DirEntry<C>, &DirEntry<C>),
+{
+    type Item = Result<DirEntry<C>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.depth_first {
+            return self.iter.next();
+        }
+        loop {
+            if let Some(dir_entry_result) = self.ready.pop_front() {
+                return Some(dir_entry_result);
+            }
+            if self.done {
+                return None;
+            }
+            match self.iter.next() {
+                Some(Ok(dir_entry)) => {
+                    self.complete_pending(dir_entry.depth);
+                    self.pending.push(dir_entry);
+                }
+                Some(Err(err)) => self.ready.push_back(Err(err)),
+                None => {
+                    self.complete_pending(0);
+                    self.done = true;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+
+/// Tells whether more directories may be read while the walk is paused or
+/// too many entries were read but not yet consumed.
+///
+/// Reads are only dispatched while there is capacity, so no worker waits
+/// for it. As results are consumed in strict order, the consumer may wait
+/// for a directory that wasn't read yet while capacity is used up by later
+/// results, or while the walk is paused. Reads continue while the consumer
+/// is `starved` in this way to avoid deadlocking, and only then.
+pub(crate) struct Backpressure {
+    max_pending_entries: Option<usize>,
+    pending_entries: AtomicUsize,
+    paused: AtomicBool,
+    starved: AtomicBool,
+}
+
+impl Backpressure {
+    pub(crate) fn new(max_pending_entries: Option<usize>) -> Backpressure {
+        Backpressure {
+            max_pending_entries,
+            pending_entries: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            starved: AtomicBool::new(false),
+        }
+    }
+
+    // Whether pending entries are counted at all.
+    pub(crate) fn is_bounded(&self) -> bool {
+        self.max_pending_entries.is_some()
+    }
+
+    pub(crate) fn add_pending(&self, count: usize) {
+        if self.is_bounded() {
+            self.pending_entries
+                .fetch_add(count, AtomicOrdering::SeqCst);
+        }
+    }
+
+    pub(crate) fn remove_pending(&self, count: usize) {
+        if self.is_bounded() {
+            self.pending_entries
+                .fetch_sub(count, AtomicOrdering::SeqCst);
+        }
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, AtomicOrdering::SeqCst);
+    }
+
+    // Set while the consumer waits for a directory in `next()`.
+    pub(crate) fn set_starved(&self, starved: bool) {
+        self.starved.store(starved, AtomicOrdering::SeqCst);
+    }
+
+    pub(crate) fn has_capacity(&self) -> bool {
+        let has_room = !self.paused.load(AtomicOrdering::SeqCst)
+            && self.max_pending_entries.is_none_or(|max_pending_entries| {
+                self.pending_entries.load(AtomicOrdering::SeqCst) < max_pending_entries
+            });
+        has_room || self.starved.load(AtomicOrdering::SeqCst)
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+/// Position of a walk from which it can be resumed, see
+/// [`DirEntryIter::checkpoint`](struct.DirEntryIter.html#method.checkpoint)
+/// and [`resume_from`](struct.WalkDirGeneric.html#method.resume_from).
+///
+/// Consists of the entries that were not yielded yet, in the order they
+/// would have been yielded. The contents of all other directories were
+/// yielded completely, so walking these entries finishes the walk.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    /// Entries left to yield.
+    pub pending: Vec<PendingEntry>,
+}
+
+/// An entry that was not yielded yet when a [`Checkpoint`] was taken.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingEntry {
+    /// Path of the entry.
+    pub path: PathBuf,
+    /// Depth of the entry relative to the root of the walk.
+    pub depth: usize,
+    /// Whether the contents of the entry are read. `false` for files and for
+    /// directories that were excluded from reading through
+    /// [`read_children_path`](struct.DirEntry.html#structfield.read_children_path).
+    pub read_children: bool,
+}
+
+impl Checkpoint {
+    /// Returns `true` if there is nothing left to walk.
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use super::*;
+use crate::{ListingCache, WalkDirGeneric};
+
+// Walk with `iterative_deepening`, walked again one level deeper in each
+// round.
+pub(crate) struct Deepening<C: ClientState> {
+    walk_dir: WalkDirGeneric<C>,
+    // depths of the entries the current round yields
+    min_depth: usize,
+    depth: usize,
+    // a directory was seen at `depth`, so the next round yields entries
+    deeper: bool,
+    // entries above this depth were yielded in an earlier round
+    walked_depth: usize,
+    round_state: RoundState,
+}
+
+// State of a walk shared by all of its rounds, so that each round continues
+// where the last one stopped rather than starting over.
+#[derive(Clone, Default)]
+pub(crate) struct RoundState {
+    pub(crate) seen_hardlinks: Arc<Mutex<HashSet<(u64, u64)>>>,
+    pub(crate) seen_dirs: Arc<Mutex<HashMap<(u64, u64), PathBuf>>>,
+    // directories above this depth were read in an earlier round
+    pub(crate) read_depth: usize,
+}
+
+impl<C: ClientState> Deepening<C> {
+    // Iterator of the first round, walking to depth 1 or `min_depth`.
+    pub(crate) fn start(mut walk_dir: WalkDirGeneric<C>) -> DirEntryIter<C> {
+        walk_dir.options.iterative_deepening = false;
+        if walk_dir.options.listing_cache.is_none() {
+            let listings: Arc<dyn ListingCache> = Arc::new(Mutex::new(HashMap::new()));
+            walk_dir.options.listing_cache = Some(listings);
+        }
+        let deepening = Deepening {
+            min_depth: walk_dir.options.min_depth,
+            depth: walk_dir
+                .options
+                .min_depth
+                .max(1)
+                .min(walk_dir.options.max_depth),
+            deeper: false,
+            walked_depth: 0,
+            round_state: RoundState::default(),
+            walk_dir,
+        };
+        deepening.round()
+    }
+
+    // Iterator of the round after this one, unless it would be empty.
+    pub(crate) fn next_round(mut self) -> Option<DirEntryIter<C>> {
+        if !self.can_deepen() {
+            return None;
+        }
+        self.depth += 1;
+        self.min_depth = self.depth;
+        self.deeper = false;
+        self.walked_depth = self.depth;
+        self.round_state.read_depth = self.depth - 1;
+        Some(self.round())
+    }
+
+    pub(crate) fn can_deepen(&self) -> bool {
+        self.deeper && self.depth < self.walk_dir.options.max_depth
+    }
+
+    // Note that `dir_entry` is a directory to read in the next round.
+    pub(crate) fn note(deepening: Option<&mut Self>, dir_entry: &DirEntry<C>) {
+        if let Some(deepening) = deepening {
+            if dir_entry.depth == deepening.depth && dir_entry.read_children_path.is_some() {
+                deepening.deeper = true;
+            }
+        }
+    }
+
+    // Whether `err` was yielded in an earlier round already. Directories are
+    // only checked for changes in the round reading them first.
+    pub(crate) fn repeats(deepening: Option<&Self>, err: &Error) -> bool {
+        deepening.is_some_and(|deepening| {
+            err.depth() < deepening.walked_depth && !err.is_busy() && !err.is_changed()
+        })
+    }
+
+    fn round(self) -> DirEntryIter<C> {
+        let mut walk_dir = self.walk_dir.clone();
+        walk_dir.options.min_depth = self.min_depth;
+        walk_dir.options.max_depth = self.depth;
+        walk_dir.options.round_state = Some(self.round_state.clone());
+        let mut dir_entry_iter = walk_dir.into_iter();
+        dir_entry_iter.deepening = Some(self);
+        dir_entry_iter
+    }
+}
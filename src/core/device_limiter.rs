@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+
+/// Limits the number of directories read concurrently from each device.
+///
+/// Reads from different devices don't wait for each other, so all threads
+/// are used when walking across several devices.
+pub(crate) struct DeviceLimiter {
+    max_concurrent_reads: usize,
+    active_reads: Mutex<HashMap<u64, usize>>,
+    released: Condvar,
+}
+
+/// Permission to read from a device, released when dropped.
+pub(crate) struct DevicePermit<'a> {
+    limiter: &'a DeviceLimiter,
+    device: u64,
+}
+
+impl DeviceLimiter {
+    pub(crate) fn new(max_concurrent_reads: usize) -> DeviceLimiter {
+        DeviceLimiter {
+            max_concurrent_reads: max_concurrent_reads.max(1),
+            active_reads: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until the device `path` resides on has capacity for another
+    /// read.
+    pub(crate) fn acquire(&self, path: &Path) -> DevicePermit<'_> {
+        let device = device_of(path);
+        let mut active_reads = self.active_reads.lock().unwrap();
+        while active_reads.get(&device).copied().unwrap_or(0) >= self.max_concurrent_reads {
+            active_reads = self.released.wait(active_reads).unwrap();
+        }
+        *active_reads.entry(device).or_insert(0) += 1;
+        DevicePermit {
+            limiter: self,
+            device,
+        }
+    }
+}
+
+impl Drop for DevicePermit<'_> {
+    fn drop(&mut self) {
+        let mut active_reads = self.limiter.active_reads.lock().unwrap();
+        if let Some(count) = active_reads.get_mut(&self.device) {
+            *count -= 1;
+            if *count == 0 {
+                active_reads.remove(&self.device);
+            }
+        }
+        self.limiter.released.notify_all();
+    }
+}
+
+#[cfg(unix)]
+fn device_of(path: &Path) -> u64 {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path)
+        .map(|metadata| metadata.dev())
+        .unwrap_or(0)
+}
+
+// Without device ids all paths are treated as residing on one device.
+#[cfg(not(unix))]
+fn device_of(_path: &Path) -> u64 {
+    0
+}
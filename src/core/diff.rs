@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::FileType;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::*;
+use crate::Result;
+
+/// Paths and metadata of the entries of an earlier walk, to compare a new
+/// walk against with [`diff`](../fn.diff.html).
+///
+/// Collect it from the entries of a walk, or record them while iterating a
+/// [`DiffIter`] to compare the next walk against this one.
+///
+/// ```no_run
+/// use jwalk::{diff, Change, Snapshot, WalkDir};
+///
+/// let snapshot: Snapshot = WalkDir::new("foo").into_iter().flatten().collect();
+/// // ... later
+/// for change in diff(snapshot, WalkDir::new("foo")) {
+///     if let Change::Added(dir_entry) = change? {
+///         println!("added {}", dir_entry.path().display());
+///     }
+/// }
+/// # Ok::<(), jwalk::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    // Entries by the path of their directory, in the order of their names
+    dirs: HashMap<PathBuf, BTreeMap<OsString, EntryState>>,
+    len: usize,
+}
+
+// What is compared of an entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct EntryState {
+    file_type: FileType,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// How an entry changed since a [`Snapshot`] was taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+    Unchanged,
+}
+
+/// Item of [`DiffIter`], see [`diff`](../fn.diff.html).
+#[derive(Debug)]
+pub enum Change<C: ClientState> {
+    /// An entry that isn't in the snapshot.
+    Added(DirEntry<C>),
+    /// An entry of the snapshot that is gone. It has the type it had then,
+    /// its metadata can't be read anymore.
+    Removed(DirEntry<C>),
+    /// An entry whose type, size or modification time changed. Directories
+    /// change when entries were added to or removed from them.
+    Modified(DirEntry<C>),
+    /// An entry that didn't change.
+    Unchanged(DirEntry<C>),
+}
+
+impl<C: ClientState> Change<C> {
+    /// The entry that changed.
+    pub fn dir_entry(&self) -> &DirEntry<C> {
+        match self {
+            Change::Added(dir_entry)
+            | Change::Removed(dir_entry)
+            | Change::Modified(dir_entry)
+            | Change::Unchanged(dir_entry) => dir_entry,
+        }
+    }
+
+    /// The entry that changed.
+    pub fn into_dir_entry(self) -> DirEntry<C> {
+        match self {
+            Change::Added(dir_entry)
+            | Change::Removed(dir_entry)
+            | Change::Modified(dir_entry)
+            | Change::Unchanged(dir_entry) => dir_entry,
+        }
+    }
+}
+
+impl Snapshot {
+    /// An empty snapshot, against which all entries are added.
+    pub fn new() -> Self {
+        Snapshot::default()
+    }
+
+    /// Record the path, type, size and modification time of `dir_entry`,
+    /// reading its metadata.
+    pub fn insert<C: ClientState>(&mut self, dir_entry: &DirEntry<C>) {
+        let (dir, file_name) = split_path(&dir_entry.path());
+        let entries = self.dirs.entry(dir).or_default();
+        if entries
+            .insert(file_name, EntryState::of(dir_entry))
+            .is_none()
+        {
+            self.len += 1;
+        }
+    }
+
+    /// The number of entries in the snapshot.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// True if the snapshot has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Mark the entries read from the directory at `dir` with their change,
+    // reading their metadata, and append the removed ones.
+    pub(crate) fn diff_read_dir<C: ClientState>(
+        &self,
+        dir: &Arc<Path>,
+        depth: usize,
+        dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+        keep_removed: impl Fn(FileType) -> bool,
+    ) {
+        let old_entries = self.dirs.get(dir.as_ref());
+        for dir_entry in dir_entry_results.iter_mut().flatten() {
+            let old_state = old_entries.and_then(|entries| entries.get(&dir_entry.file_name));
+            dir_entry.change = Some(change_of(old_state, dir_entry));
+        }
+        let old_entries = match old_entries {
+            Some(old_entries) => old_entries,
+            None => return,
+        };
+        let listed: HashSet<&OsString> = dir_entry_results
+            .iter()
+            .flatten()
+            .map(|dir_entry| &dir_entry.file_name)
+            .collect();
+        let removed: Vec<_> = old_entries
+            .iter()
+            .filter(|(file_name, old_state)| {
+                !listed.contains(file_name) && keep_removed(old_state.file_type)
+            })
+            .map(|(file_name, old_state)| Ok(removed_entry(dir, depth, file_name, old_state)))
+            .collect();
+        dir_entry_results.extend(removed);
+    }
+
+    // Removed entries of the directory at `dir` that was removed itself.
+    fn removed_entries<C: ClientState>(&self, dir: &Arc<Path>, depth: usize) -> Vec<DirEntry<C>> {
+        self.dirs
+            .get(dir.as_ref())
+            .map_or_else(Vec::new, |entries| {
+                entries
+                    .iter()
+                    .map(|(file_name, old_state)| removed_entry(dir, depth, file_name, old_state))
+                    .collect()
+            })
+    }
+
+    fn was_dir(&self, path: &Path) -> bool {
+        let (dir, file_name) = split_path(path);
+        self.dirs
+            .get(&dir)
+            .and_then(|entries| entries.get(&file_name))
+            .is_some_and(|old_state| old_state.file_type.is_dir())
+    }
+}
+
+impl<C: ClientState> FromIterator<DirEntry<C>> for Snapshot {
+    fn from_iter<I: IntoIterator<Item = DirEntry<C>>>(iter: I) -> Self {
+        let mut snapshot = Snapshot::new();
+        snapshot.extend(iter);
+        snapshot
+    }
+}
+
+impl<C: ClientState> Extend<DirEntry<C>> for Snapshot {
+    fn extend<I: IntoIterator<Item = DirEntry<C>>>(&mut self, iter: I) {
+        for dir_entry in iter {
+            self.insert(&dir_entry);
+        }
+    }
+}
+
+impl EntryState {
+    fn of<C: ClientState>(dir_entry: &DirEntry<C>) -> Self {
+        let metadata = dir_entry.metadata().ok();
+        EntryState {
+            file_type: dir_entry.file_type,
+            len: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+            modified: metadata.and_then(|metadata| metadata.modified().ok()),
+        }
+    }
+}
+
+// The directory and name of the entry at `path`. Paths without a name,
+// like `.`, are their own name.
+fn split_path(path: &Path) -> (PathBuf, OsString) {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(file_name)) => (dir.to_owned(), file_name.to_owned()),
+        _ => (PathBuf::new(), path.as_os_str().to_owned()),
+    }
+}
+
+fn change_of<C: ClientState>(
+    old_state: Option<&EntryState>,
+    dir_entry: &DirEntry<C>,
+) -> ChangeKind {
+    match old_state {
+        None => ChangeKind::Added,
+        Some(old_state) if *old_state == EntryState::of(dir_entry) => ChangeKind::Unchanged,
+        Some(_) => ChangeKind::Modified,
+    }
+}
+
+fn removed_entry<C: ClientState>(
+    dir: &Arc<Path>,
+    depth: usize,
+    file_name: &OsString,
+    old_state: &EntryState,
+) -> DirEntry<C> {
+    let mut dir_entry = DirEntry::from_file_type(
+        depth,
+        dir.clone(),
+        file_name.clone(),
+        old_state.file_type,
+        Default::default(),
+    );
+    dir_entry.read_children_path = None;
+    dir_entry.change = Some(ChangeKind::Removed);
+    dir_entry
+}
+
+/// Change iterator from [`diff`](../fn.diff.html).
+///
+/// Yields the entries of a [`DirEntryIter`] in the same order as
+/// [`Change`]s against a [`Snapshot`]. Removed entries follow the entries of
+/// their directory, and the contents of removed directories follow them.
+pub struct DiffIter<C: ClientState> {
+    iter: DirEntryIter<C>,
+    snapshot: Arc<Snapshot>,
+    // contents of removed directories left to yield, next last
+    removed: Vec<DirEntry<C>>,
+}
+
+impl<C: ClientState> DiffIter<C> {
+    pub(crate) fn new(iter: DirEntryIter<C>, snapshot: Arc<Snapshot>) -> Self {
+        DiffIter {
+            iter,
+            snapshot,
+            removed: Vec::new(),
+        }
+    }
+
+    // Queue the former contents of the directory at `path`, which is gone
+    // or not a directory anymore.
+    fn remove_contents(&mut self, path: &Path, depth: usize) {
+        let mut removed = self.snapshot.removed_entries(&Arc::from(path), depth + 1);
+        removed.reverse();
+        self.removed.extend(removed);
+    }
+}
+
+impl<C: ClientState> Iterator for DiffIter<C> {
+    type Item = Result<Change<C>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut dir_entry = match self.removed.pop() {
+            Some(dir_entry) => dir_entry,
+            None => match self.iter.next()? {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => return Some(Err(err)),
+            },
+        };
+        // Roots aren't read by workers
+        let change = dir_entry.change.take().unwrap_or_else(|| {
+            let (dir, file_name) = split_path(&dir_entry.path());
+            let old_state = self
+                .snapshot
+                .dirs
+                .get(&dir)
+                .and_then(|entries| entries.get(&file_name));
+            change_of(old_state, &dir_entry)
+        });
+        if change == ChangeKind::Removed || !dir_entry.file_type.is_dir() {
+            let path = dir_entry.path();
+            if self.snapshot.was_dir(&path) {
+                self.remove_contents(&path, dir_entry.depth);
+            }
+        }
+        Some(Ok(match change {
+            ChangeKind::Added => Change::Added(dir_entry),
+            ChangeKind::Removed => Change::Removed(dir_entry),
+            ChangeKind::Modified => Change::Modified(dir_entry),
+            ChangeKind::Unchanged => Change::Unchanged(dir_entry),
+        }))
+    }
+}
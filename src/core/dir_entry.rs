@@ -1,10 +1,17 @@
+use std::collections::{HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs::{self, FileType};
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 
-use crate::{ClientState, Error, ReadDirSpec, Result};
+use super::{ChangeKind, LinkAncestor};
+use crate::{
+    ClientState, CloneParentFunction, DirEntryExt, Error, FileId, Operation, ReadDirSpec, Result,
+    RootName,
+};
 
 /// Representation of a file or directory.
 ///
@@ -32,43 +39,163 @@ pub struct DirEntry<C: ClientState> {
     /// may set this field to `None` to skip reading the contents of a
     /// particular directory.
     pub read_children_path: Option<Arc<Path>>,
+    /// Priority of reading this entry's children relative to other pending
+    /// directories, defaults to `0`. The
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback may raise it to have parallel walks read the contents of
+    /// particular directories before others. Higher values are read first.
+    ///
+    /// This only changes when directories are read. Entries are still
+    /// yielded in depth first order unless
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed)
+    /// is used.
+    pub read_children_priority: i32,
+    /// State the contents of this directory are read with, passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// when they are. Defaults to `None`, which reads them with a clone of
+    /// the state of this entry's parent as left by `process_read_dir`. The
+    /// callback may set it to give subdirectories states of their own, like
+    /// the ignore rules found in each of them.
+    pub read_children_state: Option<C::ReadDirState>,
     /// If `read_children_path` is set and resulting `fs::read_dir` generates an error
     /// then that error is stored here.
     pub read_children_error: Option<Error>,
+    /// True if reading the contents of this directory stopped early at
+    /// [`max_entries_per_dir`](struct.WalkDirGeneric.html#method.max_entries_per_dir)
+    /// entries.
+    ///
+    /// Like `read_children_error`, this is only set by iterators that yield
+    /// directories after reading their contents, which
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed)
+    /// and [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter)
+    /// don't.
+    pub read_children_truncated: bool,
+    // Extended metadata if it was preloaded.
+    pub(crate) ext: Option<Result<DirEntryExt>>,
+    // Attributes of the entry itself if they came with its listing.
+    #[cfg(windows)]
+    pub(crate) file_attributes: Option<u32>,
+    // Metadata once read by `metadata_cached`, or when the entry was created
+    // from it.
+    metadata: OnceLock<Result<fs::Metadata>>,
+    // Start of the contents of regular files if they were sniffed.
+    pub(crate) content_prefix: Option<Vec<u8>>,
+    // True if a hard link to the same file was seen before in this walk.
+    pub(crate) is_hardlink_duplicate: bool,
+    // True if rejected by the file type filter but kept to read its contents.
+    pub(crate) is_filtered: bool,
+    // Copies this directory for the callback processing its contents, set
+    // by `process_read_dir_with_parent`.
+    pub(crate) clone_parent: Option<CloneParentFunction<C>>,
+    // How the entry changed since the snapshot of a `diff`, if it was read
+    // by a worker of one.
+    pub(crate) change: Option<ChangeKind>,
+    // Position among the entries of the parent and their number.
+    pub(crate) sibling_index: usize,
+    pub(crate) sibling_count: usize,
+    // Target of the symlink this entry was created from, if it was followed.
+    resolved_path: Option<PathBuf>,
+    // Full path, built on first use.
+    full_path: OnceLock<Arc<Path>>,
+    // Lossy conversion of a `file_name` that isn't valid Unicode, built on
+    // first use.
+    file_name_lossy: OnceLock<Box<str>>,
+    // Path of a root entry whose `file_name` was replaced by `RootName`.
+    root_path: Option<Arc<Path>>,
     // True if [`follow_links`] is `true` AND was created from a symlink path.
     follow_link: bool,
     // Origins of symlinks followed to get to this entry.
-    follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+    follow_link_ancestors: Arc<Vec<LinkAncestor>>,
+    // Paths of the symlinks followed to get to this entry, outermost first.
+    pub(crate) symlink_chain: Arc<Vec<Arc<Path>>>,
 }
 
 impl<C: ClientState> DirEntry<C> {
-    pub(crate) fn from_entry(
+    /// Entry named `file_name` in the directory at `parent_path`, for custom
+    /// directory readers passed to [`custom::walk`](custom/fn.walk.html).
+    /// Directories are read next unless `read_children_path` is cleared.
+    pub fn new(
         depth: usize,
+        file_name: OsString,
+        file_type: FileType,
         parent_path: Arc<Path>,
-        fs_dir_entry: &fs::DirEntry,
-        follow_link_ancestors: Arc<Vec<Arc<Path>>>,
-    ) -> Result<Self> {
-        let file_type = fs_dir_entry
-            .file_type()
-            .map_err(|err| Error::from_path(depth, fs_dir_entry.path(), err))?;
-        let file_name = fs_dir_entry.file_name();
+    ) -> Self {
+        DirEntry::from_file_type(depth, parent_path, file_name, file_type, Default::default())
+    }
+
+    pub(crate) fn from_file_type(
+        depth: usize,
+        parent_path: Arc<Path>,
+        file_name: OsString,
+        file_type: FileType,
+        follow_link_ancestors: Arc<Vec<LinkAncestor>>,
+    ) -> Self {
         let read_children_path: Option<Arc<Path>> = if file_type.is_dir() {
             Some(Arc::from(parent_path.join(&file_name)))
         } else {
             None
         };
 
-        Ok(DirEntry {
+        DirEntry {
             depth,
             file_name,
             file_type,
             parent_path,
             read_children_path,
+            read_children_priority: 0,
+            read_children_state: None,
             read_children_error: None,
+            read_children_truncated: false,
+            ext: None,
+            #[cfg(windows)]
+            file_attributes: None,
+            metadata: OnceLock::new(),
+            content_prefix: None,
+            is_hardlink_duplicate: false,
+            is_filtered: false,
+            change: None,
+            clone_parent: None,
+            sibling_index: 0,
+            sibling_count: 0,
+            resolved_path: None,
+            full_path: OnceLock::new(),
+            file_name_lossy: OnceLock::new(),
+            root_path: None,
             client_state: C::DirEntryState::default(),
             follow_link: false,
             follow_link_ancestors,
-        })
+            symlink_chain: Default::default(),
+        }
+    }
+
+    pub(crate) fn from_entry(
+        depth: usize,
+        parent_path: Arc<Path>,
+        fs_dir_entry: &fs::DirEntry,
+        follow_link_ancestors: Arc<Vec<LinkAncestor>>,
+    ) -> Result<Self> {
+        let file_type = fs_dir_entry.file_type().map_err(|err| {
+            Error::from_path(depth, fs_dir_entry.path(), Operation::Metadata, err)
+        })?;
+        #[cfg_attr(not(windows), allow(unused_mut))]
+        let mut dir_entry = DirEntry::from_file_type(
+            depth,
+            parent_path,
+            fs_dir_entry.file_name(),
+            file_type,
+            follow_link_ancestors,
+        );
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+
+            // Served from the directory listing on Windows, no extra system call.
+            dir_entry.file_attributes = fs_dir_entry
+                .metadata()
+                .ok()
+                .map(|metadata| metadata.file_attributes());
+        }
+        Ok(dir_entry)
     }
 
     // Only used for root and when following links.
@@ -76,33 +203,63 @@ impl<C: ClientState> DirEntry<C> {
         depth: usize,
         path: &Path,
         follow_link: bool,
-        follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+        follow_link_ancestors: Arc<Vec<LinkAncestor>>,
     ) -> Result<Self> {
         let metadata = if follow_link {
-            fs::metadata(path).map_err(|err| Error::from_path(depth, path.to_owned(), err))?
+            fs::metadata(path)
+                .map_err(|err| Error::from_path(depth, path.to_owned(), Operation::Metadata, err))?
         } else {
             fs::symlink_metadata(path)
-                .map_err(|err| Error::from_path(depth, path.to_owned(), err))?
+                .map_err(|err| Error::from_path(depth, path.to_owned(), Operation::Metadata, err))?
         };
 
-        let root_name = path.file_name().unwrap_or(path.as_os_str());
+        // Paths like `.` or `a/..` are their own name, so `path()` returns
+        // them unchanged.
+        let (root_name, parent_path) = match path.file_name() {
+            Some(file_name) => (file_name, path.parent().unwrap_or(Path::new(""))),
+            None => (path.as_os_str(), Path::new("")),
+        };
 
         let read_children_path: Option<Arc<Path>> = if metadata.file_type().is_dir() {
             Some(Arc::from(path))
         } else {
             None
         };
+        #[cfg(windows)]
+        let file_attributes = {
+            use std::os::windows::fs::MetadataExt;
+            (!follow_link).then(|| metadata.file_attributes())
+        };
 
         Ok(DirEntry {
             depth,
             file_name: root_name.to_owned(),
             file_type: metadata.file_type(),
-            parent_path: Arc::from(path.parent().map(Path::to_path_buf).unwrap_or_default()),
+            parent_path: Arc::from(parent_path),
             read_children_path,
+            read_children_priority: 0,
+            read_children_state: None,
             read_children_error: None,
+            read_children_truncated: false,
+            ext: None,
+            #[cfg(windows)]
+            file_attributes,
+            metadata: OnceLock::from(Ok(metadata)),
+            content_prefix: None,
+            is_hardlink_duplicate: false,
+            is_filtered: false,
+            change: None,
+            clone_parent: None,
+            sibling_index: 0,
+            sibling_count: 0,
+            resolved_path: None,
+            full_path: OnceLock::new(),
+            file_name_lossy: OnceLock::new(),
+            root_path: None,
             client_state: C::DirEntryState::default(),
             follow_link,
             follow_link_ancestors,
+            symlink_chain: Default::default(),
         })
     }
 
@@ -126,6 +283,62 @@ impl<C: ClientState> DirEntry<C> {
         &self.file_name
     }
 
+    /// Return the file name of this entry as bytes, for matching names
+    /// without converting them to `str`.
+    ///
+    /// The bytes are in the platform's encoding of `OsStr`, which is UTF-8
+    /// for valid Unicode names everywhere, and WTF-8 for the others on
+    /// Windows.
+    pub fn file_name_bytes(&self) -> &[u8] {
+        self.file_name.as_encoded_bytes()
+    }
+
+    /// Return the file name of this entry as `str`, with parts that aren't
+    /// valid Unicode replaced like [`OsStr::to_string_lossy`] does.
+    ///
+    /// Valid names are borrowed, others are converted on first use and
+    /// cached in the entry, so comparing or displaying names repeatedly, like
+    /// when sorting, converts each at most once. Changes to `file_name` made
+    /// after that aren't reflected.
+    pub fn file_name_lossy_cached(&self) -> &str {
+        match self.file_name.to_str() {
+            Some(file_name) => file_name,
+            None => self
+                .file_name_lossy
+                .get_or_init(|| self.file_name.to_string_lossy().into()),
+        }
+    }
+
+    /// Returns `true` if [`path`](struct.DirEntry.html#method.path) starts
+    /// with `base`, comparing whole components like
+    /// [`Path::starts_with`], but without building the path.
+    pub fn path_starts_with(&self, base: impl AsRef<Path>) -> bool {
+        let mut components = self.path_components();
+        base.as_ref()
+            .components()
+            .all(|component| components.next() == Some(component))
+    }
+
+    /// Returns `true` if [`path`](struct.DirEntry.html#method.path) ends
+    /// with `child`, comparing whole components like [`Path::ends_with`],
+    /// but without building the path.
+    pub fn path_ends_with(&self, child: impl AsRef<Path>) -> bool {
+        let mut components = self.path_components().rev();
+        child
+            .as_ref()
+            .components()
+            .rev()
+            .all(|component| components.next() == Some(component))
+    }
+
+    fn path_components(&self) -> impl DoubleEndedIterator<Item = Component<'_>> {
+        let (parent_path, file_name) = match &self.root_path {
+            Some(root_path) => (root_path.as_ref(), Path::new("")),
+            None => (self.parent_path.as_ref(), Path::new(&self.file_name)),
+        };
+        parent_path.components().chain(file_name.components())
+    }
+
     /// Returns the depth at which this entry was created relative to the root.
     ///
     /// The smallest depth is `0` and always corresponds to the path given
@@ -137,9 +350,82 @@ impl<C: ClientState> DirEntry<C> {
 
     /// Path to the file/directory represented by this entry.
     ///
-    /// The path is created by joining `parent_path` with `file_name`.
+    /// The path is created by joining `parent_path` with `file_name`, except
+    /// for the root entry, whose path is always the path the walk started
+    /// from regardless of
+    /// [`root_file_name`](struct.WalkDirGeneric.html#method.root_file_name).
+    /// The paths of all entries thus start with the root's path, so
+    /// `strip_prefix` with it works for relative roots like `.` too.
     pub fn path(&self) -> PathBuf {
-        self.parent_path.join(&self.file_name)
+        match &self.root_path {
+            Some(root_path) => root_path.to_path_buf(),
+            None => self.parent_path.join(&self.file_name),
+        }
+    }
+
+    /// Shared path to the file/directory represented by this entry.
+    ///
+    /// Unlike [`path`](struct.DirEntry.html#method.path) this allocates only
+    /// once, on first use or on the worker thread if
+    /// [`precompute_paths`](struct.WalkDirGeneric.html#method.precompute_paths)
+    /// is enabled, and later calls return the cached path. Changes to
+    /// `parent_path` or `file_name` made after that aren't reflected.
+    pub fn full_path(&self) -> &Arc<Path> {
+        self.full_path.get_or_init(|| match &self.root_path {
+            Some(root_path) => root_path.clone(),
+            None => Arc::from(self.parent_path.join(&self.file_name)),
+        })
+    }
+
+    pub(crate) fn set_root_file_name(&mut self, root_file_name: RootName) {
+        if root_file_name == RootName::AsGiven {
+            return;
+        }
+        let canonical = match fs::canonicalize(self.path()) {
+            Ok(canonical) => canonical,
+            Err(_) => return,
+        };
+        let file_name = match root_file_name {
+            RootName::FileName => match canonical.file_name() {
+                Some(file_name) => file_name.to_owned(),
+                None => return,
+            },
+            _ => canonical.into_os_string(),
+        };
+        self.root_path = Some(Arc::from(self.path()));
+        self.file_name = file_name;
+    }
+
+    /// Path of the root this entry was found under, which tells entries of
+    /// walks with several roots, like glob walks, apart.
+    ///
+    /// This is the ancestor of [`full_path`](struct.DirEntry.html#method.full_path)
+    /// at this entry's `depth`, so it is the root's
+    /// [`path`](struct.DirEntry.html#method.path) unless `parent_path` or
+    /// `file_name` were changed.
+    pub fn root(&self) -> &Path {
+        self.full_path()
+            .ancestors()
+            .nth(self.depth)
+            .unwrap_or(Path::new(""))
+    }
+
+    /// Path of this entry relative to its [`root`](struct.DirEntry.html#method.root),
+    /// which is empty for the root itself.
+    ///
+    /// This is [`path`](struct.DirEntry.html#method.path) with the root
+    /// stripped, but allocates only the relative path, for tools that copy
+    /// or compare trees. If `parent_path` was changed so that it doesn't
+    /// start with the root anymore, it is the whole path.
+    pub fn relative_path(&self) -> PathBuf {
+        if self.depth == 0 {
+            return PathBuf::new();
+        }
+        let root = self.parent_path.ancestors().nth(self.depth - 1);
+        match root.and_then(|root| self.parent_path.strip_prefix(root).ok()) {
+            Some(parent_path) => parent_path.join(&self.file_name),
+            None => self.path(),
+        }
     }
 
     /// Returns `true` if and only if this entry was created from a symbolic
@@ -156,6 +442,82 @@ impl<C: ClientState> DirEntry<C> {
         self.file_type.is_symlink() || self.follow_link
     }
 
+    /// Returns the Windows attributes of this entry, like
+    /// `FILE_ATTRIBUTE_HIDDEN`. The entry itself is checked even if it is a
+    /// link that was followed.
+    ///
+    /// Attributes come with the directory listing, so this makes no system
+    /// calls for listed entries. They are read for roots and entries whose
+    /// listing didn't have them, like those of a
+    /// [`listing_cache`](struct.WalkDirGeneric.html#method.listing_cache).
+    /// Returns `None` if they can't be read.
+    #[cfg(windows)]
+    pub fn file_attributes(&self) -> Option<u32> {
+        use std::os::windows::fs::MetadataExt;
+
+        match (self.file_attributes, self.ext.as_ref()) {
+            (Some(file_attributes), _) => Some(file_attributes),
+            (None, Some(Ok(ext))) if !self.follow_link => Some(ext.file_attributes),
+            _ => fs::symlink_metadata(self.path())
+                .ok()
+                .map(|metadata| metadata.file_attributes()),
+        }
+    }
+
+    /// Returns `true` if this entry is a reparse point, like a symbolic link,
+    /// a junction or a placeholder of a cloud storage provider. The entry
+    /// itself is checked even if it is a link that was followed.
+    ///
+    /// Uses the attributes of
+    /// [`file_attributes`](struct.DirEntry.html#method.file_attributes).
+    /// Returns `false` if they can't be read.
+    #[cfg(windows)]
+    pub fn is_reparse_point(&self) -> bool {
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+        self.file_attributes()
+            .is_some_and(|file_attributes| file_attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0)
+    }
+
+    /// Returns `true` if this entry is a junction, also called a mount
+    /// point, like the `Application Data` junctions in user profiles.
+    ///
+    /// Junctions have the file type of symbolic links to directories, so
+    /// they are only read if
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+    /// enabled, and loops they form are detected then.
+    #[cfg(windows)]
+    pub fn is_junction(&self) -> bool {
+        const IO_REPARSE_TAG_MOUNT_POINT: u32 = 0xA000_0003;
+
+        self.is_reparse_point() && reparse_tag(&self.path()) == Some(IO_REPARSE_TAG_MOUNT_POINT)
+    }
+
+    /// Returns the target of the symbolic link this entry was created from if
+    /// the link was followed because [`follow_links`] is `true`.
+    ///
+    /// The target is read once while following the link. Relative targets
+    /// are joined with [`parent_path`], but not canonicalized.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    /// [`parent_path`]: struct.DirEntry.html#method.parent_path
+    pub fn resolved_path(&self) -> Option<&Path> {
+        self.resolved_path.as_deref()
+    }
+
+    /// Returns the paths of the symbolic links that were followed to get to
+    /// this entry, outermost first, when [`follow_links`] is `true`.
+    ///
+    /// The last path is this entry's own if it was created from a followed
+    /// link. Paths are the ones the walk read the links at, so each is below
+    /// the target of the link before it. The chain is empty for entries that
+    /// were reached without following links.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn symlink_chain(&self) -> &[Arc<Path>] {
+        &self.symlink_chain
+    }
+
     /// Return the metadata for the file that this entry points to.
     ///
     /// This will follow symbolic links if and only if the [`WalkDir`] value
@@ -184,9 +546,203 @@ impl<C: ClientState> DirEntry<C> {
         } else {
             fs::symlink_metadata(self.path())
         }
-        .map_err(|err| Error::from_entry(self, err))
+        .map_err(|err| Error::from_entry(self, Operation::Metadata, err))
+    }
+
+    /// Like [`metadata`](struct.DirEntry.html#method.metadata), but reading
+    /// the metadata only on the first call and returning it, or the error
+    /// reading it, from the entry on later ones.
+    ///
+    /// Roots and followed links come with their metadata, which was read to
+    /// create them. Use
+    /// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata)
+    /// to read it for all entries in the worker that reads their directory.
+    pub fn metadata_cached(&self) -> Result<&fs::Metadata> {
+        self.metadata
+            .get_or_init(|| self.metadata())
+            .as_ref()
+            .map_err(Error::clone)
     }
 
+    /// Return the extended metadata for the file that this entry points to.
+    ///
+    /// This is `None` unless
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext)
+    /// is enabled, in which case it was loaded in the worker that read the
+    /// parent directory, following symbolic links in the same way as
+    /// [`metadata`](struct.DirEntry.html#method.metadata).
+    pub fn ext(&self) -> Option<&Result<DirEntryExt>> {
+        self.ext.as_ref()
+    }
+
+    /// Returns the device and inode number of the file that this entry points
+    /// to, taken from its preloaded [`ext`](struct.DirEntry.html#method.ext).
+    ///
+    /// This is `None` unless
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext)
+    /// is enabled and loading the metadata succeeded, and always on
+    /// platforms other than unix, whose file indices aren't available
+    /// through the standard library.
+    pub fn file_id(&self) -> Option<FileId> {
+        #[cfg(unix)]
+        if let Some(Ok(ext)) = self.ext.as_ref() {
+            return Some(FileId {
+                dev: ext.dev,
+                ino: ext.ino,
+            });
+        }
+        None
+    }
+
+    pub(crate) fn preload_ext(&mut self) {
+        if self.ext.is_some() {
+            return;
+        }
+        self.ext = Some(match self.metadata.get() {
+            Some(metadata) => metadata
+                .as_ref()
+                .map(DirEntryExt::from_metadata)
+                .map_err(Error::clone),
+            None => self
+                .metadata()
+                .map(|metadata| DirEntryExt::from_metadata(&metadata)),
+        });
+    }
+
+    /// Open the file this entry points to for reading.
+    ///
+    /// See [`open_options`](struct.DirEntry.html#method.open_options).
+    pub fn open(&self) -> Result<fs::File> {
+        self.open_options(fs::OpenOptions::new().read(true))
+    }
+
+    /// Open the file this entry points to with `options`.
+    ///
+    /// Symbolic links are opened in the same way they were walked: links
+    /// that were followed open their target, while opening a link that
+    /// wasn't followed fails instead of silently resolving it. Pipelines
+    /// processing file contents can so open entries without rebuilding
+    /// their paths or deciding about links on their own.
+    pub fn open_options(&self, options: &fs::OpenOptions) -> Result<fs::File> {
+        if self.file_type.is_symlink() {
+            let err = io::Error::new(io::ErrorKind::InvalidInput, "symbolic link wasn't followed");
+            return Err(Error::from_entry(self, Operation::Open, err));
+        }
+        options
+            .open(self.path())
+            .map_err(|err| Error::from_entry(self, Operation::Open, err))
+    }
+
+    /// Returns the first bytes of the contents of this file.
+    ///
+    /// This is `None` unless
+    /// [`sniff_content`](struct.WalkDirGeneric.html#method.sniff_content)
+    /// is enabled and this is a regular file that could be read, in which
+    /// case the bytes were read in the worker that read the parent
+    /// directory. Files shorter than the requested length are read whole.
+    pub fn content_prefix(&self) -> Option<&[u8]> {
+        self.content_prefix.as_deref()
+    }
+
+    pub(crate) fn sniff_content(&mut self, len: usize) {
+        if !self.file_type.is_file() {
+            return;
+        }
+        let mut content_prefix = Vec::with_capacity(len);
+        let read = self
+            .open()
+            .ok()
+            .and_then(|file| file.take(len as u64).read_to_end(&mut content_prefix).ok());
+        self.content_prefix = read.map(|_| content_prefix);
+    }
+
+    /// Returns the position of this entry among the entries of its parent
+    /// directory, starting at `0`.
+    ///
+    /// Positions are assigned after
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// ran and count errors reading entries, as these are yielded in their
+    /// place. Root entries are numbered among each other. Entries rejected by
+    /// [`file_type_filter`](struct.WalkDirGeneric.html#method.file_type_filter)
+    /// or [`filter_metadata`](struct.WalkDirGeneric.html#method.filter_metadata)
+    /// aren't counted. Entries skipped
+    /// later, like by [`min_depth`](struct.WalkDirGeneric.html#method.min_depth)
+    /// or [`filter_entry`](struct.DirEntryIter.html#method.filter_entry),
+    /// keep their positions.
+    pub fn sibling_index(&self) -> usize {
+        self.sibling_index
+    }
+
+    /// Returns the number of entries of the parent directory, including
+    /// this one, so the last one has a
+    /// [`sibling_index`](struct.DirEntry.html#method.sibling_index) of
+    /// `sibling_count() - 1`.
+    ///
+    /// This is `0` for entries created by custom directory readers passed to
+    /// [`custom::walk`](custom/fn.walk.html).
+    pub fn sibling_count(&self) -> usize {
+        self.sibling_count
+    }
+
+    /// Returns `true` if another hard link to the same file was already
+    /// encountered during this walk.
+    ///
+    /// This is only ever `true` if
+    /// [`track_hardlinks`](struct.WalkDirGeneric.html#method.track_hardlinks)
+    /// is enabled. Which of the links is considered the duplicate depends on
+    /// which directory was read first, which isn't deterministic for parallel
+    /// walks.
+    pub fn is_hardlink_duplicate(&self) -> bool {
+        self.is_hardlink_duplicate
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn track_hardlink(&mut self, seen: &Mutex<HashSet<(u64, u64)>>) {
+        use std::os::unix::fs::MetadataExt;
+
+        if self.file_type.is_dir() {
+            return;
+        }
+        let (dev, ino, nlink) = match self.ext.as_ref() {
+            Some(Ok(ext)) => (ext.dev, ext.ino, ext.nlink),
+            _ => match self.metadata() {
+                Ok(metadata) => (metadata.dev(), metadata.ino(), metadata.nlink()),
+                Err(_) => return,
+            },
+        };
+        if nlink > 1 {
+            self.is_hardlink_duplicate = !seen.lock().unwrap().insert((dev, ino));
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn track_hardlink(&mut self, _seen: &Mutex<HashSet<(u64, u64)>>) {}
+
+    #[cfg(unix)]
+    pub(crate) fn skip_if_visited(&mut self, seen: &Mutex<HashMap<(u64, u64), PathBuf>>) {
+        use std::os::unix::fs::MetadataExt;
+
+        let read_children_path = match self.read_children_path.as_ref() {
+            Some(read_children_path) => read_children_path,
+            None => return,
+        };
+        // Errors are left for the actual read of the directory to report.
+        // The path reading it first keeps doing so in later deepening rounds.
+        if let Ok(metadata) = fs::metadata(read_children_path) {
+            let path = self.path();
+            let mut seen = seen.lock().unwrap();
+            let first_path = seen
+                .entry((metadata.dev(), metadata.ino()))
+                .or_insert_with(|| path.clone());
+            if *first_path != path {
+                self.read_children_path = None;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn skip_if_visited(&mut self, _seen: &Mutex<HashMap<(u64, u64), PathBuf>>) {}
+
     /// Reference to the path of the directory containing this entry.
     pub fn parent_path(&self) -> &Path {
         &self.parent_path
@@ -194,37 +750,128 @@ impl<C: ClientState> DirEntry<C> {
 
     pub(crate) fn read_children_spec(
         &self,
-        client_read_state: C::ReadDirState,
+        parent_read_state: &C::ReadDirState,
     ) -> Option<ReadDirSpec<C>> {
         self.read_children_path
             .as_ref()
             .map(|read_children_path| ReadDirSpec {
                 depth: self.depth,
-                client_read_state,
+                client_read_state: self
+                    .read_children_state
+                    .clone()
+                    .unwrap_or_else(|| parent_read_state.clone()),
+                priority: self.read_children_priority,
                 path: read_children_path.clone(),
                 follow_link_ancestors: self.follow_link_ancestors.clone(),
+                symlink_chain: self.symlink_chain.clone(),
+                parent_dir: None,
+                parent: self
+                    .clone_parent
+                    .map(|clone_parent| Box::new(clone_parent(self))),
             })
     }
 
-    pub(crate) fn follow_symlink(&self) -> Result<Self> {
+    // Entry of the target of this link, `None` if `follows` rejects it.
+    pub(crate) fn follow_symlink(
+        &self,
+        follows: impl FnOnce(&Self) -> bool,
+    ) -> Result<Option<Self>> {
         let path = self.path();
         let origins = self.follow_link_ancestors.clone();
-        let dir_entry = DirEntry::from_path(self.depth, &path, true, origins)?;
+        let mut dir_entry = DirEntry::from_path(self.depth, &path, true, origins)?;
+        if !follows(&dir_entry) {
+            return Ok(None);
+        }
+
+        let target = fs::read_link(&path)
+            .map_err(|err| Error::from_io(self.depth, Operation::ReadLink, err))?;
+        dir_entry.resolved_path = Some(self.parent_path.join(&target));
+        #[cfg(windows)]
+        {
+            dir_entry.file_attributes = self.file_attributes;
+        }
+        let mut symlink_chain = Vec::with_capacity(self.symlink_chain.len() + 1);
+        symlink_chain.extend(self.symlink_chain.iter().cloned());
+        symlink_chain.push(Arc::from(path.as_path()));
+        dir_entry.symlink_chain = Arc::new(symlink_chain);
 
         if dir_entry.file_type.is_dir() {
-            let target = fs::read_link(&path).map_err(|err| Error::from_io(self.depth, err))?;
+            // The identity of the target is only needed once an ancestor has one.
+            let mut target_id = None;
             for ancestor in self.follow_link_ancestors.iter().rev() {
-                if target.as_path() == ancestor.as_ref() {
+                let ancestor_id = match ancestor.id.as_ref() {
+                    Some(ancestor_id) => ancestor_id,
+                    None => continue,
+                };
+                let target_id = target_id.get_or_insert_with(|| ancestor_id.of_same_kind(&path));
+                if target_id.as_ref() == Some(ancestor_id) {
                     return Err(Error::from_loop(
                         self.depth,
-                        ancestor.as_ref(),
+                        ancestor.path.as_ref(),
                         path.as_ref(),
                     ));
                 }
             }
         }
 
-        Ok(dir_entry)
+        Ok(Some(dir_entry))
+    }
+}
+
+impl<C: ClientState> Clone for DirEntry<C>
+where
+    C::DirEntryState: Clone,
+{
+    fn clone(&self) -> Self {
+        DirEntry {
+            depth: self.depth,
+            file_name: self.file_name.clone(),
+            file_type: self.file_type,
+            client_state: self.client_state.clone(),
+            parent_path: self.parent_path.clone(),
+            read_children_path: self.read_children_path.clone(),
+            read_children_priority: self.read_children_priority,
+            read_children_state: self.read_children_state.clone(),
+            read_children_error: self.read_children_error.clone(),
+            read_children_truncated: self.read_children_truncated,
+            ext: self.ext.clone(),
+            #[cfg(windows)]
+            file_attributes: self.file_attributes,
+            metadata: self.metadata.clone(),
+            content_prefix: self.content_prefix.clone(),
+            is_hardlink_duplicate: self.is_hardlink_duplicate,
+            is_filtered: self.is_filtered,
+            change: self.change,
+            clone_parent: self.clone_parent,
+            sibling_index: self.sibling_index,
+            sibling_count: self.sibling_count,
+            resolved_path: self.resolved_path.clone(),
+            full_path: self.full_path.clone(),
+            file_name_lossy: self.file_name_lossy.clone(),
+            root_path: self.root_path.clone(),
+            follow_link: self.follow_link,
+            follow_link_ancestors: self.follow_link_ancestors.clone(),
+            symlink_chain: self.symlink_chain.clone(),
+        }
+    }
+}
+
+/// Entries are equal if they have the same `parent_path` and `file_name`,
+/// their client state and metadata aren't compared. As the cached
+/// [`full_path`](struct.DirEntry.html#method.full_path) isn't either,
+/// entries can be used as keys despite clippy's `mutable_key_type` lint.
+impl<C: ClientState> PartialEq for DirEntry<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.parent_path == other.parent_path && self.file_name == other.file_name
+    }
+}
+
+impl<C: ClientState> Eq for DirEntry<C> {}
+
+impl<C: ClientState> Hash for DirEntry<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.parent_path.hash(state);
+        self.file_name.hash(state);
     }
 }
 
@@ -233,3 +880,146 @@ impl<C: ClientState> fmt::Debug for DirEntry<C> {
         write!(f, "DirEntry({:?})", self.path())
     }
 }
+
+// Entries are written as their path, depth, kind of file and client state.
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+#[cfg(feature = "serde")]
+impl FileKind {
+    fn of(file_type: FileType) -> FileKind {
+        if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct DirEntryRef<'a, S> {
+    depth: usize,
+    path: &'a Path,
+    file_type: FileKind,
+    client_state: &'a S,
+}
+
+#[cfg(feature = "serde")]
+impl<C: ClientState> serde::Serialize for DirEntry<C>
+where
+    C::DirEntryState: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        DirEntryRef {
+            depth: self.depth,
+            path: self.full_path(),
+            file_type: FileKind::of(self.file_type),
+            client_state: &self.client_state,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// A [`DirEntry`](struct.DirEntry.html) read back from its serialized form.
+///
+/// Entries are written as their path, depth, kind of file and client state.
+/// As a `FileType` can only be obtained from the file system, they are read
+/// back as this instead, without touching the file system.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(bound(
+    serialize = "C::DirEntryState: serde::Serialize",
+    deserialize = "C::DirEntryState: serde::Deserialize<'de>"
+))]
+pub struct SerializedDirEntry<C: ClientState> {
+    depth: usize,
+    path: PathBuf,
+    file_type: FileKind,
+    client_state: C::DirEntryState,
+}
+
+#[cfg(feature = "serde")]
+impl<C: ClientState> SerializedDirEntry<C> {
+    /// Depth of the entry relative to the root directory of its walk.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Path of the entry, as returned by
+    /// [`DirEntry::path`](struct.DirEntry.html#method.path).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// True if the entry was a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileKind::Dir
+    }
+
+    /// True if the entry was a regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileKind::File
+    }
+
+    /// True if the entry was a symlink that wasn't followed.
+    pub fn is_symlink(&self) -> bool {
+        self.file_type == FileKind::Symlink
+    }
+
+    /// Client state stored in the entry.
+    pub fn client_state(&self) -> &C::DirEntryState {
+        &self.client_state
+    }
+
+    /// Take the client state stored in the entry.
+    pub fn into_client_state(self) -> C::DirEntryState {
+        self.client_state
+    }
+}
+
+// Tag of the reparse point at `path`, as listed by `FindFirstFileExW`.
+#[cfg(windows)]
+fn reparse_tag(path: &Path) -> Option<u32> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW, WIN32_FIND_DATAW,
+    };
+
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // SAFETY: `WIN32_FIND_DATAW` is plain data and valid when zeroed.
+    let mut find_data: WIN32_FIND_DATAW = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is nul terminated and `find_data` matches the
+    // `FindExInfoBasic` info level.
+    let handle = unsafe {
+        FindFirstFileExW(
+            path.as_ptr(),
+            FindExInfoBasic,
+            &mut find_data as *mut WIN32_FIND_DATAW as *mut _,
+            FindExSearchNameMatch,
+            std::ptr::null(),
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    // SAFETY: the handle was just opened and isn't used after this.
+    unsafe { FindClose(handle) };
+    // `dwReserved0` holds the reparse tag of reparse points.
+    Some(find_data.dwReserved0)
+}
@@ -0,0 +1,33 @@
+use super::*;
+use crate::Result;
+
+/// Batched DirEntry iterator from `WalkDir.into_batched_iter()`.
+///
+/// Yields the entries of a [`DirEntryIter`] in the same order, grouped into
+/// `Vec`s of up to `batch_size` entries.
+pub struct DirEntryBatchIter<C: ClientState> {
+    iter: DirEntryIter<C>,
+    batch_size: usize,
+}
+
+impl<C: ClientState> DirEntryBatchIter<C> {
+    pub(crate) fn new(iter: DirEntryIter<C>, batch_size: usize) -> Self {
+        DirEntryBatchIter {
+            iter,
+            batch_size: batch_size.max(1),
+        }
+    }
+}
+
+impl<C: ClientState> Iterator for DirEntryBatchIter<C> {
+    type Item = Vec<Result<DirEntry<C>>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        batch.extend(self.iter.by_ref().take(self.batch_size));
+        if batch.is_empty() {
+            None
+        } else {
+            Some(batch)
+        }
+    }
+}
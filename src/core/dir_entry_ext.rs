@@ -0,0 +1,195 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+/// Extended metadata of a file, as preloaded by
+/// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext).
+///
+/// The available fields depend on the platform and mirror the `MetadataExt`
+/// traits of the standard library. All unix platforms, including the BSDs
+/// and illumos, share the same fields, and those with `chflags` add
+/// [`flags`](struct.DirEntryExt.html#structfield.flags).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirEntryExt {
+    /// The rights applied to this file.
+    #[cfg(unix)]
+    pub mode: u32,
+    /// The inode number.
+    #[cfg(unix)]
+    pub ino: u64,
+    /// The ID of the device containing the file.
+    #[cfg(unix)]
+    pub dev: u64,
+    /// The number of hard links pointing to this file.
+    #[cfg(unix)]
+    pub nlink: u64,
+    /// The user ID of the owner of this file.
+    #[cfg(unix)]
+    pub uid: u32,
+    /// The group ID of the owner of this file.
+    #[cfg(unix)]
+    pub gid: u32,
+    /// The size of the file, in bytes.
+    #[cfg(unix)]
+    pub size: u64,
+    /// The device ID of this file, if it is a special one.
+    #[cfg(unix)]
+    pub rdev: u64,
+    /// The block size for filesystem I/O.
+    #[cfg(unix)]
+    pub blksize: u64,
+    /// The number of 512-byte blocks allocated to this file.
+    #[cfg(unix)]
+    pub blocks: u64,
+    /// The last modification time of this file, in seconds since the epoch.
+    #[cfg(unix)]
+    pub mtime: i64,
+    /// The nanoseconds part of the last modification time of this file.
+    #[cfg(unix)]
+    pub mtime_nsec: i64,
+    /// The user defined flags of this file, as set by `chflags`, like
+    /// `UF_NODUMP` or `SF_IMMUTABLE`.
+    #[cfg(any(
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly",
+        target_os = "macos",
+        target_os = "ios"
+    ))]
+    pub flags: u32,
+    /// The `dwFileAttributes` field of this file.
+    #[cfg(windows)]
+    pub file_attributes: u32,
+    /// The creation time of this file, in 100ns intervals since 1601.
+    #[cfg(windows)]
+    pub creation_time: u64,
+    /// The last access time of this file, in 100ns intervals since 1601.
+    #[cfg(windows)]
+    pub last_access_time: u64,
+    /// The last write time of this file, in 100ns intervals since 1601.
+    #[cfg(windows)]
+    pub last_write_time: u64,
+    /// The size of the file, in bytes.
+    #[cfg(windows)]
+    pub file_size: u64,
+}
+
+impl DirEntryExt {
+    #[cfg(unix)]
+    pub(crate) fn from_metadata(metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        DirEntryExt {
+            mode: metadata.mode(),
+            ino: metadata.ino(),
+            dev: metadata.dev(),
+            nlink: metadata.nlink(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size(),
+            rdev: metadata.rdev(),
+            blksize: metadata.blksize(),
+            blocks: metadata.blocks(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            #[cfg(any(
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd",
+                target_os = "dragonfly",
+                target_os = "macos",
+                target_os = "ios"
+            ))]
+            flags: st_flags(metadata),
+        }
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn from_metadata(metadata: &fs::Metadata) -> Self {
+        use std::os::windows::fs::MetadataExt;
+        DirEntryExt {
+            file_attributes: metadata.file_attributes(),
+            creation_time: metadata.creation_time(),
+            last_access_time: metadata.last_access_time(),
+            last_write_time: metadata.last_write_time(),
+            file_size: metadata.file_size(),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    pub(crate) fn from_metadata(_metadata: &fs::Metadata) -> Self {
+        DirEntryExt {}
+    }
+
+    /// Returns the last modification time of this file.
+    ///
+    /// This is `None` on platforms other than unix and Windows, and if the
+    /// time can't be represented.
+    #[cfg(unix)]
+    pub fn modified(&self) -> Option<SystemTime> {
+        let nanos = Duration::from_nanos(self.mtime_nsec as u64);
+        if self.mtime >= 0 {
+            SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(self.mtime as u64) + nanos)
+        } else {
+            SystemTime::UNIX_EPOCH
+                .checked_sub(Duration::from_secs(self.mtime.unsigned_abs()))?
+                .checked_add(nanos)
+        }
+    }
+
+    /// Returns the last modification time of this file.
+    ///
+    /// This is `None` on platforms other than unix and Windows, and if the
+    /// time can't be represented.
+    #[cfg(windows)]
+    pub fn modified(&self) -> Option<SystemTime> {
+        // Seconds from 1601 to the unix epoch
+        const EPOCH_DIFFERENCE: u64 = 11_644_473_600;
+        let since_1601 = Duration::from_secs(self.last_write_time / 10_000_000)
+            + Duration::from_nanos(self.last_write_time % 10_000_000 * 100);
+        match since_1601.checked_sub(Duration::from_secs(EPOCH_DIFFERENCE)) {
+            Some(since_epoch) => SystemTime::UNIX_EPOCH.checked_add(since_epoch),
+            None => SystemTime::UNIX_EPOCH
+                .checked_sub(Duration::from_secs(EPOCH_DIFFERENCE) - since_1601),
+        }
+    }
+
+    /// Returns the last modification time of this file.
+    ///
+    /// This is `None` on platforms other than unix and Windows, and if the
+    /// time can't be represented.
+    #[cfg(not(any(unix, windows)))]
+    pub fn modified(&self) -> Option<SystemTime> {
+        None
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+fn st_flags(metadata: &fs::Metadata) -> u32 {
+    std::os::freebsd::fs::MetadataExt::st_flags(metadata)
+}
+
+#[cfg(target_os = "openbsd")]
+fn st_flags(metadata: &fs::Metadata) -> u32 {
+    std::os::openbsd::fs::MetadataExt::st_flags(metadata)
+}
+
+#[cfg(target_os = "netbsd")]
+fn st_flags(metadata: &fs::Metadata) -> u32 {
+    std::os::netbsd::fs::MetadataExt::st_flags(metadata)
+}
+
+#[cfg(target_os = "dragonfly")]
+fn st_flags(metadata: &fs::Metadata) -> u32 {
+    std::os::dragonfly::fs::MetadataExt::st_flags(metadata)
+}
+
+#[cfg(target_os = "macos")]
+fn st_flags(metadata: &fs::Metadata) -> u32 {
+    std::os::macos::fs::MetadataExt::st_flags(metadata)
+}
+
+#[cfg(target_os = "ios")]
+fn st_flags(metadata: &fs::Metadata) -> u32 {
+    std::os::ios::fs::MetadataExt::st_flags(metadata)
+}
@@ -1,17 +1,45 @@
-use std::iter::Peekable;
+use rayon::ThreadPool;
+use std::iter::FusedIterator;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{SendError, Sender};
+use std::time::Instant;
 
 use super::*;
-use crate::Result;
+use crate::{BusyFallback, PoolFallback, Result};
+
+// Results of a ReadDir left to yield, and its snapshot to check once they are
+// exhausted.
+type ReadDirResults<C> = (vec::IntoIter<Result<DirEntry<C>>>, Option<ReadDirSnapshot>);
 
 /// DirEntry iterator from `WalkDir.into_iter()`.
 ///
-/// Yields entries from recursive traversal of filesystem.
+/// Yields entries from recursive traversal of filesystem. Once it returned
+/// `None` it keeps doing so. The lower bound of its `size_hint` counts the
+/// entries that were read already and wait to be yielded.
 pub struct DirEntryIter<C: ClientState> {
     min_depth: usize,
+    parallelism: Parallelism,
+    // the rayon pool directories are read on, if not the default pool
+    thread_pool: Option<Arc<ThreadPool>>,
+    // yield each ReadDir as soon as it's available instead of depth first
+    relaxed_ordering: bool,
     // iterator yielding next ReadDir results when needed
-    pub(crate) read_dir_iter: Option<Peekable<ReadDirIter<C>>>,
+    pub(crate) read_dir_iter: Option<ReadDirIter<C>>,
     // stack of ReadDir results, track location in filesystem traversal
-    read_dir_results_stack: Vec<vec::IntoIter<Result<DirEntry<C>>>>,
+    read_dir_results_stack: Vec<ReadDirResults<C>>,
+    // the last yielded entry was a directory to be read
+    yielded_dir: bool,
+    // the contents of the last yielded entry are on top of the stack
+    yielded_dir_contents: bool,
+    // statistics of the yielded results
+    summary: WalkSummary,
+    // channel errors are sent to instead of being yielded
+    pub(crate) errors_to: Option<Sender<Error>>,
+    // number of entries to yield before ending the walk
+    pub(crate) max_entries: Option<usize>,
+    // the walk to continue one level deeper once this round is exhausted
+    pub(crate) deepening: Option<Deepening<C>>,
+    started: Instant,
 }
 
 impl<C: ClientState> DirEntryIter<C> {
@@ -19,88 +47,639 @@ impl<C: ClientState> DirEntryIter<C> {
         root_entry_results: Vec<Result<DirEntry<C>>>,
         parallelism: Parallelism,
         min_depth: usize,
+        relaxed_ordering: bool,
+        queue_limits: QueueLimits,
         root_read_dir_state: C::ReadDirState,
         core_read_dir_callback: Arc<ReadDirCallback<C>>,
     ) -> DirEntryIter<C> {
         // 1. Gather read_dir_specs from root level
-        let read_dir_specs: Vec<_> = root_entry_results
-            .iter()
-            .flat_map(|dir_entry_result| {
-                dir_entry_result
-                    .as_ref()
-                    .ok()?
-                    .read_children_spec(root_read_dir_state.clone())
-            })
-            .collect();
+        let read_dir_specs = root_read_dir_specs(&root_entry_results, &root_read_dir_state);
 
-        // 2. Init new read_dir_iter from those specs
-        let read_dir_iter =
-            ReadDirIter::try_new(read_dir_specs, parallelism, core_read_dir_callback)
-                .map(|iter| iter.peekable());
+        // 2. Init new read_dir_iter from those specs, on a pool built once
+        //    for the whole walk
+        let thread_pool = parallelism.thread_pool();
+        let read_dir_iter = thread_pool.as_ref().ok().and_then(|thread_pool| {
+            ReadDirIter::try_new(
+                read_dir_specs,
+                parallelism.clone(),
+                thread_pool.as_ref(),
+                relaxed_ordering,
+                queue_limits,
+                core_read_dir_callback,
+            )
+        });
 
         // 3. Return DirEntryIter that will return initial root entries and then
         //    fill and process read_dir_iter until complete
         DirEntryIter {
             min_depth,
+            parallelism,
+            thread_pool: thread_pool.ok().flatten(),
+            relaxed_ordering,
             read_dir_iter,
-            read_dir_results_stack: vec![root_entry_results.into_iter()],
+            read_dir_results_stack: vec![(root_entry_results.into_iter(), None)],
+            yielded_dir: false,
+            yielded_dir_contents: false,
+            summary: WalkSummary::default(),
+            errors_to: None,
+            max_entries: None,
+            deepening: None,
+            started: Instant::now(),
+        }
+    }
+
+    // Start the walk again as chosen by `busy_fallback` if its pool was too
+    // busy to start it, before anything was yielded.
+    pub(crate) fn fall_back_when_busy(
+        &mut self,
+        busy_fallback: BusyFallback,
+        root_read_dir_state: &C::ReadDirState,
+        queue_limits: QueueLimits,
+        core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    ) {
+        if self.read_dir_iter.is_some() {
+            return;
+        }
+        let parallelism = match busy_fallback {
+            BusyFallback::Error => return,
+            BusyFallback::Serial => Parallelism::Serial,
+            BusyFallback::NewPool(num_threads) => Parallelism::RayonNewPoolWith {
+                num_threads,
+                busy_timeout: None,
+                fallback: PoolFallback::Error,
+            },
+        };
+        let Some((root_entry_results, _)) = self.read_dir_results_stack.first() else {
+            return;
+        };
+        let read_dir_specs =
+            root_read_dir_specs(root_entry_results.as_slice(), root_read_dir_state);
+        let Ok(thread_pool) = parallelism.thread_pool() else {
+            return;
+        };
+        self.read_dir_iter = ReadDirIter::try_new(
+            read_dir_specs,
+            parallelism.clone(),
+            thread_pool.as_ref(),
+            self.relaxed_ordering,
+            queue_limits,
+            core_read_dir_callback,
+        );
+        self.parallelism = parallelism;
+        self.thread_pool = thread_pool;
+    }
+
+    /// Skip the contents of the directory that was yielded last, or if the
+    /// last yielded entry isn't a directory, the remaining entries of its
+    /// parent directory. Mirrors `skip_current_dir` of the `walkdir` crate.
+    ///
+    /// Reads of skipped directories that were already scheduled are dropped
+    /// as they arrive. Use
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// to avoid reading them in the first place.
+    ///
+    /// This has no effect with
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed),
+    /// as the contents of a directory may be yielded at any time.
+    pub fn skip_current_dir(&mut self) {
+        if self.relaxed_ordering || (self.yielded_dir && !self.yielded_dir_contents) {
+            return;
+        }
+        self.skip_top_read_dir_results();
+    }
+
+    /// Degree of parallelism the walk was configured with. A
+    /// [`max_depth`](struct.WalkDirGeneric.html#method.max_depth) below 2
+    /// turns it into
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial).
+    pub fn parallelism(&self) -> &Parallelism {
+        &self.parallelism
+    }
+
+    /// The rayon pool directories are read on, to run related work on the
+    /// same threads.
+    ///
+    /// This is the pool of
+    /// [`RayonExistingPool`](enum.Parallelism.html#variant.RayonExistingPool)
+    /// or the one built for the walk by the variants creating a new pool.
+    /// `None` if the walk runs in the default rayon pool, including when a
+    /// new pool couldn't be built and it fell back to the default one, or if
+    /// it doesn't use rayon.
+    pub fn thread_pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.thread_pool.as_ref()
+    }
+
+    /// Stop reading directories in the background, to temporarily yield the
+    /// disk to more important work. Directories being read are finished,
+    /// and results read so far stay queued until the walk is
+    /// [`resume`](struct.DirEntryIter.html#method.resume)d.
+    ///
+    /// Iterating while paused still yields queued results, and reads the
+    /// directories needed for the next entry if it wasn't read yet. Serial
+    /// walks only read directories while iterating anyway.
+    pub fn pause(&self) {
+        if let Some(read_dir_iter) = self.read_dir_iter.as_ref() {
+            read_dir_iter.set_paused(true);
+        }
+    }
+
+    /// Continue reading directories in the background after
+    /// [`pause`](struct.DirEntryIter.html#method.pause).
+    pub fn resume(&self) {
+        if let Some(read_dir_iter) = self.read_dir_iter.as_ref() {
+            read_dir_iter.set_paused(false);
+        }
+    }
+
+    /// Number of directories scheduled for reading that weren't read yet,
+    /// including those being read right now. Together with
+    /// [`completed_dirs`](struct.DirEntryIter.html#method.completed_dirs)
+    /// this shows the progress of a running walk, for example in a progress
+    /// bar.
+    ///
+    /// New directories are scheduled as their parents are read, so this
+    /// grows and shrinks during the walk and is `0` once it is done.
+    pub fn pending_dirs(&self) -> usize {
+        self.read_dir_iter
+            .as_ref()
+            .map_or(0, ReadDirIter::pending_dirs)
+    }
+
+    /// Number of directories read so far. In parallel walks this can be
+    /// ahead of the entries yielded, as directories are read in the
+    /// background.
+    pub fn completed_dirs(&self) -> usize {
+        self.read_dir_iter
+            .as_ref()
+            .map_or(0, ReadDirIter::completed_dirs)
+    }
+
+    /// Statistics of the results yielded so far.
+    pub fn summary(&self) -> WalkSummary {
+        WalkSummary {
+            elapsed: self.started.elapsed(),
+            ..self.summary
+        }
+    }
+
+    /// Walk the remaining entries and return the statistics of the whole
+    /// walk, for tools reporting the number of files scanned without a
+    /// second pass. Use `by_ref()` to iterate before calling this.
+    pub fn finish(mut self) -> WalkSummary {
+        for _ in self.by_ref() {}
+        self.summary()
+    }
+
+    /// Yield only entries for which `predicate` returns `true`. Mirrors
+    /// `filter_entry` of the `walkdir` crate: if `predicate` rejects a
+    /// directory, none of its contents are yielded either.
+    ///
+    /// Reads of rejected directories that were already scheduled are dropped
+    /// as they arrive. Use
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// to avoid reading them in the first place.
+    ///
+    /// With
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed)
+    /// only the rejected entries themselves are filtered.
+    pub fn filter_entry<P>(self, predicate: P) -> FilterEntry<C, P>
+    where
+        P: FnMut(&DirEntry<C>) -> bool,
+    {
+        FilterEntry::new(self, predicate)
+    }
+
+    /// Fast-forward to the entry at `path`, so that it is yielded next,
+    /// without yielding the entries before it. Returns `false` if there is
+    /// no such entry.
+    ///
+    /// Entries that can't lead to `path` are dropped unread along with the
+    /// reads of their contents, which makes this much cheaper than
+    /// iterating up to `path`, for example to resume a paginated listing
+    /// after the last path shown. Errors met on the way are dropped as well.
+    ///
+    /// If `path` is missing, iteration continues after the contents of its
+    /// deepest ancestor that was found, or ends if not even the root is an
+    /// ancestor. Entries of followed links are found by their path below
+    /// the link.
+    ///
+    /// This has no effect with
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed),
+    /// as the contents of a directory may be yielded at any time.
+    pub fn skip_until<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        if self.relaxed_ordering {
+            return false;
         }
+        let path = path.as_ref();
+        self.yielded_dir = false;
+        self.yielded_dir_contents = false;
+        // Number of results pushed for ancestors of `path` while searching
+        let mut descended = 0;
+        loop {
+            let (top_read_dir_results, _) = match self.read_dir_results_stack.last_mut() {
+                Some(top) => top,
+                None => return false,
+            };
+            let dir_entry_path = match top_read_dir_results.as_slice().first() {
+                Some(Ok(dir_entry)) => dir_entry.path(),
+                Some(Err(_)) => {
+                    top_read_dir_results.next();
+                    continue;
+                }
+                None => {
+                    self.read_dir_results_stack.pop();
+                    if descended > 0 {
+                        return false;
+                    }
+                    continue;
+                }
+            };
+            if dir_entry_path == path {
+                return true;
+            }
+            let dir_entry = match top_read_dir_results.next() {
+                Some(Ok(dir_entry)) => dir_entry,
+                _ => continue,
+            };
+            let read_dir_iter = match self.read_dir_iter.as_mut() {
+                Some(read_dir_iter) if dir_entry.read_children_path.is_some() => read_dir_iter,
+                _ => continue,
+            };
+            if !path.starts_with(&dir_entry_path) {
+                read_dir_iter.skip_next();
+                continue;
+            }
+            // Descend into the ancestor of `path`, keeping its read error
+            // to be yielded next as it can't be found then
+            match Self::push_next_read_dir_results(read_dir_iter, &mut self.read_dir_results_stack)
+            {
+                Ok(_) => descended += 1,
+                Err(err) => {
+                    self.read_dir_results_stack
+                        .push((vec![Err(err)].into_iter(), None));
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Yield entries up to, but not including, the one at `path`, and end
+    /// iteration there. Together with
+    /// [`skip_until`](struct.DirEntryIter.html#method.skip_until) this
+    /// yields a page of a listing, and dropping the returned iterator stops
+    /// reading the directories after it.
+    pub fn take_until<P: Into<PathBuf>>(self, path: P) -> TakeUntil<C> {
+        TakeUntil::new(self, path.into())
+    }
+
+    // Skip the contents of the last yielded entry, if it is a directory.
+    pub(crate) fn skip_yielded_dir_contents(&mut self) {
+        if !self.relaxed_ordering && self.yielded_dir_contents {
+            self.skip_top_read_dir_results();
+        }
+    }
+
+    fn skip_top_read_dir_results(&mut self) {
+        if let Some((results, _)) = self.read_dir_results_stack.pop() {
+            self.skip_read_dir_results(results);
+        }
+        self.yielded_dir = false;
+        self.yielded_dir_contents = false;
+    }
+
+    // Drop the reads of all directories among `results`.
+    fn skip_read_dir_results(&mut self, results: vec::IntoIter<Result<DirEntry<C>>>) {
+        let read_dir_iter = match self.read_dir_iter.as_mut() {
+            Some(read_dir_iter) => read_dir_iter,
+            None => return,
+        };
+        for dir_entry in results.flatten() {
+            if dir_entry.read_children_path.is_some() {
+                read_dir_iter.skip_next();
+            }
+        }
+    }
+
+    // Remove and return the error of the root entry, if it couldn't be read.
+    // Walks of several roots, like those of a glob or a checkpoint, yield it
+    // in its place, so the other roots are still walked.
+    pub(crate) fn take_root_error(&mut self) -> Option<Error> {
+        let (root_results, _) = self.read_dir_results_stack.first_mut()?;
+        match root_results.as_slice() {
+            [Err(err)] if err.depth() == 0 => root_results.next()?.err(),
+            _ => None,
+        }
+    }
+
+    /// Take a [`Checkpoint`] of the walk to resume it later with
+    /// [`resume_from`](struct.WalkDirGeneric.html#method.resume_from).
+    ///
+    /// The checkpoint lists the entries that were not yielded yet. Reads of
+    /// directories that are in flight are discarded and repeated on resume.
+    /// Returns `None` with
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed),
+    /// as the contents of yielded directories may still be pending
+    /// then.
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        if self.relaxed_ordering {
+            return None;
+        }
+        let pending = self
+            .read_dir_results_stack
+            .iter()
+            .rev()
+            .flat_map(|(results, _)| results.as_slice().iter().flatten())
+            .map(|dir_entry| PendingEntry {
+                path: dir_entry.path(),
+                depth: dir_entry.depth,
+                read_children: dir_entry.read_children_path.is_some(),
+            })
+            .collect();
+        Some(Checkpoint { pending })
+    }
+
+    /// Yield entries contents first and fold each entry into its parent
+    /// directory before the directory itself is yielded.
+    ///
+    /// `fold` is called with the parent directory and each of its children
+    /// once the child and all of its own descendants were seen, which makes
+    /// it possible to compute aggregates such as the total size of a
+    /// directory bottom-up in the parent's
+    /// [`client_state`](struct.DirEntry.html#field.client_state).
+    ///
+    /// Note that directories are held back until their contents were
+    /// yielded, so the amount of buffered entries grows with the size of the
+    /// subtree.
+    ///
+    /// This has no effect with
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed)
+    /// or
+    /// [`iterative_deepening`](struct.WalkDirGeneric.html#method.iterative_deepening)
+    /// enabled, as a directory's descendants don't follow it then. Entries
+    /// are yielded as they arrive, and `fold` is never called.
+    pub fn aggregate<F>(self, fold: F) -> Aggregate<C, F>
+    where
+        F: FnMut(&mut DirEntry<C>, &DirEntry<C>),
+    {
+        let depth_first = !self.relaxed_ordering && self.deepening.is_none();
+        Aggregate::new(self, fold, depth_first)
     }
 
     fn push_next_read_dir_results(
-        iter: &mut Peekable<ReadDirIter<C>>,
-        results: &mut Vec<vec::IntoIter<Result<DirEntry<C>>>>,
-    ) -> Result<()> {
-        // Push next read dir results or return error if read failed
-        let read_dir_result = iter.next().unwrap();
-        let read_dir = match read_dir_result {
-            Ok(read_dir) => read_dir,
-            Err(err) => return Err(err),
+        iter: &mut ReadDirIter<C>,
+        results: &mut Vec<ReadDirResults<C>>,
+    ) -> Result<bool> {
+        // Push next read dir results or return error if read failed, and
+        // whether they were truncated
+        let read_dir = iter.next().unwrap()?;
+
+        let ReadDir {
+            results_list,
+            snapshot,
+            truncated,
+            ..
+        } = read_dir;
+        results.push((results_list.into_iter(), snapshot));
+
+        Ok(truncated)
+    }
+
+    // End the walk, dropping the ReadDirIter stops its workers. It was
+    // truncated if any result was left.
+    fn truncate(&mut self) {
+        if self.read_dir_results_stack.is_empty() && self.read_dir_iter.is_none() {
+            return;
+        }
+        let next = if self.relaxed_ordering {
+            self.next_relaxed()
+        } else {
+            self.next_strict()
         };
+        self.summary.truncated = next.is_some()
+            || self
+                .deepening
+                .take()
+                .is_some_and(|deepening| deepening.can_deepen());
+        self.read_dir_results_stack.clear();
+        self.read_dir_iter = None;
+    }
 
-        let ReadDir { results_list, .. } = read_dir;
-        results.push(results_list.into_iter());
+    // Pop exhausted read dir results, reporting if their directory changed
+    // while they were yielded.
+    fn pop_read_dir_results(&mut self) -> Result<()> {
+        match self.read_dir_results_stack.pop() {
+            Some((_, Some(snapshot))) => snapshot.check(),
+            _ => Ok(()),
+        }
+    }
+}
 
-        Ok(())
+impl<C: ClientState> DirEntryIter<C> {
+    fn next_relaxed(&mut self) -> Option<Result<DirEntry<C>>> {
+        loop {
+            // 1. If current read dir results are exhausted pull whichever
+            //    ReadDir completes next, as we don't care where it belongs.
+            let top_read_dir_results = match self.read_dir_results_stack.last_mut() {
+                Some((top_read_dir_results, _)) => top_read_dir_results,
+                None => {
+                    match self.read_dir_iter.as_mut()?.next()? {
+                        Ok(ReadDir {
+                            results_list,
+                            snapshot,
+                            ..
+                        }) => self
+                            .read_dir_results_stack
+                            .push((results_list.into_iter(), snapshot)),
+                        Err(err) => return Some(Err(err)),
+                    }
+                    continue;
+                }
+            };
+
+            // 2. Yield results of the current read dir, its children are
+            //    pulled later once they arrive.
+            if let Some(dir_entry_result) = top_read_dir_results.next() {
+                let dir_entry = match dir_entry_result {
+                    Ok(dir_entry) => dir_entry,
+                    Err(err) => return Some(Err(err)),
+                };
+                if dir_entry.read_children_path.is_some() && self.read_dir_iter.is_none() {
+                    return Some(Err(Error::busy()));
+                }
+                Deepening::note(self.deepening.as_mut(), &dir_entry);
+                if dir_entry.depth >= self.min_depth && !dir_entry.is_filtered {
+                    return Some(Ok(dir_entry));
+                }
+            } else if let Err(err) = self.pop_read_dir_results() {
+                return Some(Err(err));
+            }
+        }
     }
 }
 
 impl<C: ClientState> Iterator for DirEntryIter<C> {
     type Item = Result<DirEntry<C>>;
     fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(dir_entry_result) = self.next_in_round() {
+                return Some(dir_entry_result);
+            }
+            let round = self.deepening.take()?.next_round()?;
+            self.start_round(round);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self
+            .max_entries
+            .map(|max_entries| max_entries.saturating_sub(self.summary.entries));
+        // Walking depth first, all directories were read once the stack is
+        // empty, while relaxed walks pull them as they arrive.
+        if self.read_dir_results_stack.is_empty()
+            && (!self.relaxed_ordering || self.read_dir_iter.is_none())
+            && self.deepening.is_none()
+        {
+            return (0, Some(0));
+        }
+        let queued = self
+            .read_dir_results_stack
+            .iter()
+            .flat_map(|(read_dir_results, _)| read_dir_results.as_slice())
+            .filter(|dir_entry_result| self.yields(dir_entry_result))
+            .count();
+        // Errors don't count towards `max_entries`, so there is no upper
+        // bound while they are yielded.
+        let upper = remaining.filter(|&remaining| remaining == 0 || self.errors_to.is_some());
+        (
+            remaining.map_or(queued, |remaining| queued.min(remaining)),
+            upper,
+        )
+    }
+}
+
+impl<C: ClientState> FusedIterator for DirEntryIter<C> {}
+
+impl<C: ClientState> DirEntryIter<C> {
+    // Whether a result read already is yielded once it is reached. Entries
+    // whose directory can't be read for lack of a pool turn into errors.
+    // Errors may have been yielded in an earlier round of iterative
+    // deepening, and are left out then.
+    fn yields(&self, dir_entry_result: &Result<DirEntry<C>>) -> bool {
+        match dir_entry_result {
+            Ok(dir_entry)
+                if dir_entry.read_children_path.is_none() || self.read_dir_iter.is_some() =>
+            {
+                dir_entry.depth >= self.min_depth && !dir_entry.is_filtered
+            }
+            Ok(_) => self.errors_to.is_none(),
+            Err(_) => self.errors_to.is_none() && self.deepening.is_none(),
+        }
+    }
+
+    fn next_in_round(&mut self) -> Option<Result<DirEntry<C>>> {
+        if self
+            .max_entries
+            .is_some_and(|max_entries| self.summary.entries >= max_entries)
+        {
+            self.truncate();
+            return None;
+        }
+        loop {
+            let dir_entry_result = if self.relaxed_ordering {
+                self.next_relaxed()
+            } else {
+                self.next_strict()
+            }?;
+            if let Err(err) = &dir_entry_result {
+                if Deepening::repeats(self.deepening.as_ref(), err) {
+                    continue;
+                }
+            }
+            self.summary.count(&dir_entry_result);
+            match (dir_entry_result, self.errors_to.as_ref()) {
+                (Err(err), Some(errors_to)) => {
+                    if let Err(SendError(err)) = errors_to.send(err) {
+                        return Some(Err(err));
+                    }
+                }
+                (dir_entry_result, _) => return Some(dir_entry_result),
+            }
+        }
+    }
+
+    // Continue with the next round of an iterative deepening walk, keeping
+    // the statistics.
+    fn start_round(&mut self, round: DirEntryIter<C>) {
+        let summary = self.summary;
+        let started = self.started;
+        *self = round;
+        self.summary = summary;
+        self.started = started;
+    }
+}
+
+impl<C: ClientState> DirEntryIter<C> {
+    fn next_strict(&mut self) -> Option<Result<DirEntry<C>>> {
         loop {
             // 1. Get current read dir results iter from top of stack
-            let top_read_dir_results = self.read_dir_results_stack.last_mut()?;
+            let (top_read_dir_results, _) = self.read_dir_results_stack.last_mut()?;
 
             // 2. If more results in current read dir then process
             if let Some(dir_entry_result) = top_read_dir_results.next() {
                 // 2.1 Handle error case
                 let mut dir_entry = match dir_entry_result {
                     Ok(dir_entry) => dir_entry,
-                    Err(err) => return Some(Err(err)),
+                    Err(err) => {
+                        self.yielded_dir = false;
+                        return Some(Err(err));
+                    }
                 };
                 // 2.2 If dir_entry has a read_children_path means we need to read a new
                 // directory and push those results onto read_dir_results_stack
-                if dir_entry.read_children_path.is_some() {
+                let is_dir = dir_entry.read_children_path.is_some();
+                let mut pushed_contents = false;
+                if is_dir {
                     let iter = match self.read_dir_iter.as_mut().ok_or_else(Error::busy) {
                         Ok(iter) => iter,
                         Err(err) => return Some(Err(err)),
                     };
-                    if let Err(err) =
-                        Self::push_next_read_dir_results(iter, &mut self.read_dir_results_stack)
-                    {
-                        dir_entry.read_children_error = Some(err);
+                    match Self::push_next_read_dir_results(iter, &mut self.read_dir_results_stack) {
+                        Ok(truncated) => {
+                            dir_entry.read_children_truncated = truncated;
+                            pushed_contents = true;
+                        }
+                        Err(err) => dir_entry.read_children_error = Some(err),
                     }
                 }
 
-                if dir_entry.depth >= self.min_depth {
+                Deepening::note(self.deepening.as_mut(), &dir_entry);
+                if dir_entry.depth >= self.min_depth && !dir_entry.is_filtered {
                     // 2.3 Finished, return dir_entry
+                    self.yielded_dir = is_dir;
+                    self.yielded_dir_contents = pushed_contents;
                     return Some(Ok(dir_entry));
                 }
-            } else {
-                // If no more results in current then pop stack
-                self.read_dir_results_stack.pop();
+            } else if let Err(err) = self.pop_read_dir_results() {
+                // If no more results in current then pop stack, reporting
+                // changes made to the directory in the meantime
+                return Some(Err(err));
             }
         }
     }
 }
+
+// The directories to read first, those of the roots.
+fn root_read_dir_specs<C: ClientState>(
+    root_entry_results: &[Result<DirEntry<C>>],
+    root_read_dir_state: &C::ReadDirState,
+) -> Vec<ReadDirSpec<C>> {
+    root_entry_results
+        .iter()
+        .flat_map(|dir_entry_result| {
+            dir_entry_result
+                .as_ref()
+                .ok()?
+                .read_children_spec(root_read_dir_state)
+        })
+        .collect()
+}
@@ -0,0 +1,103 @@
+//! Directories opened relative to the file descriptor of their parent, for
+//! [`Backend::DirFd`](../enum.Backend.html#variant.DirFd).
+
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::fast_read_dir::{read_dir_file, FastReadDir};
+use super::{ClientState, DirEntry, Error, Operation};
+
+/// An open directory, kept open while its subdirectories wait to be read.
+pub(crate) struct DirFd {
+    fd: OwnedFd,
+    path: Arc<Path>,
+}
+
+impl DirFd {
+    /// An already open directory, standing in for `path`.
+    pub(crate) fn from_handle(dir: File, path: Arc<Path>) -> DirFd {
+        DirFd {
+            fd: dir.into(),
+            path,
+        }
+    }
+
+    /// The root entry of a walk starting at this directory.
+    pub(crate) fn root_entry<C: ClientState>(&self) -> crate::Result<DirEntry<C>> {
+        let metadata = self
+            .fd
+            .try_clone()
+            .and_then(|fd| File::from(fd).metadata())
+            .map_err(|err| {
+                Error::from_path(0, self.path.to_path_buf(), Operation::Metadata, err)
+            })?;
+        Ok(DirEntry::from_file_type(
+            0,
+            Arc::from(Path::new("")),
+            self.path.as_os_str().to_owned(),
+            metadata.file_type(),
+            Arc::new(vec![]),
+        ))
+    }
+
+    /// Open the directory at `path` relative to `parent` if it is an entry
+    /// of it or `parent` itself, without following symbolic links, and by
+    /// path otherwise.
+    pub(crate) fn open(parent: Option<&DirFd>, path: &Arc<Path>) -> io::Result<DirFd> {
+        let file_name = match (parent, path.parent(), path.file_name()) {
+            // Opened anew to list it with a position of its own
+            (Some(parent), _, _) if path == &parent.path => Some((parent, CString::new(".")?)),
+            (Some(parent), Some(parent_path), Some(file_name))
+                if parent_path == parent.path.as_ref() =>
+            {
+                Some((parent, CString::new(file_name.as_bytes())?))
+            }
+            _ => None,
+        };
+        let fd = match file_name {
+            Some((parent, file_name)) => {
+                let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+                // SAFETY: `file_name` is a valid C string and the parent's
+                // descriptor stays open while `parent` is borrowed.
+                let fd = unsafe { libc::openat(parent.fd.as_raw_fd(), file_name.as_ptr(), flags) };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // SAFETY: `fd` was just opened and isn't owned elsewhere.
+                unsafe { OwnedFd::from_raw_fd(fd) }
+            }
+            None => fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_DIRECTORY)
+                .open(path)?
+                .into(),
+        };
+        Ok(DirFd {
+            fd,
+            path: path.clone(),
+        })
+    }
+
+    /// List the entries of the directory, through a duplicate of its
+    /// descriptor so it stays open for opening subdirectories.
+    pub(crate) fn read_dir(&self) -> io::Result<FastReadDir> {
+        let dir = File::from(self.fd.try_clone()?);
+        Ok(read_dir_file(dir, &self.path))
+    }
+}
+
+impl fmt::Debug for DirFd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirFd")
+            .field("fd", &self.fd.as_raw_fd())
+            .field("path", &self.path)
+            .finish()
+    }
+}
@@ -0,0 +1,85 @@
+use rayon::ThreadPool;
+use std::sync::{Arc, Mutex};
+
+use super::*;
+
+/// Hands pending directory reads to the threads of a parallel walk, one task
+/// per read.
+///
+/// At most one read per thread runs at once, and none are started while
+/// the [`Backpressure`] of the walk has no capacity. So no task waits for
+/// capacity on a thread shared with other work, the consumer dispatches
+/// again once capacity frees up instead. Each finished read dispatches the
+/// reads it scheduled.
+pub(crate) struct Dispatcher<C: ClientState> {
+    run_context: RunContext<C>,
+    parallelism: Parallelism,
+    thread_pool: Option<Arc<ThreadPool>>,
+    max_in_flight: usize,
+    pending: Mutex<Pending<C>>,
+}
+
+struct Pending<C: ClientState> {
+    read_dir_spec_iter: OrderedQueueIter<ReadDirSpec<C>>,
+    in_flight: usize,
+}
+
+impl<C: ClientState> Dispatcher<C> {
+    pub(crate) fn new(
+        run_context: RunContext<C>,
+        parallelism: Parallelism,
+        thread_pool: Option<Arc<ThreadPool>>,
+        read_dir_spec_iter: OrderedQueueIter<ReadDirSpec<C>>,
+    ) -> Dispatcher<C> {
+        // An application's executor has threads of its own choosing.
+        let max_in_flight = match (&parallelism, thread_pool.as_ref()) {
+            (Parallelism::Custom(_), _) => usize::MAX,
+            (_, Some(thread_pool)) => thread_pool.current_num_threads(),
+            (_, None) => rayon::current_num_threads(),
+        };
+        Dispatcher {
+            run_context,
+            parallelism,
+            thread_pool,
+            max_in_flight,
+            pending: Mutex::new(Pending {
+                read_dir_spec_iter,
+                in_flight: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn backpressure(&self) -> &Backpressure {
+        &self.run_context.backpressure
+    }
+
+    // Start pending reads while there is capacity and threads to run them.
+    // Capacity is checked under the lock, so a change made before calling
+    // this is always seen.
+    pub(crate) fn dispatch(self: &Arc<Self>) {
+        loop {
+            let ordered_read_dir_spec = {
+                let mut pending = self.pending.lock().unwrap();
+                if pending.in_flight == self.max_in_flight
+                    || !self.backpressure().has_capacity()
+                    || self.run_context.is_stopped()
+                {
+                    return;
+                }
+                let Some(ordered_read_dir_spec) = pending.read_dir_spec_iter.try_next() else {
+                    return;
+                };
+                pending.in_flight += 1;
+                ordered_read_dir_spec
+            };
+            // Spawned without holding the lock, executors may run it right away.
+            let dispatcher = self.clone();
+            self.parallelism
+                .spawn(self.thread_pool.as_deref(), move || {
+                    multi_threaded_walk_dir(ordered_read_dir_spec, &dispatcher.run_context);
+                    dispatcher.pending.lock().unwrap().in_flight -= 1;
+                    dispatcher.dispatch();
+                });
+        }
+    }
+}
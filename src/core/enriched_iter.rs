@@ -0,0 +1,24 @@
+use crossbeam::channel::Receiver;
+
+/// Iterator over the results of the second phase of a walk, see
+/// [`into_enriched`](../struct.WalkDirGeneric.html#method.into_enriched).
+///
+/// Yields results in the order they were computed. Ends once the walk
+/// iterator was dropped and all selected entries were enriched.
+pub struct EnrichedIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> EnrichedIter<T> {
+    pub(crate) fn new(receiver: Receiver<T>) -> Self {
+        EnrichedIter { receiver }
+    }
+}
+
+impl<T> Iterator for EnrichedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
@@ -11,10 +11,13 @@ use crate::{ClientState, DirEntry};
 /// particular, it adds the following information:
 ///
 /// * The depth at which the error occurred in the file tree, relative to the
-/// root.
+///   root.
 /// * The path, if any, associated with the IO error.
 /// * An indication that a loop occurred when following symbolic links. In this
-/// case, there is no underlying IO error.
+///   case, there is no underlying IO error.
+/// * An indication that a directory changed while its contents were walked,
+///   if [`detect_changes`](struct.WalkDirGeneric.html#method.detect_changes)
+///   is enabled. There is no underlying IO error either.
 ///
 /// To maintain good ergonomics, this type has a
 /// [`impl From<Error> for std::io::Error`][impl] defined which preserves the original context.
@@ -30,17 +33,64 @@ pub struct Error {
     inner: ErrorInner,
 }
 
+/// The operation that failed, as returned by
+/// [`Error::operation`](struct.Error.html#method.operation).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operation {
+    /// Opening or reading a directory with `fs::read_dir`.
+    ReadDir,
+    /// Querying the metadata or file type of an entry.
+    Metadata,
+    /// Reading the target of a symbolic link.
+    ReadLink,
+    /// Canonicalizing the root path.
+    Canonicalize,
+    /// A symbolic link pointed to one of its ancestors.
+    Loop,
+    /// The thread-pool was too busy to run the walk.
+    Busy,
+    /// A directory changed while its contents were walked.
+    Changed,
+    /// Opening a file with
+    /// [`DirEntry::open`](struct.DirEntry.html#method.open).
+    Open,
+    /// Reading the contents of a file.
+    Read,
+}
+
+/// The kind of an error, as returned by
+/// [`Error::kind`](struct.Error.html#method.kind).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorKind {
+    /// An IO operation failed, see
+    /// [`Error::io_error`](struct.Error.html#method.io_error).
+    Io,
+    /// A symbolic link pointed to one of its ancestors, see
+    /// [`Error::loop_ancestor`](struct.Error.html#method.loop_ancestor).
+    Loop,
+    /// The thread-pool was too busy to run the walk.
+    Busy,
+    /// A directory changed while its contents were walked.
+    Changed,
+}
+
 #[derive(Debug)]
 enum ErrorInner {
     Io {
         path: Option<PathBuf>,
         err: io::Error,
+        operation: Operation,
     },
     Loop {
         ancestor: PathBuf,
         child: PathBuf,
     },
     ThreadpoolBusy,
+    Changed {
+        path: PathBuf,
+    },
 }
 
 impl Error {
@@ -59,6 +109,7 @@ impl Error {
                 ..
             } => Some(path),
             ErrorInner::Loop { ref child, .. } => Some(child),
+            ErrorInner::Changed { ref path } => Some(path),
         }
     }
 
@@ -81,6 +132,30 @@ impl Error {
         }
     }
 
+    /// Returns the operation that failed, to tell apart errors reading a
+    /// directory from those querying the metadata of an entry, for example.
+    pub fn operation(&self) -> Operation {
+        match self.inner {
+            ErrorInner::Io { operation, .. } => operation,
+            ErrorInner::Loop { .. } => Operation::Loop,
+            ErrorInner::ThreadpoolBusy => Operation::Busy,
+            ErrorInner::Changed { .. } => Operation::Changed,
+        }
+    }
+
+    /// Returns the kind of this error, to match on all of them at once
+    /// instead of probing with
+    /// [`io_error`](struct.Error.html#method.io_error),
+    /// [`loop_ancestor`](struct.Error.html#method.loop_ancestor) and friends.
+    pub fn kind(&self) -> ErrorKind {
+        match self.inner {
+            ErrorInner::Io { .. } => ErrorKind::Io,
+            ErrorInner::Loop { .. } => ErrorKind::Loop,
+            ErrorInner::ThreadpoolBusy => ErrorKind::Busy,
+            ErrorInner::Changed { .. } => ErrorKind::Changed,
+        }
+    }
+
     /// Returns the depth at which this error occurred relative to the root.
     ///
     /// The smallest depth is `0` and always corresponds to the path given to
@@ -105,7 +180,7 @@ impl Error {
     ///
     /// > This is the original [`io::Error`] and is _not_ the same as
     /// > [`impl From<Error> for std::io::Error`][impl] which contains additional context about the
-    /// error.
+    /// > error.
     ///
     /// # Example
     ///
@@ -165,6 +240,14 @@ impl Error {
         matches!(self.inner, ErrorInner::ThreadpoolBusy)
     }
 
+    /// Returns true if this error reports a directory that was modified
+    /// while its contents were yielded, see
+    /// [`detect_changes`](struct.WalkDirGeneric.html#method.detect_changes).
+    /// The entries yielded for it may not match its current contents.
+    pub fn is_changed(&self) -> bool {
+        matches!(self.inner, ErrorInner::Changed { .. })
+    }
+
     /// Similar to [`io_error`] except consumes self to convert to the original
     /// [`io::Error`] if one exists.
     ///
@@ -183,30 +266,51 @@ impl Error {
             inner: ErrorInner::ThreadpoolBusy,
         }
     }
-    pub(crate) fn from_path(depth: usize, pb: PathBuf, err: io::Error) -> Self {
+    /// Error of `operation` on the file at `path`, for custom directory
+    /// readers passed to [`custom::walk`](custom/fn.walk.html).
+    pub fn from_path(depth: usize, pb: PathBuf, operation: Operation, err: io::Error) -> Self {
         Error {
             depth,
             inner: ErrorInner::Io {
                 path: Some(pb),
                 err,
+                operation,
             },
         }
     }
 
-    pub(crate) fn from_entry<C: ClientState>(dent: &DirEntry<C>, err: io::Error) -> Self {
+    pub(crate) fn from_entry<C: ClientState>(
+        dent: &DirEntry<C>,
+        operation: Operation,
+        err: io::Error,
+    ) -> Self {
         Error {
             depth: dent.depth(),
             inner: ErrorInner::Io {
                 path: Some(dent.path()),
                 err,
+                operation,
+            },
+        }
+    }
+
+    pub(crate) fn from_io(depth: usize, operation: Operation, err: io::Error) -> Self {
+        Error {
+            depth,
+            inner: ErrorInner::Io {
+                path: None,
+                err,
+                operation,
             },
         }
     }
 
-    pub(crate) fn from_io(depth: usize, err: io::Error) -> Self {
+    pub(crate) fn from_change(depth: usize, path: &Path) -> Self {
         Error {
             depth,
-            inner: ErrorInner::Io { path: None, err },
+            inner: ErrorInner::Changed {
+                path: path.to_path_buf(),
+            },
         }
     }
 
@@ -221,11 +325,47 @@ impl Error {
     }
 }
 
+// `io::Error` isn't `Clone`, so it's recreated from its raw OS error code,
+// or from its kind and message.
+impl Clone for Error {
+    fn clone(&self) -> Self {
+        let inner = match self.inner {
+            ErrorInner::Io {
+                ref path,
+                ref err,
+                operation,
+            } => ErrorInner::Io {
+                path: path.clone(),
+                err: match err.raw_os_error() {
+                    Some(code) => io::Error::from_raw_os_error(code),
+                    None => io::Error::new(err.kind(), err.to_string()),
+                },
+                operation,
+            },
+            ErrorInner::Loop {
+                ref ancestor,
+                ref child,
+            } => ErrorInner::Loop {
+                ancestor: ancestor.clone(),
+                child: child.clone(),
+            },
+            ErrorInner::ThreadpoolBusy => ErrorInner::ThreadpoolBusy,
+            ErrorInner::Changed { ref path } => ErrorInner::Changed { path: path.clone() },
+        };
+        Error {
+            depth: self.depth,
+            inner,
+        }
+    }
+}
+
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self.inner {
             ErrorInner::Io { ref err, .. } => Some(err),
-            ErrorInner::Loop { .. } | ErrorInner::ThreadpoolBusy => None,
+            ErrorInner::Loop { .. } | ErrorInner::ThreadpoolBusy | ErrorInner::Changed { .. } => {
+                None
+            }
         }
     }
 
@@ -235,6 +375,7 @@ impl error::Error for Error {
             ErrorInner::Io { ref err, .. } => err.description(),
             ErrorInner::Loop { .. } => "file system loop found",
             ErrorInner::ThreadpoolBusy => "thread-pool busy",
+            ErrorInner::Changed { .. } => "directory changed during walk",
         }
     }
 
@@ -250,10 +391,12 @@ impl fmt::Display for Error {
             ErrorInner::Io {
                 path: None,
                 ref err,
+                ..
             } => err.fmt(f),
             ErrorInner::Io {
                 path: Some(ref path),
                 ref err,
+                ..
             } => write!(f, "IO error for operation on {}: {}", path.display(), err),
             ErrorInner::Loop {
                 ref ancestor,
@@ -265,6 +408,11 @@ impl fmt::Display for Error {
                 child.display(),
                 ancestor.display()
             ),
+            ErrorInner::Changed { ref path } => write!(
+                f,
+                "Directory {} changed while its contents were walked",
+                path.display()
+            ),
         }
     }
 }
@@ -295,7 +443,102 @@ impl From<Error> for io::Error {
                 inner: ErrorInner::ThreadpoolBusy,
                 ..
             } => io::ErrorKind::Other,
+            Error {
+                inner: ErrorInner::Changed { .. },
+                ..
+            } => io::ErrorKind::Other,
         };
         io::Error::new(kind, walk_err)
     }
 }
+
+// Errors are written as their kind along with its data. IO errors keep their
+// raw OS error code, others are read back with their message only.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ErrorData {
+    depth: usize,
+    error: ErrorInnerData,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+enum ErrorInnerData {
+    Io {
+        path: Option<PathBuf>,
+        operation: Operation,
+        raw_os_error: Option<i32>,
+        message: String,
+    },
+    Loop {
+        ancestor: PathBuf,
+        child: PathBuf,
+    },
+    Busy,
+    Changed {
+        path: PathBuf,
+    },
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let error = match self.inner {
+            ErrorInner::Io {
+                ref path,
+                ref err,
+                operation,
+            } => ErrorInnerData::Io {
+                path: path.clone(),
+                operation,
+                raw_os_error: err.raw_os_error(),
+                message: err.to_string(),
+            },
+            ErrorInner::Loop {
+                ref ancestor,
+                ref child,
+            } => ErrorInnerData::Loop {
+                ancestor: ancestor.clone(),
+                child: child.clone(),
+            },
+            ErrorInner::ThreadpoolBusy => ErrorInnerData::Busy,
+            ErrorInner::Changed { ref path } => ErrorInnerData::Changed { path: path.clone() },
+        };
+        ErrorData {
+            depth: self.depth,
+            error,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Error {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let ErrorData { depth, error } = ErrorData::deserialize(deserializer)?;
+        let inner = match error {
+            ErrorInnerData::Io {
+                path,
+                operation,
+                raw_os_error,
+                message,
+            } => ErrorInner::Io {
+                path,
+                err: match raw_os_error {
+                    Some(code) => io::Error::from_raw_os_error(code),
+                    None => io::Error::other(message),
+                },
+                operation,
+            },
+            ErrorInnerData::Loop { ancestor, child } => ErrorInner::Loop { ancestor, child },
+            ErrorInnerData::Busy => ErrorInner::ThreadpoolBusy,
+            ErrorInnerData::Changed { path } => ErrorInner::Changed { path },
+        };
+        Ok(Error { depth, inner })
+    }
+}
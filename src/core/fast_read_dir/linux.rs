@@ -0,0 +1,148 @@
+//! Directory listing with raw `getdents64` calls.
+
+use std::ffi::OsStr;
+use std::fs::{self, File, FileType};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::RawDirEntry;
+
+// Large enough to list most directories with a single call.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// Offsets into `struct linux_dirent64`.
+const D_RECLEN: usize = 16;
+const D_TYPE: usize = 18;
+const D_NAME: usize = 19;
+
+/// Iterator over the entries of a directory, without `.` and `..`.
+pub(crate) struct FastReadDir {
+    path: PathBuf,
+    dir: Option<File>,
+    buf: Vec<u8>,
+    filled: usize,
+    offset: usize,
+}
+
+/// List the directory at `path`. File types are taken from `d_type`, so
+/// entries are only `stat`ed if the file system doesn't report their type.
+pub(crate) fn read_dir(path: &Path) -> io::Result<FastReadDir> {
+    let dir = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(path)?;
+    Ok(read_dir_file(dir, path))
+}
+
+/// List the directory opened as `dir`, whose path is `path`.
+pub(crate) fn read_dir_file(dir: File, path: &Path) -> FastReadDir {
+    FastReadDir {
+        path: path.to_path_buf(),
+        dir: Some(dir),
+        buf: vec![0; BUFFER_SIZE],
+        filled: 0,
+        offset: 0,
+    }
+}
+
+impl FastReadDir {
+    // Refill the buffer, returning false at the end of the directory.
+    fn fill(&mut self) -> io::Result<bool> {
+        let dir = match self.dir.as_ref() {
+            Some(dir) => dir,
+            None => return Ok(false),
+        };
+        // SAFETY: the buffer is valid for writes of its length and the file
+        // descriptor stays open while `dir` is borrowed.
+        let filled = unsafe {
+            libc::syscall(
+                libc::SYS_getdents64,
+                dir.as_raw_fd(),
+                self.buf.as_mut_ptr(),
+                self.buf.len(),
+            )
+        };
+        if filled < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.filled = filled as usize;
+        self.offset = 0;
+        Ok(self.filled > 0)
+    }
+}
+
+impl Iterator for FastReadDir {
+    type Item = io::Result<RawDirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<RawDirEntry>> {
+        loop {
+            if self.offset >= self.filled {
+                match self.fill() {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        self.dir = None;
+                        return None;
+                    }
+                    Err(err) => {
+                        self.dir = None;
+                        return Some(Err(err));
+                    }
+                }
+            }
+
+            let record = &self.buf[self.offset..self.filled];
+            let reclen = u16::from_ne_bytes([record[D_RECLEN], record[D_RECLEN + 1]]) as usize;
+            self.offset += reclen;
+
+            let name = &record[D_NAME..reclen];
+            let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+            if name == b"." || name == b".." {
+                continue;
+            }
+
+            let file_name = OsStr::from_bytes(name).to_owned();
+            let file_type = file_type(&self.path, &file_name, record[D_TYPE]);
+            return Some(Ok(RawDirEntry {
+                file_name,
+                file_type,
+                ext: None,
+            }));
+        }
+    }
+}
+
+// `FileType` can't be created from `d_type`, so the type of the first entry
+// with each `d_type` is `stat`ed and reused for all entries after it.
+fn file_type(dir: &Path, file_name: &OsStr, d_type: u8) -> io::Result<FileType> {
+    static FILE_TYPES: [OnceLock<FileType>; 16] = [const { OnceLock::new() }; 16];
+
+    let cached = FILE_TYPES
+        .get(d_type as usize)
+        .filter(|_| d_type != libc::DT_UNKNOWN);
+    if let Some(file_type) = cached.and_then(OnceLock::get) {
+        return Ok(*file_type);
+    }
+    let file_type = fs::symlink_metadata(dir.join(file_name))?.file_type();
+    // The entry may have been replaced since it was listed.
+    if let Some(cached) = cached.filter(|_| has_d_type(file_type, d_type)) {
+        let _ = cached.set(file_type);
+    }
+    Ok(file_type)
+}
+
+fn has_d_type(file_type: FileType, d_type: u8) -> bool {
+    match d_type {
+        libc::DT_DIR => file_type.is_dir(),
+        libc::DT_REG => file_type.is_file(),
+        libc::DT_LNK => file_type.is_symlink(),
+        libc::DT_FIFO => file_type.is_fifo(),
+        libc::DT_SOCK => file_type.is_socket(),
+        libc::DT_CHR => file_type.is_char_device(),
+        libc::DT_BLK => file_type.is_block_device(),
+        _ => false,
+    }
+}
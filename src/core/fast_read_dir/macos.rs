@@ -0,0 +1,284 @@
+//! Directory listing with `getattrlistbulk`.
+
+use std::ffi::OsStr;
+use std::fs::{self, File, FileType};
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::RawDirEntry;
+use crate::DirEntryExt;
+
+// Large enough to list most directories with a single call.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// Missing from `libc`, see `getattrlist(2)` and `sys/vnode.h`.
+const ATTR_CMN_ERROR: u32 = 0x2000_0000;
+const VREG: u32 = 1;
+const VDIR: u32 = 2;
+const VBLK: u32 = 3;
+const VCHR: u32 = 4;
+const VLNK: u32 = 5;
+const VSOCK: u32 = 6;
+const VFIFO: u32 = 7;
+
+const COMMON_ATTRS: u32 = libc::ATTR_CMN_RETURNED_ATTRS
+    | libc::ATTR_CMN_NAME
+    | ATTR_CMN_ERROR
+    | libc::ATTR_CMN_DEVID
+    | libc::ATTR_CMN_OBJTYPE
+    | libc::ATTR_CMN_MODTIME
+    | libc::ATTR_CMN_OWNERID
+    | libc::ATTR_CMN_GRPID
+    | libc::ATTR_CMN_ACCESSMASK
+    | libc::ATTR_CMN_FLAGS
+    | libc::ATTR_CMN_FILEID;
+const FILE_ATTRS: u32 = libc::ATTR_FILE_LINKCOUNT
+    | libc::ATTR_FILE_ALLOCSIZE
+    | libc::ATTR_FILE_IOBLOCKSIZE
+    | libc::ATTR_FILE_DEVTYPE
+    | libc::ATTR_FILE_DATALENGTH;
+// Attributes needed for `DirEntryExt`.
+const EXT_COMMON_ATTRS: u32 = COMMON_ATTRS & !(libc::ATTR_CMN_RETURNED_ATTRS | ATTR_CMN_ERROR);
+
+/// Iterator over the entries of a directory.
+pub(crate) struct FastReadDir {
+    path: PathBuf,
+    dir: Option<File>,
+    buf: Vec<u8>,
+    // entries in the buffer that weren't yielded yet
+    remaining: usize,
+    offset: usize,
+}
+
+/// List the directory at `path`. Names, types, sizes and the rest of
+/// `DirEntryExt` of regular files are read in bulk, entries are only
+/// `stat`ed once per type to create their `FileType`.
+pub(crate) fn read_dir(path: &Path) -> io::Result<FastReadDir> {
+    let dir = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(path)?;
+    Ok(read_dir_file(dir, path))
+}
+
+/// List the directory opened as `dir`, whose path is `path`.
+pub(crate) fn read_dir_file(dir: File, path: &Path) -> FastReadDir {
+    FastReadDir {
+        path: path.to_path_buf(),
+        dir: Some(dir),
+        buf: vec![0; BUFFER_SIZE],
+        remaining: 0,
+        offset: 0,
+    }
+}
+
+impl FastReadDir {
+    // Refill the buffer, returning false at the end of the directory.
+    fn fill(&mut self) -> io::Result<bool> {
+        let dir = match self.dir.as_ref() {
+            Some(dir) => dir,
+            None => return Ok(false),
+        };
+        // SAFETY: `attrlist` is plain data and valid when zeroed.
+        let mut attr_list: libc::attrlist = unsafe { mem::zeroed() };
+        attr_list.bitmapcount = libc::ATTR_BIT_MAP_COUNT;
+        attr_list.commonattr = COMMON_ATTRS;
+        attr_list.fileattr = FILE_ATTRS;
+        // SAFETY: the buffer is valid for writes of its length and the file
+        // descriptor stays open while `dir` is borrowed.
+        let count = unsafe {
+            libc::getattrlistbulk(
+                dir.as_raw_fd(),
+                &mut attr_list as *mut libc::attrlist as *mut _,
+                self.buf.as_mut_ptr() as *mut _,
+                self.buf.len(),
+                0,
+            )
+        };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.remaining = count as usize;
+        self.offset = 0;
+        Ok(self.remaining > 0)
+    }
+}
+
+impl Iterator for FastReadDir {
+    type Item = io::Result<RawDirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<RawDirEntry>> {
+        if self.remaining == 0 {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.dir = None;
+                    return None;
+                }
+                Err(err) => {
+                    self.dir = None;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        let mut fields = Fields {
+            entry: &self.buf[self.offset..],
+            offset: 0,
+        };
+        let length = fields.u32() as usize;
+        fields.entry = &fields.entry[..length];
+        self.offset += length;
+        self.remaining -= 1;
+        Some(Ok(fields.raw_dir_entry(&self.path)))
+    }
+}
+
+// Attributes of one entry, packed in the order they are requested in.
+struct Fields<'a> {
+    entry: &'a [u8],
+    offset: usize,
+}
+
+impl Fields<'_> {
+    fn u32(&mut self) -> u32 {
+        let bytes = &self.entry[self.offset..self.offset + 4];
+        self.offset += 4;
+        u32::from_ne_bytes(bytes.try_into().unwrap())
+    }
+
+    fn u64(&mut self) -> u64 {
+        let bytes = &self.entry[self.offset..self.offset + 8];
+        self.offset += 8;
+        u64::from_ne_bytes(bytes.try_into().unwrap())
+    }
+
+    // Attributes are only packed if they are `returned`.
+    fn u32_if(&mut self, returned: u32, attr: u32) -> u32 {
+        if returned & attr != 0 {
+            self.u32()
+        } else {
+            0
+        }
+    }
+
+    fn u64_if(&mut self, returned: u32, attr: u32) -> u64 {
+        if returned & attr != 0 {
+            self.u64()
+        } else {
+            0
+        }
+    }
+
+    // The data of an `attrreference_t` is found relative to its own start.
+    fn name(&mut self) -> &OsStr {
+        let start = self.offset;
+        let data_offset = self.u32() as i32;
+        let length = self.u32() as usize;
+        let data_start = (start as isize + data_offset as isize) as usize;
+        let name = &self.entry[data_start..data_start + length];
+        OsStr::from_bytes(name.strip_suffix(b"\0").unwrap_or(name))
+    }
+
+    fn raw_dir_entry(&mut self, dir: &Path) -> RawDirEntry {
+        let common = self.u32();
+        let _volume = self.u32();
+        let _dir = self.u32();
+        let file = self.u32();
+        let _fork = self.u32();
+
+        let error = self.u32_if(common, ATTR_CMN_ERROR);
+        let file_name = if common & libc::ATTR_CMN_NAME != 0 {
+            self.name().to_owned()
+        } else {
+            Default::default()
+        };
+        let dev = self.u32_if(common, libc::ATTR_CMN_DEVID) as i32 as u64;
+        let obj_type = self.u32_if(common, libc::ATTR_CMN_OBJTYPE);
+        // A `timespec` of two 64 bit fields
+        let mtime = self.u64_if(common, libc::ATTR_CMN_MODTIME) as i64;
+        let mtime_nsec = self.u64_if(common, libc::ATTR_CMN_MODTIME) as i64;
+        let uid = self.u32_if(common, libc::ATTR_CMN_OWNERID);
+        let gid = self.u32_if(common, libc::ATTR_CMN_GRPID);
+        let access_mask = self.u32_if(common, libc::ATTR_CMN_ACCESSMASK);
+        let flags = self.u32_if(common, libc::ATTR_CMN_FLAGS);
+        let ino = self.u64_if(common, libc::ATTR_CMN_FILEID);
+        let nlink = self.u32_if(file, libc::ATTR_FILE_LINKCOUNT) as u64;
+        let alloc_size = self.u64_if(file, libc::ATTR_FILE_ALLOCSIZE);
+        let blksize = self.u32_if(file, libc::ATTR_FILE_IOBLOCKSIZE) as u64;
+        let rdev = self.u32_if(file, libc::ATTR_FILE_DEVTYPE) as i32 as u64;
+        let size = self.u64_if(file, libc::ATTR_FILE_DATALENGTH);
+
+        if error != 0 {
+            return RawDirEntry {
+                file_name,
+                file_type: Err(io::Error::from_raw_os_error(error as i32)),
+                ext: None,
+            };
+        }
+
+        let has_ext = obj_type == VREG
+            && common & EXT_COMMON_ATTRS == EXT_COMMON_ATTRS
+            && file & FILE_ATTRS == FILE_ATTRS;
+        let ext = has_ext.then_some(DirEntryExt {
+            mode: libc::S_IFREG as u32 | access_mask & 0o7777,
+            ino,
+            dev,
+            nlink,
+            uid,
+            gid,
+            size,
+            rdev,
+            blksize,
+            blocks: alloc_size / 512,
+            mtime,
+            mtime_nsec,
+            flags,
+        });
+        let file_type = if common & libc::ATTR_CMN_OBJTYPE != 0 {
+            file_type(dir, &file_name, obj_type)
+        } else {
+            fs::symlink_metadata(dir.join(&file_name)).map(|metadata| metadata.file_type())
+        };
+        RawDirEntry {
+            file_name,
+            file_type,
+            ext,
+        }
+    }
+}
+
+// `FileType` can't be created from an object type, so the type of the first
+// entry of each object type is `stat`ed and reused for all entries after it.
+fn file_type(dir: &Path, file_name: &OsStr, obj_type: u32) -> io::Result<FileType> {
+    static FILE_TYPES: [OnceLock<FileType>; 8] = [const { OnceLock::new() }; 8];
+
+    let cached = FILE_TYPES.get(obj_type as usize);
+    if let Some(file_type) = cached.and_then(OnceLock::get) {
+        return Ok(*file_type);
+    }
+    let file_type = fs::symlink_metadata(dir.join(file_name))?.file_type();
+    // The entry may have been replaced since it was listed.
+    if let Some(cached) = cached.filter(|_| has_obj_type(file_type, obj_type)) {
+        let _ = cached.set(file_type);
+    }
+    Ok(file_type)
+}
+
+fn has_obj_type(file_type: FileType, obj_type: u32) -> bool {
+    match obj_type {
+        VREG => file_type.is_file(),
+        VDIR => file_type.is_dir(),
+        VBLK => file_type.is_block_device(),
+        VCHR => file_type.is_char_device(),
+        VLNK => file_type.is_symlink(),
+        VSOCK => file_type.is_socket(),
+        VFIFO => file_type.is_fifo(),
+        _ => false,
+    }
+}
@@ -0,0 +1,30 @@
+//! Directory listings with platform specific system calls, enabled by the
+//! `fast_readdir` feature.
+
+use std::ffi::OsString;
+use std::fs::FileType;
+use std::io;
+
+use super::DirEntryExt;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::{read_dir, read_dir_file, FastReadDir};
+#[cfg(target_os = "macos")]
+pub(crate) use macos::{read_dir, read_dir_file, FastReadDir};
+#[cfg(windows)]
+pub(crate) use windows::{read_dir, FastReadDir};
+
+/// Entry as listed by the platform.
+pub(crate) struct RawDirEntry {
+    pub(crate) file_name: OsString,
+    pub(crate) file_type: io::Result<FileType>,
+    // Metadata returned along with the listing, if the platform does.
+    pub(crate) ext: Option<DirEntryExt>,
+}
@@ -0,0 +1,205 @@
+//! Directory listing with `FindFirstFileExW` and large fetches.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, FileType};
+use std::io;
+use std::iter;
+use std::mem;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::OnceLock;
+
+use windows_sys::Win32::Foundation::{
+    GetLastError, ERROR_FILE_NOT_FOUND, ERROR_NO_MORE_FILES, FILETIME, HANDLE, INVALID_HANDLE_VALUE,
+};
+use windows_sys::Win32::Storage::FileSystem::{
+    FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW, FindNextFileW,
+    FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT, FIND_FIRST_EX_LARGE_FETCH,
+    WIN32_FIND_DATAW,
+};
+
+use super::RawDirEntry;
+use crate::DirEntryExt;
+
+// Reparse tags of symbolic links and junctions have this bit set.
+const NAME_SURROGATE: u32 = 0x2000_0000;
+
+/// Iterator over the entries of a directory, without `.` and `..`.
+pub(crate) struct FastReadDir {
+    path: PathBuf,
+    handle: HANDLE,
+    // entry returned by `FindFirstFileExW`, not yet yielded
+    first: Option<WIN32_FIND_DATAW>,
+}
+
+// SAFETY: the find handle isn't tied to the thread that opened it.
+unsafe impl Send for FastReadDir {}
+
+/// List the directory at `path`. Attributes, sizes and times come with the
+/// listing, so entries are only `stat`ed once per kind of file.
+pub(crate) fn read_dir(path: &Path) -> io::Result<FastReadDir> {
+    let pattern: Vec<u16> = path
+        .join("*")
+        .as_os_str()
+        .encode_wide()
+        .chain(iter::once(0))
+        .collect();
+    // SAFETY: `WIN32_FIND_DATAW` is plain data and valid when zeroed.
+    let mut find_data: WIN32_FIND_DATAW = unsafe { mem::zeroed() };
+    // SAFETY: `pattern` is nul terminated and `find_data` matches the
+    // `FindExInfoBasic` info level.
+    let handle = unsafe {
+        FindFirstFileExW(
+            pattern.as_ptr(),
+            FindExInfoBasic,
+            &mut find_data as *mut WIN32_FIND_DATAW as *mut _,
+            FindExSearchNameMatch,
+            ptr::null(),
+            FIND_FIRST_EX_LARGE_FETCH,
+        )
+    };
+    let first = if handle == INVALID_HANDLE_VALUE {
+        // Roots of empty drives have no `.` entry to find.
+        // SAFETY: no other call was made since `FindFirstFileExW` failed.
+        if unsafe { GetLastError() } != ERROR_FILE_NOT_FOUND {
+            return Err(io::Error::last_os_error());
+        }
+        None
+    } else {
+        Some(find_data)
+    };
+    Ok(FastReadDir {
+        path: path.to_path_buf(),
+        handle,
+        first,
+    })
+}
+
+impl FastReadDir {
+    fn find_next(&mut self) -> Option<io::Result<WIN32_FIND_DATAW>> {
+        if let Some(find_data) = self.first.take() {
+            return Some(Ok(find_data));
+        }
+        if self.handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        // SAFETY: `WIN32_FIND_DATAW` is plain data and valid when zeroed.
+        let mut find_data: WIN32_FIND_DATAW = unsafe { mem::zeroed() };
+        // SAFETY: the handle is open until dropped.
+        if unsafe { FindNextFileW(self.handle, &mut find_data) } != 0 {
+            return Some(Ok(find_data));
+        }
+        // SAFETY: no other call was made since `FindNextFileW` failed.
+        if unsafe { GetLastError() } == ERROR_NO_MORE_FILES {
+            return None;
+        }
+        Some(Err(io::Error::last_os_error()))
+    }
+
+    fn close(&mut self) {
+        if self.handle != INVALID_HANDLE_VALUE {
+            // SAFETY: the handle is open and not used after this.
+            unsafe { FindClose(self.handle) };
+            self.handle = INVALID_HANDLE_VALUE;
+        }
+    }
+}
+
+impl Iterator for FastReadDir {
+    type Item = io::Result<RawDirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<RawDirEntry>> {
+        loop {
+            let find_data = match self.find_next()? {
+                Ok(find_data) => find_data,
+                Err(err) => {
+                    self.close();
+                    return Some(Err(err));
+                }
+            };
+
+            let len = find_data
+                .cFileName
+                .iter()
+                .position(|c| *c == 0)
+                .unwrap_or(find_data.cFileName.len());
+            let name = &find_data.cFileName[..len];
+            if name == [b'.' as u16] || name == [b'.' as u16, b'.' as u16] {
+                continue;
+            }
+
+            let file_name = OsString::from_wide(name);
+            let file_type = file_type(&self.path, &file_name, &find_data);
+            return Some(Ok(RawDirEntry {
+                file_name,
+                file_type,
+                ext: Some(DirEntryExt {
+                    file_attributes: find_data.dwFileAttributes,
+                    creation_time: file_time(find_data.ftCreationTime),
+                    last_access_time: file_time(find_data.ftLastAccessTime),
+                    last_write_time: file_time(find_data.ftLastWriteTime),
+                    file_size: (find_data.nFileSizeHigh as u64) << 32
+                        | find_data.nFileSizeLow as u64,
+                }),
+            }));
+        }
+    }
+}
+
+impl Drop for FastReadDir {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn file_time(file_time: FILETIME) -> u64 {
+    (file_time.dwHighDateTime as u64) << 32 | file_time.dwLowDateTime as u64
+}
+
+// Kinds of files that `FileType` tells apart.
+const FILE: usize = 0;
+const DIR: usize = 1;
+const SYMLINK_FILE: usize = 2;
+const SYMLINK_DIR: usize = 3;
+
+// `FileType` can't be created from attributes, so the type of the first
+// entry of each kind is `stat`ed and reused for all entries after it.
+fn file_type(dir: &Path, file_name: &OsStr, find_data: &WIN32_FIND_DATAW) -> io::Result<FileType> {
+    static FILE_TYPES: [OnceLock<FileType>; 4] = [const { OnceLock::new() }; 4];
+
+    let is_dir = find_data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY != 0;
+    // `dwReserved0` holds the reparse tag of reparse points.
+    let is_symlink = find_data.dwFileAttributes & FILE_ATTRIBUTE_REPARSE_POINT != 0
+        && find_data.dwReserved0 & NAME_SURROGATE != 0;
+    let kind = match (is_symlink, is_dir) {
+        (false, false) => FILE,
+        (false, true) => DIR,
+        (true, false) => SYMLINK_FILE,
+        (true, true) => SYMLINK_DIR,
+    };
+
+    let cached = &FILE_TYPES[kind];
+    if let Some(file_type) = cached.get() {
+        return Ok(*file_type);
+    }
+    let file_type = fs::symlink_metadata(dir.join(file_name))?.file_type();
+    // The entry may have been replaced since it was listed.
+    if kind_of(file_type) == kind {
+        let _ = cached.set(file_type);
+    }
+    Ok(file_type)
+}
+
+fn kind_of(file_type: FileType) -> usize {
+    if file_type.is_symlink_dir() {
+        SYMLINK_DIR
+    } else if file_type.is_symlink_file() {
+        SYMLINK_FILE
+    } else if file_type.is_dir() {
+        DIR
+    } else {
+        FILE
+    }
+}
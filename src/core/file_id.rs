@@ -0,0 +1,13 @@
+/// Identity of a file on its file system, as returned by
+/// [`DirEntry::file_id`](struct.DirEntry.html#method.file_id).
+///
+/// Hard links to the same file and paths reaching it through symbolic links
+/// all have the same id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId {
+    /// The ID of the device containing the file.
+    pub dev: u64,
+    /// The inode number of the file.
+    pub ino: u64,
+}
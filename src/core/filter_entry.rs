@@ -0,0 +1,47 @@
+use super::*;
+use crate::Result;
+
+/// Iterator returned by [`DirEntryIter::filter_entry`].
+///
+/// Yields the entries accepted by its predicate, skipping the contents of
+/// rejected directories.
+pub struct FilterEntry<C: ClientState, P> {
+    iter: DirEntryIter<C>,
+    predicate: P,
+}
+
+impl<C, P> FilterEntry<C, P>
+where
+    C: ClientState,
+    P: FnMut(&DirEntry<C>) -> bool,
+{
+    pub(crate) fn new(iter: DirEntryIter<C>, predicate: P) -> Self {
+        FilterEntry { iter, predicate }
+    }
+
+    /// Skip the contents of the directory that was yielded last, see
+    /// [`DirEntryIter::skip_current_dir`].
+    pub fn skip_current_dir(&mut self) {
+        self.iter.skip_current_dir();
+    }
+}
+
+impl<C, P> Iterator for FilterEntry<C, P>
+where
+    C: ClientState,
+    P: FnMut(&DirEntry<C>) -> bool,
+{
+    type Item = Result<DirEntry<C>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dir_entry = match self.iter.next()? {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => return Some(Err(err)),
+            };
+            if (self.predicate)(&dir_entry) {
+                return Some(Ok(dir_entry));
+            }
+            self.iter.skip_yielded_dir_contents();
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Paths matching `pattern`, sorted by name within each directory.
+///
+/// Within a path component, `*` matches any run of characters, `?` a single
+/// character and `[...]` one of the characters or ranges within, or none of
+/// them if it starts with `!`. Names starting with `.` are only matched by
+/// components starting with `.` too. A leading `~` stands for the home
+/// directory. Directories that can't be read don't match anything.
+pub(crate) fn expand(pattern: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::new()];
+    for (index, component) in pattern.components().enumerate() {
+        let name = component.as_os_str();
+        if index == 0 && name == "~" {
+            if let Some(home) = home_dir() {
+                candidates = vec![home];
+                continue;
+            }
+        }
+        match name.to_str().filter(|name| is_pattern(name)) {
+            Some(pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                candidates = candidates
+                    .iter()
+                    .flat_map(|dir| matching(dir, &pattern))
+                    .collect();
+            }
+            None => {
+                for candidate in candidates.iter_mut() {
+                    candidate.push(name);
+                }
+            }
+        }
+    }
+    candidates.retain(|path| fs::symlink_metadata(path).is_ok());
+    candidates
+}
+
+fn is_pattern(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+fn home_dir() -> Option<PathBuf> {
+    let home = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    env::var_os(home).map(PathBuf::from)
+}
+
+// Entries of `dir` matching `pattern`, sorted by name.
+fn matching(dir: &Path, pattern: &[char]) -> Vec<PathBuf> {
+    let read_dir = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+    let read_dir = match read_dir {
+        Ok(read_dir) => read_dir,
+        Err(_) => return Vec::new(),
+    };
+    let mut names: Vec<_> = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .filter(|name| {
+            let name: Vec<char> = match name.to_str() {
+                Some(name) => name.chars().collect(),
+                None => return false,
+            };
+            (name.first() != Some(&'.') || pattern.first() == Some(&'.')) && matches(pattern, &name)
+        })
+        .collect();
+    names.sort();
+    names.into_iter().map(|name| dir.join(name)).collect()
+}
+
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+        Some(('[', rest)) => match rest.iter().skip(1).position(|c| *c == ']') {
+            Some(end) => {
+                let (class, rest) = (&rest[..end + 1], &rest[end + 2..]);
+                match name.split_first() {
+                    Some((c, name)) => class_matches(class, *c) && matches(rest, name),
+                    None => false,
+                }
+            }
+            None => name.first() == Some(&'[') && matches(rest, &name[1..]),
+        },
+        Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+    }
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, mut class) = match class.split_first() {
+        Some(('!', class)) if !class.is_empty() => (true, class),
+        _ => (false, class),
+    };
+    let mut found = false;
+    while let Some((first, rest)) = class.split_first() {
+        match rest {
+            ['-', last, rest @ ..] => {
+                found |= (*first..=*last).contains(&c);
+                class = rest;
+            }
+            _ => {
+                found |= *first == c;
+                class = rest;
+            }
+        }
+    }
+    found != negated
+}
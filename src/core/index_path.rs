@@ -1,33 +1,47 @@
 use std::cmp::Ordering;
 
+/// Position in a tree, as the index of each ancestor among its siblings
+/// followed by the index of the item itself. Roots are at `[0]`, `[1]` and
+/// so on.
+///
+/// Paths compare in reverse depth first order, so that earlier paths are
+/// greater and popped first from a `BinaryHeap`.
 #[derive(Clone, Debug)]
 pub struct IndexPath {
+    /// Indices from the root down to the item.
     pub indices: Vec<usize>,
 }
 
 impl IndexPath {
+    /// Path made of `indices`.
     pub fn new(indices: Vec<usize>) -> IndexPath {
         IndexPath { indices }
     }
 
+    /// Path of the child at `index` of the item at this path.
     pub fn adding(&self, index: usize) -> IndexPath {
         let mut indices = self.indices.clone();
         indices.push(index);
         IndexPath::new(indices)
     }
 
+    /// Move to the child at `index`.
     pub fn push(&mut self, index: usize) {
         self.indices.push(index);
     }
 
+    /// Move to the next sibling.
     pub fn increment_last(&mut self) {
         *self.indices.last_mut().unwrap() += 1;
     }
 
+    /// Move to the parent, returning the index of the item among its
+    /// siblings.
     pub fn pop(&mut self) -> Option<usize> {
         self.indices.pop()
     }
 
+    /// True if this is the path of no item.
     pub fn is_empty(&self) -> bool {
         self.indices.is_empty()
     }
@@ -43,7 +57,7 @@ impl Eq for IndexPath {}
 
 impl PartialOrd for IndexPath {
     fn partial_cmp(&self, o: &Self) -> Option<Ordering> {
-        Some(o.indices.cmp(&self.indices))
+        Some(self.cmp(o))
     }
 }
 
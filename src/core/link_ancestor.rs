@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::LoopDetection;
+
+/// A directory above an entry, which followed symlinks must not point to.
+#[derive(Clone, Debug)]
+pub(crate) struct LinkAncestor {
+    pub(crate) path: Arc<Path>,
+    pub(crate) id: Option<DirId>,
+}
+
+impl LinkAncestor {
+    pub(crate) fn new(path: Arc<Path>, loop_detection: LoopDetection) -> LinkAncestor {
+        let id = DirId::of(&path, loop_detection);
+        LinkAncestor { path, id }
+    }
+}
+
+/// Identity of a directory, in the way chosen by
+/// [`LoopDetection`](enum.LoopDetection.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum DirId {
+    #[cfg(unix)]
+    DevIno(u64, u64),
+    Canonical(PathBuf),
+}
+
+impl DirId {
+    pub(crate) fn of(path: &Path, loop_detection: LoopDetection) -> Option<DirId> {
+        match loop_detection {
+            #[cfg(unix)]
+            LoopDetection::DevIno => {
+                use std::os::unix::fs::MetadataExt;
+                let metadata = fs::metadata(path).ok()?;
+                Some(DirId::DevIno(metadata.dev(), metadata.ino()))
+            }
+            // Without device and inode numbers, paths are compared instead.
+            #[cfg(not(unix))]
+            LoopDetection::DevIno => DirId::of(path, LoopDetection::Canonical),
+            LoopDetection::Canonical => fs::canonicalize(path).ok().map(DirId::Canonical),
+            LoopDetection::Off => None,
+        }
+    }
+
+    /// Identity of the directory at `path` of the same kind as this one.
+    pub(crate) fn of_same_kind(&self, path: &Path) -> Option<DirId> {
+        match self {
+            #[cfg(unix)]
+            DirId::DevIno(..) => DirId::of(path, LoopDetection::DevIno),
+            DirId::Canonical(_) => DirId::of(path, LoopDetection::Canonical),
+        }
+    }
+}
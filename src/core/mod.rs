@@ -1,29 +1,183 @@
+mod adaptive_limiter;
+mod aggregate;
+mod backpressure;
+mod checkpoint;
+mod deepening;
+mod device_limiter;
+mod diff;
 mod dir_entry;
+mod dir_entry_batch_iter;
+mod dir_entry_ext;
 mod dir_entry_iter;
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos")
+))]
+mod dir_fd;
+mod dispatcher;
+mod enriched_iter;
 mod error;
-mod index_path;
-mod ordered;
-mod ordered_queue;
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos", windows)
+))]
+mod fast_read_dir;
+mod file_id;
+mod filter_entry;
+mod glob;
+pub(crate) mod index_path;
+mod link_ancestor;
+pub(crate) mod ordered;
+pub(crate) mod ordered_queue;
+mod par_dir_entry_iter;
+mod prune;
 mod read_dir;
 mod read_dir_iter;
 mod read_dir_spec;
 mod run_context;
+mod spill;
+mod take_until;
+mod walk_event;
+mod walk_summary;
 
 use rayon::prelude::*;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::vec;
 
+use backpressure::*;
+use dispatcher::*;
 use index_path::*;
 use ordered::*;
 use ordered_queue::*;
 use read_dir_iter::*;
 use run_context::*;
+use spill::*;
 
+pub(crate) use adaptive_limiter::AdaptiveLimiter;
+pub use aggregate::Aggregate;
+pub use checkpoint::{Checkpoint, PendingEntry};
+pub(crate) use deepening::{Deepening, RoundState};
+pub(crate) use device_limiter::DeviceLimiter;
+pub(crate) use diff::ChangeKind;
+pub use diff::{Change, DiffIter, Snapshot};
 pub use dir_entry::DirEntry;
+#[cfg(feature = "serde")]
+pub use dir_entry::SerializedDirEntry;
+pub use dir_entry_batch_iter::DirEntryBatchIter;
+pub use dir_entry_ext::DirEntryExt;
 pub use dir_entry_iter::DirEntryIter;
-pub use error::Error;
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos")
+))]
+pub(crate) use dir_fd::DirFd;
+pub use enriched_iter::EnrichedIter;
+pub use error::{Error, ErrorKind, Operation};
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos", windows)
+))]
+pub(crate) use fast_read_dir::{read_dir as fast_read_dir, FastReadDir};
+pub use file_id::FileId;
+pub use filter_entry::FilterEntry;
+pub(crate) use glob::expand as expand_glob;
+pub(crate) use link_ancestor::LinkAncestor;
+pub use par_dir_entry_iter::ParDirEntryIter;
+pub(crate) use prune::Pruner;
 pub use read_dir::ReadDir;
+pub(crate) use read_dir::ReadDirSnapshot;
+pub(crate) use read_dir_iter::{QueueLimits, ReadDirCallback};
 pub use read_dir_spec::ReadDirSpec;
+pub use take_until::TakeUntil;
+pub use walk_event::{WalkEvent, WalkEventIter};
+pub use walk_summary::WalkSummary;
 
 use crate::{ClientState, Parallelism};
+
+/// Directories can't be opened relative to their parent on this platform.
+#[cfg(not(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos")
+)))]
+#[derive(Debug)]
+pub(crate) enum DirFd {}
+
+#[cfg(not(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos")
+)))]
+impl DirFd {
+    pub(crate) fn root_entry<C: ClientState>(&self) -> crate::Result<DirEntry<C>> {
+        match *self {}
+    }
+}
+
+/// Read directories starting at `read_dir_specs` with the `read_dir`
+/// callback, yielding each `ReadDir` in depth first order.
+///
+/// This is the core of [`WalkDirGeneric`](../struct.WalkDirGeneric.html)
+/// without any of its processing. The callback reads the directory of the
+/// given spec and returns its entries, for example using `getdents64` on
+/// Linux or `NtQueryDirectoryFile` on Windows. Entries are created with
+/// [`DirEntry::new`](../struct.DirEntry.html#method.new) at one level below
+/// the spec's `depth`, and the directories among them are read next if
+/// their `read_children_path` is set. Errors are created with
+/// [`Error::from_path`](../struct.Error.html#method.from_path).
+///
+/// With `Parallelism::Serial` directories are read on the calling thread,
+/// otherwise they are read in parallel and handed out in order. If the
+/// rayon pool is too busy to start the walk, a single error is yielded.
+///
+/// ```no_run
+/// use std::fs;
+/// use std::sync::Arc;
+/// use jwalk::custom::{walk, ReadDir, ReadDirSpec};
+/// use jwalk::{DirEntry, Error, Operation, Parallelism};
+///
+/// let specs = vec![ReadDirSpec::<((), ())>::new(".", 0, ())];
+/// for read_dir in walk(specs, Parallelism::RayonNewPool(4), |spec| {
+///     let read_dir = fs::read_dir(&spec.path).map_err(|err| {
+///         Error::from_path(spec.depth, spec.path.to_path_buf(), Operation::ReadDir, err)
+///     })?;
+///     let entries = read_dir
+///         .map(|entry| {
+///             let entry = entry.map_err(|err| {
+///                 Error::from_path(spec.depth, spec.path.to_path_buf(), Operation::ReadDir, err)
+///             })?;
+///             let file_type = entry.file_type().map_err(|err| {
+///                 Error::from_path(spec.depth + 1, entry.path(), Operation::Metadata, err)
+///             })?;
+///             Ok(DirEntry::new(spec.depth + 1, entry.file_name(), file_type, spec.path.clone()))
+///         })
+///         .collect();
+///     Ok(ReadDir::new(spec.client_read_state, entries))
+/// }) {
+///     for dir_entry in read_dir?.results() {
+///         println!("{}", dir_entry.as_ref().unwrap().path().display());
+///     }
+/// }
+/// # Ok::<(), Error>(())
+/// ```
+pub fn walk<C, F>(
+    read_dir_specs: Vec<ReadDirSpec<C>>,
+    parallelism: Parallelism,
+    read_dir: F,
+) -> impl Iterator<Item = crate::Result<ReadDir<C>>>
+where
+    C: ClientState,
+    F: Fn(ReadDirSpec<C>) -> crate::Result<ReadDir<C>> + Send + Sync + 'static,
+{
+    let read_dir_iter = parallelism.thread_pool().ok().and_then(|thread_pool| {
+        ReadDirIter::try_new(
+            read_dir_specs,
+            parallelism,
+            thread_pool.as_ref(),
+            false,
+            QueueLimits::default(),
+            Arc::new(read_dir),
+        )
+    });
+    let busy = read_dir_iter.is_none().then(|| Err(Error::busy()));
+    busy.into_iter().chain(read_dir_iter.into_iter().flatten())
+}
@@ -2,25 +2,46 @@ use std::cmp::Ordering;
 
 use super::index_path::IndexPath;
 
+/// Value at a position in a tree, as passed through an
+/// [`OrderedQueue`](struct.OrderedQueue.html).
+///
+/// Items are ordered by priority, then by `index_path`, so that the greatest
+/// item, which a `BinaryHeap` pops first, comes first in depth first order.
+#[derive(Debug)]
 pub struct Ordered<T> {
+    /// The value at this position.
     pub value: T,
+    /// Position of the value in the tree.
     pub index_path: IndexPath,
     pub(crate) child_count: usize,
+    // items with higher priority are ordered first, regardless of index_path
+    pub(crate) priority: i32,
 }
 
 impl<T> Ordered<T> {
+    /// `value` at `index_path`, followed by `child_count` items at
+    /// `index_path.adding(0)` and on in strict order.
     pub fn new(value: T, index_path: IndexPath, child_count: usize) -> Ordered<T> {
         Ordered {
             value,
             index_path,
             child_count,
+            priority: 0,
         }
     }
+
+    /// Take this item before items of lower priority in relaxed order,
+    /// wherever it is in the tree. Defaults to `0`, and is ignored by
+    /// [`Strict`](enum.Ordering.html#variant.Strict) queues.
+    pub fn with_priority(mut self, priority: i32) -> Ordered<T> {
+        self.priority = priority;
+        self
+    }
 }
 
 impl<T> PartialEq for Ordered<T> {
     fn eq(&self, o: &Self) -> bool {
-        self.index_path.eq(&o.index_path)
+        self.priority == o.priority && self.index_path.eq(&o.index_path)
     }
 }
 
@@ -28,12 +49,14 @@ impl<T> Eq for Ordered<T> {}
 
 impl<T> PartialOrd for Ordered<T> {
     fn partial_cmp(&self, o: &Self) -> Option<Ordering> {
-        Some(self.index_path.cmp(&o.index_path))
+        Some(self.cmp(o))
     }
 }
 
 impl<T> Ord for Ordered<T> {
     fn cmp(&self, o: &Self) -> Ordering {
-        self.index_path.cmp(&o.index_path)
+        self.priority
+            .cmp(&o.priority)
+            .then_with(|| self.index_path.cmp(&o.index_path))
     }
 }
@@ -1,27 +1,50 @@
 //! Ordered queue backed by a channel.
 
-use crossbeam::channel::{self, Receiver, SendError, Sender, TryRecvError};
+use crossbeam::channel::{self, Receiver, Sender, TryRecvError};
 use std::collections::BinaryHeap;
+use std::mem;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::Arc;
 use std::thread;
 
 use super::*;
 
-pub(crate) struct OrderedQueue<T>
+/// Sending half of an ordered queue, see
+/// [`ordered_queue`](fn.ordered_queue.html). Clones send to the same
+/// queue.
+pub struct OrderedQueue<T>
 where
     T: Send,
 {
     sender: Sender<Ordered<T>>,
     pending_count: Arc<AtomicUsize>,
+    completed_count: Arc<AtomicUsize>,
     stop: Arc<AtomicBool>,
 }
 
+/// Counts of the items of a queue, readable while it is in use.
+pub(crate) struct QueueProgress {
+    pending_count: Arc<AtomicUsize>,
+    completed_count: Arc<AtomicUsize>,
+}
+
+/// Order in which an [`OrderedQueueIter`](struct.OrderedQueueIter.html)
+/// yields items.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Ordering {
+    /// Yield the earliest item received so far, by priority and then in
+    /// depth first order. The iterator ends once all items pushed were
+    /// completed with
+    /// [`complete_item`](struct.OrderedQueue.html#method.complete_item).
     Relaxed,
+    /// Yield items in depth first order, waiting for the next one if it
+    /// wasn't received yet. The iterator ends once the roots and all their
+    /// announced children were yielded.
     Strict,
 }
 
+/// Receiving half of an ordered queue, see
+/// [`ordered_queue`](fn.ordered_queue.html).
 pub struct OrderedQueueIter<T>
 where
     T: Send,
@@ -32,6 +55,18 @@ where
     receive_buffer: BinaryHeap<Ordered<T>>,
     pending_count: Arc<AtomicUsize>,
     ordered_matcher: OrderedMatcher,
+    // called when waiting for the next item starts and ends
+    on_starved: Option<Box<StarvedFunction>>,
+    is_starved: bool,
+    overflow: Option<Overflow<T>>,
+}
+
+type StarvedFunction = dyn Fn(bool) + Send + Sync;
+
+// Buffered items beyond `threshold` are moved to disk.
+struct Overflow<T> {
+    threshold: usize,
+    segments: Box<dyn SpillSegments<T>>,
 }
 
 struct OrderedMatcher {
@@ -39,6 +74,23 @@ struct OrderedMatcher {
     child_count_stack: Vec<usize>,
 }
 
+/// Create a queue passing the items of a tree produced in parallel, yielding
+/// them in the given `ordering`.
+///
+/// Items are pushed with the [`IndexPath`](struct.IndexPath.html) of their
+/// position in the tree. Use a [`Relaxed`](enum.Ordering.html#variant.Relaxed)
+/// queue for the work of producing items, and a
+/// [`Strict`](enum.Ordering.html#variant.Strict) one for the results, which
+/// yields them depth first however the work was spread over threads. The
+/// iterator blocks while the next item isn't available, and ends if all
+/// queues were dropped.
+pub fn ordered_queue<T>(ordering: Ordering) -> (OrderedQueue<T>, OrderedQueueIter<T>)
+where
+    T: Send,
+{
+    new_ordered_queue(Arc::new(AtomicBool::new(false)), ordering)
+}
+
 pub(crate) fn new_ordered_queue<T>(
     stop: Arc<AtomicBool>,
     ordering: Ordering,
@@ -52,6 +104,7 @@ where
         OrderedQueue {
             sender,
             pending_count: pending_count.clone(),
+            completed_count: Arc::new(AtomicUsize::new(0)),
             stop: stop.clone(),
         },
         OrderedQueueIter {
@@ -61,6 +114,9 @@ where
             receive_buffer: BinaryHeap::new(),
             pending_count,
             stop,
+            on_starved: None,
+            is_starved: false,
+            overflow: None,
         },
     )
 }
@@ -69,14 +125,38 @@ impl<T> OrderedQueue<T>
 where
     T: Send,
 {
-    pub fn push(&self, ordered: Ordered<T>) -> Result<(), SendError<Ordered<T>>> {
+    /// Push `ordered` to the queue, handing it back if the iterator was
+    /// dropped.
+    pub fn push(&self, ordered: Ordered<T>) -> Result<(), Ordered<T>> {
         self.pending_count.fetch_add(1, AtomicOrdering::SeqCst);
-        self.sender.send(ordered)
+        self.sender.send(ordered).map_err(|err| err.into_inner())
     }
 
+    /// Mark an item taken from the queue as done, after pushing the items
+    /// it produced. Relaxed iterators end once all items are completed.
     pub fn complete_item(&self) {
+        self.completed_count.fetch_add(1, AtomicOrdering::SeqCst);
         self.pending_count.fetch_sub(1, AtomicOrdering::SeqCst);
     }
+
+    pub(crate) fn progress(&self) -> QueueProgress {
+        QueueProgress {
+            pending_count: self.pending_count.clone(),
+            completed_count: self.completed_count.clone(),
+        }
+    }
+}
+
+impl QueueProgress {
+    /// Items pushed but not completed yet.
+    pub(crate) fn pending(&self) -> usize {
+        self.pending_count.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Items completed so far.
+    pub(crate) fn completed(&self) -> usize {
+        self.completed_count.load(AtomicOrdering::SeqCst)
+    }
 }
 
 impl<T> Clone for OrderedQueue<T>
@@ -87,6 +167,7 @@ where
         OrderedQueue {
             sender: self.sender.clone(),
             pending_count: self.pending_count.clone(),
+            completed_count: self.completed_count.clone(),
             stop: self.stop.clone(),
         }
     }
@@ -96,10 +177,67 @@ impl<T> OrderedQueueIter<T>
 where
     T: Send,
 {
+    /// Consider this queue exhausted once all items of `queue` are completed
+    /// instead of tracking our own pending items. Useful for result queues
+    /// that are consumed in relaxed order, as results are always sent before
+    /// the item producing them is completed.
+    pub(crate) fn complete_with<U: Send>(&mut self, queue: &OrderedQueue<U>) {
+        self.pending_count = queue.pending_count.clone();
+    }
+
+    /// Expect `count` top level items with index paths `[0]` to
+    /// `[count - 1]` instead of a single one, in strict order. With a count
+    /// of zero the iterator ends right away.
+    pub fn expect_roots(&mut self, count: usize) {
+        self.ordered_matcher = if count == 0 {
+            OrderedMatcher {
+                looking_for: IndexPath::new(vec![]),
+                child_count_stack: vec![],
+            }
+        } else {
+            OrderedMatcher {
+                looking_for: IndexPath::new(vec![0]),
+                child_count_stack: vec![count],
+            }
+        };
+    }
+
+    /// Move buffered items to disk once more than `threshold` of them are
+    /// waiting. Only items taken in relaxed order are spilled.
+    pub(crate) fn spill_to_disk(&mut self, threshold: usize)
+    where
+        T: Spill + 'static,
+    {
+        self.overflow = Some(Overflow {
+            threshold: threshold.max(1),
+            segments: Box::new(SpillFile::new()),
+        });
+    }
+
+    /// Call `on_starved` with `true` when `next()` has to wait for the next
+    /// item, and with `false` once it returns.
+    pub(crate) fn signal_starved(&mut self, on_starved: impl Fn(bool) + Send + Sync + 'static) {
+        self.on_starved = Some(Box::new(on_starved));
+    }
+
+    fn set_starved(&mut self, starved: bool) {
+        if self.is_starved != starved {
+            self.is_starved = starved;
+            if let Some(on_starved) = self.on_starved.as_ref() {
+                on_starved(starved);
+            }
+        }
+    }
+
     fn pending_count(&self) -> usize {
         self.pending_count.load(AtomicOrdering::SeqCst)
     }
 
+    // The next item in relaxed order if one is available right away.
+    pub(crate) fn try_next(&mut self) -> Option<Ordered<T>> {
+        self.try_next_relaxed().ok()
+    }
+
     fn is_stop(&self) -> bool {
         self.stop.load(AtomicOrdering::SeqCst)
     }
@@ -109,19 +247,58 @@ where
             return Err(TryRecvError::Disconnected);
         }
 
+        // Check before draining so items sent right before completion aren't missed.
+        let is_complete = self.pending_count() == 0;
+
         while let Ok(ordered_work) = self.receiver.try_recv() {
-            self.receive_buffer.push(ordered_work)
+            self.receive_buffer.push(ordered_work);
+            self.spill_overflow();
+        }
+
+        if self.receive_buffer.is_empty() {
+            self.unspill_overflow();
         }
 
         if let Some(ordered_work) = self.receive_buffer.pop() {
             Ok(ordered_work)
-        } else if self.pending_count() == 0 {
+        } else if is_complete {
             Err(TryRecvError::Disconnected)
         } else {
             Err(TryRecvError::Empty)
         }
     }
 
+    // Move the less urgent half of the buffer to disk once it's over the
+    // threshold. Items stay in memory if they can't be written.
+    fn spill_overflow(&mut self) {
+        let overflow = match self.overflow.as_mut() {
+            Some(overflow) if self.receive_buffer.len() > overflow.threshold => overflow,
+            _ => return,
+        };
+        // sorted from least to most urgent
+        let mut items = mem::take(&mut self.receive_buffer).into_sorted_vec();
+        let keep = items.split_off(items.len() / 2);
+        self.receive_buffer = keep.into();
+        if let Err(items) = overflow.segments.push_segment(items) {
+            self.receive_buffer.extend(items);
+            self.overflow = None;
+        }
+    }
+
+    // Refill the buffer with the segment spilled last. Stops the walk if it
+    // can't be read back, as its items would be missing otherwise.
+    fn unspill_overflow(&mut self) {
+        let overflow = match self.overflow.as_mut() {
+            Some(overflow) => overflow,
+            None => return,
+        };
+        match overflow.segments.pop_segment() {
+            Ok(Some(items)) => self.receive_buffer.extend(items),
+            Ok(None) => {}
+            Err(_) => self.stop.store(true, AtomicOrdering::SeqCst),
+        }
+    }
+
     fn try_next_strict(&mut self) -> Result<Ordered<T>, TryRecvError> {
         let looking_for = &self.ordered_matcher.looking_for;
 
@@ -142,17 +319,23 @@ where
             }
 
             match self.receiver.try_recv() {
-                Ok(ordered) => {
+                Ok(mut ordered) => {
+                    // Strict order goes by `index_path` alone
+                    ordered.priority = 0;
                     self.receive_buffer.push(ordered);
                 }
                 Err(err) => match err {
-                    TryRecvError::Empty => thread::yield_now(),
+                    TryRecvError::Empty => return Err(TryRecvError::Empty),
                     TryRecvError::Disconnected => break,
                 },
             }
         }
 
-        let ordered = self.receive_buffer.pop().unwrap();
+        // All queues were dropped before the next item was pushed
+        let ordered = self
+            .receive_buffer
+            .pop()
+            .ok_or(TryRecvError::Disconnected)?;
         self.ordered_matcher.advance_past(&ordered);
         Ok(ordered)
     }
@@ -171,11 +354,18 @@ where
             };
             match try_next {
                 Ok(next) => {
+                    self.set_starved(false);
                     return Some(next);
                 }
                 Err(err) => match err {
-                    TryRecvError::Empty => thread::yield_now(),
-                    TryRecvError::Disconnected => return None,
+                    TryRecvError::Empty => {
+                        self.set_starved(true);
+                        thread::yield_now()
+                    }
+                    TryRecvError::Disconnected => {
+                        self.set_starved(false);
+                        return None;
+                    }
                 },
             }
         }
@@ -0,0 +1,143 @@
+use rayon::iter::plumbing::UnindexedConsumer;
+use rayon::iter::walk_tree_prefix;
+use std::sync::mpsc::{SendError, Sender};
+
+use super::*;
+use crate::Result;
+
+/// DirEntry parallel iterator from `WalkDir.into_par_iter()`.
+///
+/// Reads directories as rayon tasks and hands their entries straight to the
+/// consumer.
+pub struct ParDirEntryIter<C: ClientState> {
+    root_entry_results: Vec<Result<DirEntry<C>>>,
+    parallelism: Parallelism,
+    min_depth: usize,
+    root_read_dir_state: C::ReadDirState,
+    errors_to: Option<Sender<Error>>,
+    core_read_dir_callback: Arc<ReadDirCallback<C>>,
+}
+
+impl<C: ClientState> ParDirEntryIter<C> {
+    pub(crate) fn new(
+        root_entry_results: Vec<Result<DirEntry<C>>>,
+        parallelism: Parallelism,
+        min_depth: usize,
+        root_read_dir_state: C::ReadDirState,
+        errors_to: Option<Sender<Error>>,
+        core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    ) -> ParDirEntryIter<C> {
+        ParDirEntryIter {
+            root_entry_results,
+            parallelism,
+            min_depth,
+            root_read_dir_state,
+            errors_to,
+            core_read_dir_callback,
+        }
+    }
+}
+
+// A directory entry with the state its children are read with and the open
+// directory it was listed from.
+type Node<C> = (
+    Result<DirEntry<C>>,
+    <C as ClientState>::ReadDirState,
+    Option<Arc<DirFd>>,
+);
+
+// Read the contents of the directory `dir_entry_result` points to, pairing
+// each result with the state its own children are read with.
+fn read_children<C: ClientState>(
+    (dir_entry_result, read_dir_state, parent_dir): &Node<C>,
+    core_read_dir_callback: &ReadDirCallback<C>,
+) -> Vec<Node<C>> {
+    let read_dir_spec = match dir_entry_result {
+        Ok(dir_entry) => dir_entry
+            .read_children_spec(read_dir_state)
+            .map(|mut read_dir_spec| {
+                // Targets of followed links are opened by path
+                if !dir_entry.path_is_symlink() {
+                    read_dir_spec.parent_dir = parent_dir.clone();
+                }
+                read_dir_spec
+            }),
+        Err(_) => None,
+    };
+    match read_dir_spec.map(core_read_dir_callback) {
+        None => Vec::new(),
+        Some(Ok(read_dir)) => {
+            let ReadDir {
+                read_dir_state,
+                results_list,
+                dir_fd,
+                ..
+            } = read_dir;
+            results_list
+                .into_iter()
+                .map(|result| (result, read_dir_state.clone(), dir_fd.clone()))
+                .collect()
+        }
+        Some(Err(err)) => vec![(Err(err), read_dir_state.clone(), None)],
+    }
+}
+
+impl<C: ClientState> ParallelIterator for ParDirEntryIter<C> {
+    type Item = Result<DirEntry<C>>;
+
+    fn drive_unindexed<U>(self, consumer: U) -> U::Result
+    where
+        U: UnindexedConsumer<Self::Item>,
+    {
+        let ParDirEntryIter {
+            root_entry_results,
+            parallelism,
+            min_depth,
+            root_read_dir_state,
+            errors_to,
+            core_read_dir_callback,
+        } = self;
+
+        let thread_pool = match parallelism.thread_pool() {
+            Ok(thread_pool) => thread_pool,
+            Err(err) => {
+                return rayon::iter::once(err)
+                    .filter_map(move |err| send_error(errors_to.as_ref(), err))
+                    .map(Err)
+                    .drive_unindexed(consumer)
+            }
+        };
+        let roots: Vec<_> = root_entry_results
+            .into_iter()
+            .map(|result| (result, root_read_dir_state.clone(), None))
+            .collect();
+
+        let walk = move |consumer: U| {
+            roots
+                .into_par_iter()
+                .flat_map(|root| {
+                    walk_tree_prefix(root, |node| {
+                        read_children(node, core_read_dir_callback.as_ref())
+                    })
+                })
+                .filter_map(move |(result, _, _)| match result {
+                    Ok(dir_entry) if dir_entry.depth < min_depth || dir_entry.is_filtered => None,
+                    Ok(dir_entry) => Some(Ok(dir_entry)),
+                    Err(err) => send_error(errors_to.as_ref(), err).map(Err),
+                })
+                .drive_unindexed(consumer)
+        };
+        match thread_pool {
+            Some(thread_pool) => thread_pool.install(|| walk(consumer)),
+            None => walk(consumer),
+        }
+    }
+}
+
+// Send `err` to `errors_to` if set, returning it if it wasn't sent.
+fn send_error(errors_to: Option<&Sender<Error>>, err: Error) -> Option<Error> {
+    match errors_to {
+        Some(errors_to) => errors_to.send(err).err().map(|SendError(err)| err),
+        None => Some(err),
+    }
+}
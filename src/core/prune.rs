@@ -0,0 +1,72 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::path::{Component, Path, PathBuf};
+
+/// Paths relative to the root and file names whose entries are skipped
+/// together with their contents.
+///
+/// Paths are kept in a trie of their components, so each directory looks
+/// up its own node once and then checks its entries by name.
+pub(crate) struct Pruner {
+    names: HashSet<OsString>,
+    paths: PathNode,
+}
+
+/// The pruned names and paths below one directory.
+pub(crate) struct PrunedDir<'a> {
+    names: &'a HashSet<OsString>,
+    paths: Option<&'a PathNode>,
+}
+
+#[derive(Default)]
+struct PathNode {
+    pruned: bool,
+    children: HashMap<OsString, PathNode>,
+}
+
+impl Pruner {
+    /// `paths` starting with `root` are taken relative to it.
+    pub(crate) fn new(root: &Path, paths: &[PathBuf], names: &[OsString]) -> Pruner {
+        let mut root_node = PathNode::default();
+        for path in paths {
+            let path = path.strip_prefix(root).unwrap_or(path);
+            let mut node = &mut root_node;
+            for component in path.components() {
+                if let Component::Normal(name) = component {
+                    node = node.children.entry(name.to_owned()).or_default();
+                }
+            }
+            // The root itself is never pruned, so an empty path has no effect.
+            node.pruned = true;
+        }
+        Pruner {
+            names: names.iter().cloned().collect(),
+            paths: root_node,
+        }
+    }
+
+    /// The pruned entries of the directory at `relative_path` below the root.
+    pub(crate) fn dir(&self, relative_path: &Path) -> PrunedDir<'_> {
+        let paths = relative_path
+            .components()
+            .try_fold(&self.paths, |node, component| match component {
+                Component::Normal(name) => node.children.get(name),
+                _ => Some(node),
+            });
+        PrunedDir {
+            names: &self.names,
+            paths,
+        }
+    }
+}
+
+impl PrunedDir<'_> {
+    /// Whether the entry called `name` is skipped.
+    pub(crate) fn prunes(&self, name: &OsStr) -> bool {
+        self.names.contains(name)
+            || self
+                .paths
+                .and_then(|node| node.children.get(name))
+                .is_some_and(|node| node.pruned)
+    }
+}
@@ -1,14 +1,56 @@
-use super::{ClientState, DirEntry, IndexPath, Ordered, ReadDirSpec};
-use crate::Result;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::{ClientState, DirEntry, DirFd, IndexPath, Ordered, ReadDirSpec};
+use crate::{Error, Result};
 
 /// Results of successfully reading a directory.
 #[derive(Debug)]
 pub struct ReadDir<C: ClientState> {
     pub(crate) read_dir_state: C::ReadDirState,
     pub(crate) results_list: Vec<Result<DirEntry<C>>>,
+    pub(crate) snapshot: Option<ReadDirSnapshot>,
+    // True if reading stopped at `max_entries_per_dir`.
+    pub(crate) truncated: bool,
+    // The directory kept open to open its subdirectories relative to it.
+    pub(crate) dir_fd: Option<Arc<DirFd>>,
+}
+
+/// Modification time of a directory when it was read.
+#[derive(Debug)]
+pub(crate) struct ReadDirSnapshot {
+    depth: usize,
+    path: Arc<Path>,
+    modified: SystemTime,
+}
+
+impl ReadDirSnapshot {
+    pub(crate) fn take(depth: usize, path: Arc<Path>, read_dir_path: &Path) -> Option<Self> {
+        let modified = fs::metadata(read_dir_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        Some(ReadDirSnapshot {
+            depth,
+            path,
+            modified,
+        })
+    }
+
+    // Error if the directory was modified or removed since the snapshot.
+    pub(crate) fn check(&self) -> Result<()> {
+        let modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified());
+        match modified {
+            Ok(modified) if modified == self.modified => Ok(()),
+            _ => Err(Error::from_change(self.depth, &self.path)),
+        }
+    }
 }
 
 impl<C: ClientState> ReadDir<C> {
+    /// Contents of a directory, with the state its subdirectories are read
+    /// with.
     pub fn new(
         read_dir_state: C::ReadDirState,
         results_list: Vec<Result<DirEntry<C>>>,
@@ -16,24 +58,60 @@ impl<C: ClientState> ReadDir<C> {
         ReadDir {
             read_dir_state,
             results_list,
+            snapshot: None,
+            truncated: false,
+            dir_fd: None,
         }
     }
 
+    /// Entries of the directory, in the order they were read.
+    pub fn results(&self) -> &[Result<DirEntry<C>>] {
+        &self.results_list
+    }
+
+    /// Take the entries of the directory.
+    pub fn into_results(self) -> Vec<Result<DirEntry<C>>> {
+        self.results_list
+    }
+
+    pub(crate) fn with_snapshot(mut self, snapshot: Option<ReadDirSnapshot>) -> ReadDir<C> {
+        self.snapshot = snapshot;
+        self
+    }
+
+    pub(crate) fn with_truncated(mut self, truncated: bool) -> ReadDir<C> {
+        self.truncated = truncated;
+        self
+    }
+
+    pub(crate) fn with_dir_fd(mut self, dir_fd: Option<Arc<DirFd>>) -> ReadDir<C> {
+        self.dir_fd = dir_fd;
+        self
+    }
+
+    /// Specs for reading the subdirectories of this directory.
     pub fn read_children_specs(&self) -> impl Iterator<Item = ReadDirSpec<C>> + '_ {
         self.results_list.iter().filter_map(move |each| {
-            each.as_ref()
-                .ok()?
-                .read_children_spec(self.read_dir_state.clone())
+            let dir_entry = each.as_ref().ok()?;
+            let mut spec = dir_entry.read_children_spec(&self.read_dir_state)?;
+            // Targets of followed links are opened by path
+            if !dir_entry.path_is_symlink() {
+                spec.parent_dir = self.dir_fd.clone();
+            }
+            Some(spec)
         })
     }
 
-    pub fn ordered_read_children_specs(
+    pub(crate) fn ordered_read_children_specs(
         &self,
         index_path: &IndexPath,
     ) -> Vec<Ordered<ReadDirSpec<C>>> {
         self.read_children_specs()
             .enumerate()
-            .map(|(i, spec)| Ordered::new(spec, index_path.adding(i), 0))
+            .map(|(i, spec)| {
+                let priority = spec.priority;
+                Ordered::new(spec, index_path.adding(i), 0).with_priority(priority)
+            })
             .collect()
     }
 }
@@ -1,3 +1,4 @@
+use rayon::ThreadPool;
 use std::sync::Arc;
 
 use super::*;
@@ -7,6 +8,13 @@ use crate::Result;
 pub(crate) type ReadDirCallback<C> =
     dyn Fn(ReadDirSpec<C>) -> Result<ReadDir<C>> + Send + Sync + 'static;
 
+/// Limits on the queues of a parallel walk.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct QueueLimits {
+    pub(crate) max_pending_entries: Option<usize>,
+    pub(crate) spill_threshold: Option<usize>,
+}
+
 /// Result<ReadDir> Iterator.
 ///
 /// Yields ReadDirs (results of fs::read_dir) in order required for recursive
@@ -16,9 +24,12 @@ pub enum ReadDirIter<C: ClientState> {
     Walk {
         read_dir_spec_stack: Vec<ReadDirSpec<C>>,
         core_read_dir_callback: Arc<ReadDirCallback<C>>,
+        completed_dirs: usize,
     },
     ParWalk {
         read_dir_result_iter: OrderedQueueIter<Result<ReadDir<C>>>,
+        dispatcher: Arc<Dispatcher<C>>,
+        progress: QueueProgress,
     },
 }
 
@@ -26,23 +37,43 @@ impl<C: ClientState> ReadDirIter<C> {
     pub(crate) fn try_new(
         read_dir_specs: Vec<ReadDirSpec<C>>,
         parallelism: Parallelism,
+        thread_pool: Option<&Arc<ThreadPool>>,
+        relaxed_ordering: bool,
+        queue_limits: QueueLimits,
         core_read_dir_callback: Arc<ReadDirCallback<C>>,
     ) -> Option<Self> {
         if let Parallelism::Serial = parallelism {
+            // Specs are popped from the end of the stack
+            let mut read_dir_spec_stack = read_dir_specs;
+            read_dir_spec_stack.reverse();
             ReadDirIter::Walk {
-                read_dir_spec_stack: read_dir_specs,
+                read_dir_spec_stack,
                 core_read_dir_callback,
+                completed_dirs: 0,
             }
         } else {
             let stop = Arc::new(AtomicBool::new(false));
-            let read_dir_result_queue = new_ordered_queue(stop.clone(), Ordering::Strict);
-            let (read_dir_result_queue, read_dir_result_iter) = read_dir_result_queue;
+            let result_ordering = if relaxed_ordering {
+                Ordering::Relaxed
+            } else {
+                Ordering::Strict
+            };
+            let read_dir_result_queue = new_ordered_queue(stop.clone(), result_ordering);
+            let (read_dir_result_queue, mut read_dir_result_iter) = read_dir_result_queue;
             let read_dir_spec_queue = new_ordered_queue(stop.clone(), Ordering::Relaxed);
-            let (read_dir_spec_queue, read_dir_spec_iter) = read_dir_spec_queue;
+            let (read_dir_spec_queue, mut read_dir_spec_iter) = read_dir_spec_queue;
+            if let Some(threshold) = queue_limits.spill_threshold {
+                read_dir_spec_iter.spill_to_disk(threshold);
+            }
+            if relaxed_ordering {
+                read_dir_result_iter.complete_with(&read_dir_spec_queue);
+            }
+            let progress = read_dir_spec_queue.progress();
 
+            read_dir_result_iter.expect_roots(read_dir_specs.len());
             for (i, read_dir_spec) in read_dir_specs.into_iter().enumerate() {
                 read_dir_spec_queue
-                    .push(Ordered::new(read_dir_spec, IndexPath::new(vec![0]), i))
+                    .push(Ordered::new(read_dir_spec, IndexPath::new(vec![i]), 0))
                     .unwrap();
             }
 
@@ -51,7 +82,19 @@ impl<C: ClientState> ReadDirIter<C> {
                 read_dir_spec_queue,
                 read_dir_result_queue,
                 core_read_dir_callback,
+                backpressure: Arc::new(Backpressure::new(queue_limits.max_pending_entries)),
             };
+            let dispatcher = Arc::new(Dispatcher::new(
+                run_context,
+                parallelism.clone(),
+                thread_pool.cloned(),
+                read_dir_spec_iter,
+            ));
+            let starved_dispatcher = dispatcher.clone();
+            read_dir_result_iter.signal_starved(move |starved| {
+                starved_dispatcher.backpressure().set_starved(starved);
+                starved_dispatcher.dispatch();
+            });
 
             let (startup_tx, startup_rx) = parallelism
                 .timeout()
@@ -60,31 +103,78 @@ impl<C: ClientState> ReadDirIter<C> {
                     (Some(tx), Some((rx, duration)))
                 })
                 .unwrap_or((None, None));
-            parallelism.spawn(move || {
+            let startup_dispatcher = dispatcher.clone();
+            parallelism.spawn(thread_pool.map(|thread_pool| &**thread_pool), move || {
                 if let Some(tx) = startup_tx {
                     if tx.send(()).is_err() {
                         // rayon didn't install this function in time so the listener exited. Do the same.
                         return;
                     }
                 }
-                read_dir_spec_iter.par_bridge().for_each_with(
-                    run_context,
-                    |run_context, ordered_read_dir_spec| {
-                        multi_threaded_walk_dir(ordered_read_dir_spec, run_context);
-                    },
-                );
+                startup_dispatcher.dispatch();
             });
-            if startup_rx.map_or(false, |(rx, duration)| rx.recv_timeout(duration).is_err()) {
+            if startup_rx.is_some_and(|(rx, duration)| rx.recv_timeout(duration).is_err()) {
                 return None;
             }
             ReadDirIter::ParWalk {
                 read_dir_result_iter,
+                dispatcher,
+                progress,
             }
         }
         .into()
     }
 }
 
+impl<C: ClientState> ReadDirIter<C> {
+    // Hold workers back before they read another directory, or let them
+    // continue. Serial walks only read when asked for the next ReadDir.
+    pub(crate) fn set_paused(&self, paused: bool) {
+        if let ReadDirIter::ParWalk { dispatcher, .. } = self {
+            dispatcher.backpressure().set_paused(paused);
+            dispatcher.dispatch();
+        }
+    }
+
+    // Directories scheduled but not read yet, including those being read.
+    pub(crate) fn pending_dirs(&self) -> usize {
+        match self {
+            ReadDirIter::Walk {
+                read_dir_spec_stack,
+                ..
+            } => read_dir_spec_stack.len(),
+            ReadDirIter::ParWalk { progress, .. } => progress.pending(),
+        }
+    }
+
+    // Directories read so far, which can be ahead of the yielded entries.
+    pub(crate) fn completed_dirs(&self) -> usize {
+        match self {
+            ReadDirIter::Walk { completed_dirs, .. } => *completed_dirs,
+            ReadDirIter::ParWalk { progress, .. } => progress.completed(),
+        }
+    }
+
+    // Drop the next ReadDir along with the reads of all directories below it.
+    pub(crate) fn skip_next(&mut self) {
+        match self {
+            ReadDirIter::Walk {
+                read_dir_spec_stack,
+                ..
+            } => {
+                read_dir_spec_stack.pop();
+            }
+            ReadDirIter::ParWalk { .. } => {
+                if let Some(Ok(read_dir)) = self.next() {
+                    for _ in read_dir.read_children_specs() {
+                        self.skip_next();
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl<C: ClientState> Iterator for ReadDirIter<C> {
     type Item = Result<ReadDir<C>>;
     fn next(&mut self) -> Option<Self::Item> {
@@ -92,9 +182,11 @@ impl<C: ClientState> Iterator for ReadDirIter<C> {
             ReadDirIter::Walk {
                 read_dir_spec_stack,
                 core_read_dir_callback,
+                completed_dirs,
             } => {
                 let read_dir_spec = read_dir_spec_stack.pop()?;
                 let read_dir_result = core_read_dir_callback(read_dir_spec);
+                *completed_dirs += 1;
 
                 if let Ok(read_dir) = read_dir_result.as_ref() {
                     for each_spec in read_dir
@@ -112,16 +204,28 @@ impl<C: ClientState> Iterator for ReadDirIter<C> {
 
             ReadDirIter::ParWalk {
                 read_dir_result_iter,
-            } => read_dir_result_iter
-                .next()
-                .map(|read_dir_result| read_dir_result.value),
+                dispatcher,
+                ..
+            } => {
+                let read_dir_result = read_dir_result_iter.next()?.value;
+                if let Ok(read_dir) = read_dir_result.as_ref() {
+                    if dispatcher.backpressure().is_bounded() {
+                        // Consuming entries makes room for reading more.
+                        dispatcher
+                            .backpressure()
+                            .remove_pending(read_dir.results_list.len());
+                        dispatcher.dispatch();
+                    }
+                }
+                Some(read_dir_result)
+            }
         }
     }
 }
 
-fn multi_threaded_walk_dir<C: ClientState>(
+pub(crate) fn multi_threaded_walk_dir<C: ClientState>(
     ordered_read_dir_spec: Ordered<ReadDirSpec<C>>,
-    run_context: &mut RunContext<C>,
+    run_context: &RunContext<C>,
 ) {
     let Ordered {
         value: read_dir_spec,
@@ -1,7 +1,10 @@
 use std::path::Path;
 use std::sync::Arc;
 
-use crate::ClientState;
+use super::link_ancestor::{DirId, LinkAncestor};
+use super::spill::*;
+use super::DirFd;
+use crate::{ClientState, DirEntry};
 
 /// Specification for reading a directory.
 ///
@@ -22,6 +25,117 @@ pub struct ReadDirSpec<C: ClientState> {
     /// when reading this directory's parent. One intended use case is to store
     /// `.gitignore` state to filter entries during the walk.
     pub client_read_state: C::ReadDirState,
+    /// Directories with higher priority are read first by parallel walks.
+    pub priority: i32,
     // Origins of symlinks followed to get to this entry.
-    pub(crate) follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+    pub(crate) follow_link_ancestors: Arc<Vec<LinkAncestor>>,
+    // Paths of the symlinks followed to get to this directory.
+    pub(crate) symlink_chain: Arc<Vec<Arc<Path>>>,
+    // Parent directory to open this one relative to, with `Backend::DirFd`.
+    pub(crate) parent_dir: Option<Arc<DirFd>>,
+    // Entry of this directory, for `process_read_dir_with_parent`.
+    pub(crate) parent: Option<Box<DirEntry<C>>>,
+}
+
+impl<C: ClientState> ReadDirSpec<C> {
+    /// Spec for reading the directory at `path`, whose entries will be at
+    /// `depth + 1`. Use depth `0` for the root of a walk.
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        depth: usize,
+        client_read_state: C::ReadDirState,
+    ) -> ReadDirSpec<C> {
+        ReadDirSpec {
+            depth,
+            path: Arc::from(path.as_ref()),
+            client_read_state,
+            priority: 0,
+            follow_link_ancestors: Default::default(),
+            symlink_chain: Default::default(),
+            parent_dir: None,
+            parent: None,
+        }
+    }
+}
+
+// Everything but the client state, the parent entry and the parent directory
+// is written to disk, spilled directories are opened by path.
+impl<C: ClientState> Spill for ReadDirSpec<C> {
+    type Residue = (C::ReadDirState, Option<Box<DirEntry<C>>>);
+
+    fn spill(self, buf: &mut Vec<u8>) -> Self::Residue {
+        write_usize(buf, self.depth);
+        write_path(buf, &self.path);
+        write_i32(buf, self.priority);
+        write_link_ancestors(buf, &self.follow_link_ancestors);
+        write_paths(buf, &self.symlink_chain);
+        (self.client_read_state, self.parent)
+    }
+
+    fn unspill(buf: &mut &[u8], (client_read_state, parent): Self::Residue) -> ReadDirSpec<C> {
+        let depth = read_usize(buf);
+        let path = read_path(buf);
+        let priority = read_i32(buf);
+        let follow_link_ancestors = read_link_ancestors(buf);
+        let symlink_chain = read_paths(buf);
+        ReadDirSpec {
+            depth,
+            path,
+            client_read_state,
+            priority,
+            follow_link_ancestors,
+            symlink_chain,
+            parent_dir: None,
+            parent,
+        }
+    }
+}
+
+fn write_link_ancestors(buf: &mut Vec<u8>, ancestors: &[LinkAncestor]) {
+    write_usize(buf, ancestors.len());
+    for ancestor in ancestors {
+        write_path(buf, &ancestor.path);
+        match &ancestor.id {
+            None => write_usize(buf, 0),
+            #[cfg(unix)]
+            Some(DirId::DevIno(dev, ino)) => {
+                write_usize(buf, 1);
+                write_u64(buf, *dev);
+                write_u64(buf, *ino);
+            }
+            Some(DirId::Canonical(path)) => {
+                write_usize(buf, 2);
+                write_path(buf, path);
+            }
+        }
+    }
+}
+
+fn read_link_ancestors(buf: &mut &[u8]) -> Arc<Vec<LinkAncestor>> {
+    let count = read_usize(buf);
+    let ancestors = (0..count)
+        .map(|_| {
+            let path = read_path(buf);
+            let id = match read_usize(buf) {
+                #[cfg(unix)]
+                1 => Some(DirId::DevIno(read_u64(buf), read_u64(buf))),
+                2 => Some(DirId::Canonical(read_path(buf).to_path_buf())),
+                _ => None,
+            };
+            LinkAncestor { path, id }
+        })
+        .collect();
+    Arc::new(ancestors)
+}
+
+fn write_paths(buf: &mut Vec<u8>, paths: &[Arc<Path>]) {
+    write_usize(buf, paths.len());
+    for path in paths {
+        write_path(buf, path);
+    }
+}
+
+fn read_paths(buf: &mut &[u8]) -> Arc<Vec<Arc<Path>>> {
+    let count = read_usize(buf);
+    Arc::new((0..count).map(|_| read_path(buf)).collect())
 }
@@ -1,7 +1,9 @@
 use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::sync::Arc;
 
-use super::{ClientState, Ordered, OrderedQueue, ReadDir, ReadDirCallback, ReadDirSpec};
+use super::{
+    Backpressure, ClientState, Ordered, OrderedQueue, ReadDir, ReadDirCallback, ReadDirSpec,
+};
 use crate::Result;
 
 pub(crate) struct RunContext<C: ClientState> {
@@ -9,6 +11,7 @@ pub(crate) struct RunContext<C: ClientState> {
     pub(crate) read_dir_spec_queue: OrderedQueue<ReadDirSpec<C>>,
     pub(crate) read_dir_result_queue: OrderedQueue<Result<ReadDir<C>>>,
     pub(crate) core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    pub(crate) backpressure: Arc<Backpressure>,
 }
 
 impl<C: ClientState> RunContext<C> {
@@ -16,6 +19,10 @@ impl<C: ClientState> RunContext<C> {
         self.stop.store(true, AtomicOrdering::SeqCst);
     }
 
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stop.load(AtomicOrdering::SeqCst)
+    }
+
     pub(crate) fn schedule_read_dir_spec(&self, ordered_read_dir: Ordered<ReadDirSpec<C>>) -> bool {
         self.read_dir_spec_queue.push(ordered_read_dir).is_ok()
     }
@@ -24,6 +31,9 @@ impl<C: ClientState> RunContext<C> {
         &self,
         read_dir_result: Ordered<Result<ReadDir<C>>>,
     ) -> bool {
+        if let Ok(read_dir) = read_dir_result.value.as_ref() {
+            self.backpressure.add_pending(read_dir.results_list.len());
+        }
         self.read_dir_result_queue.push(read_dir_result).is_ok()
     }
 
@@ -39,6 +49,7 @@ impl<C: ClientState> Clone for RunContext<C> {
             read_dir_spec_queue: self.read_dir_spec_queue.clone(),
             read_dir_result_queue: self.read_dir_result_queue.clone(),
             core_read_dir_callback: self.core_read_dir_callback.clone(),
+            backpressure: self.backpressure.clone(),
         }
     }
 }
@@ -0,0 +1,225 @@
+//! Disk-backed overflow for ordered queues.
+
+use std::borrow::Cow;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+use super::{IndexPath, Ordered};
+
+/// Values that can be moved to disk while waiting in a queue.
+pub(crate) trait Spill: Sized {
+    /// The part of the value that can't be encoded and stays in memory.
+    type Residue: Send;
+
+    fn spill(self, buf: &mut Vec<u8>) -> Self::Residue;
+    fn unspill(buf: &mut &[u8], residue: Self::Residue) -> Self;
+}
+
+/// Segments of queued items that were written to disk.
+///
+/// Segments are read back last in first out, which keeps the file from
+/// growing beyond the largest amount of items spilled at once.
+pub(crate) trait SpillSegments<T>: Send {
+    /// Write `items` to disk, handing them back if that fails.
+    fn push_segment(&mut self, items: Vec<Ordered<T>>) -> Result<(), Vec<Ordered<T>>>;
+    fn pop_segment(&mut self) -> io::Result<Option<Vec<Ordered<T>>>>;
+}
+
+/// Temporary file holding spilled segments, removed when dropped.
+pub(crate) struct SpillFile<T: Spill> {
+    file: Option<(PathBuf, File)>,
+    len: u64,
+    // start of each segment in the file along with the residues of its items
+    segments: Vec<(u64, Vec<T::Residue>)>,
+}
+
+static SPILL_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+impl<T: Spill> SpillFile<T> {
+    pub(crate) fn new() -> SpillFile<T> {
+        SpillFile {
+            file: None,
+            len: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    // The file is only created once something is spilled.
+    fn file(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            let path = env::temp_dir().join(format!(
+                "jwalk-spill-{}-{}",
+                process::id(),
+                SPILL_FILE_COUNT.fetch_add(1, AtomicOrdering::SeqCst)
+            ));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            self.file = Some((path, file));
+        }
+        Ok(&mut self.file.as_mut().unwrap().1)
+    }
+}
+
+impl<T: Spill + Send> SpillSegments<T> for SpillFile<T> {
+    fn push_segment(&mut self, items: Vec<Ordered<T>>) -> Result<(), Vec<Ordered<T>>> {
+        let mut buf = Vec::new();
+        let mut residues = Vec::with_capacity(items.len());
+        for ordered in items {
+            write_usize(&mut buf, ordered.index_path.indices.len());
+            for index in ordered.index_path.indices.iter() {
+                write_usize(&mut buf, *index);
+            }
+            write_usize(&mut buf, ordered.child_count);
+            write_i32(&mut buf, ordered.priority);
+            residues.push(ordered.value.spill(&mut buf));
+        }
+
+        let start = self.len;
+        let written = self.file().and_then(|file| {
+            file.seek(SeekFrom::Start(start))?;
+            file.write_all(&buf)
+        });
+        if written.is_err() {
+            return Err(decode_segment(&buf, residues));
+        }
+        self.len += buf.len() as u64;
+        self.segments.push((start, residues));
+        Ok(())
+    }
+
+    fn pop_segment(&mut self) -> io::Result<Option<Vec<Ordered<T>>>> {
+        let (start, residues) = match self.segments.pop() {
+            Some(segment) => segment,
+            None => return Ok(None),
+        };
+        let mut buf = vec![0; (self.len - start) as usize];
+        let file = self.file()?;
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buf)?;
+        file.set_len(start)?;
+        self.len = start;
+        Ok(Some(decode_segment(&buf, residues)))
+    }
+}
+
+fn decode_segment<T: Spill>(mut buf: &[u8], residues: Vec<T::Residue>) -> Vec<Ordered<T>> {
+    residues
+        .into_iter()
+        .map(|residue| {
+            let depth = read_usize(&mut buf);
+            let indices = (0..depth).map(|_| read_usize(&mut buf)).collect();
+            let child_count = read_usize(&mut buf);
+            let priority = read_i32(&mut buf);
+            let value = T::unspill(&mut buf, residue);
+            Ordered::new(value, IndexPath::new(indices), child_count).with_priority(priority)
+        })
+        .collect()
+}
+
+impl<T: Spill> Drop for SpillFile<T> {
+    fn drop(&mut self) {
+        if let Some((path, _)) = self.file.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+pub(crate) fn write_usize(buf: &mut Vec<u8>, value: usize) {
+    buf.extend_from_slice(&(value as u64).to_le_bytes());
+}
+
+pub(crate) fn read_usize(buf: &mut &[u8]) -> usize {
+    u64::from_le_bytes(take_bytes(buf)) as usize
+}
+
+#[cfg(unix)]
+pub(crate) fn write_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(unix)]
+pub(crate) fn read_u64(buf: &mut &[u8]) -> u64 {
+    u64::from_le_bytes(take_bytes(buf))
+}
+
+pub(crate) fn write_i32(buf: &mut Vec<u8>, value: i32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn read_i32(buf: &mut &[u8]) -> i32 {
+    i32::from_le_bytes(take_bytes(buf))
+}
+
+pub(crate) fn write_path(buf: &mut Vec<u8>, path: &Path) {
+    let bytes = path_to_bytes(path);
+    write_usize(buf, bytes.len());
+    buf.extend_from_slice(&bytes);
+}
+
+pub(crate) fn read_path(buf: &mut &[u8]) -> Arc<Path> {
+    let len = read_usize(buf);
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Arc::from(PathBuf::from(path_from_bytes(bytes)))
+}
+
+fn take_bytes<const N: usize>(buf: &mut &[u8]) -> [u8; N] {
+    let (bytes, rest) = buf.split_at(N);
+    *buf = rest;
+    bytes.try_into().unwrap()
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::OsStr::from_bytes(bytes).to_owned()
+}
+
+#[cfg(windows)]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::windows::ffi::OsStrExt;
+    Cow::Owned(
+        path.as_os_str()
+            .encode_wide()
+            .flat_map(u16::to_le_bytes)
+            .collect(),
+    )
+}
+
+#[cfg(windows)]
+fn path_from_bytes(bytes: &[u8]) -> OsString {
+    use std::os::windows::ffi::OsStringExt;
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    OsString::from_wide(&wide)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn path_from_bytes(bytes: &[u8]) -> OsString {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use super::*;
+use crate::Result;
+
+/// Iterator returned by [`DirEntryIter::take_until`].
+///
+/// Yields entries until it reaches the entry at its path.
+pub struct TakeUntil<C: ClientState> {
+    iter: DirEntryIter<C>,
+    path: PathBuf,
+    reached: bool,
+}
+
+impl<C: ClientState> TakeUntil<C> {
+    pub(crate) fn new(iter: DirEntryIter<C>, path: PathBuf) -> Self {
+        TakeUntil {
+            iter,
+            path,
+            reached: false,
+        }
+    }
+
+    /// Skip the contents of the directory that was yielded last, see
+    /// [`DirEntryIter::skip_current_dir`].
+    pub fn skip_current_dir(&mut self) {
+        self.iter.skip_current_dir();
+    }
+}
+
+impl<C: ClientState> Iterator for TakeUntil<C> {
+    type Item = Result<DirEntry<C>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reached {
+            return None;
+        }
+        match self.iter.next()? {
+            Ok(dir_entry) if dir_entry.path() == self.path => {
+                self.reached = true;
+                None
+            }
+            dir_entry_result => Some(dir_entry_result),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use super::*;
+use crate::Result;
+use std::path::PathBuf;
+
+/// Item of [`WalkEventIter`], see
+/// [`into_events_iter`](../struct.WalkDirGeneric.html#method.into_events_iter).
+#[derive(Debug)]
+pub enum WalkEvent<C: ClientState> {
+    /// A directory, yielded before its contents. Directories whose contents
+    /// aren't read are directly followed by their
+    /// [`LeaveDir`](enum.WalkEvent.html#variant.LeaveDir) event.
+    EnterDir(DirEntry<C>),
+    /// An entry that isn't a directory.
+    File(DirEntry<C>),
+    /// The path of a directory whose contents were all yielded, at the
+    /// depth of its [`EnterDir`](enum.WalkEvent.html#variant.EnterDir) event.
+    LeaveDir {
+        /// The path of the directory.
+        path: PathBuf,
+        /// The depth of the directory.
+        depth: usize,
+    },
+}
+
+/// Event iterator from `WalkDir.into_events_iter()`.
+///
+/// Yields the entries of a [`DirEntryIter`] in the same order, wrapped in
+/// [`WalkEvent`]s, with a
+/// [`LeaveDir`](enum.WalkEvent.html#variant.LeaveDir) event after the
+/// contents of each directory. Errors are passed through and don't affect
+/// the events.
+pub struct WalkEventIter<C: ClientState> {
+    iter: DirEntryIter<C>,
+    // directories that were entered but not left yet, innermost last
+    open_dirs: Vec<(PathBuf, usize)>,
+    // entry to yield once the directories it leaves were left
+    next: Option<Result<DirEntry<C>>>,
+}
+
+impl<C: ClientState> WalkEventIter<C> {
+    pub(crate) fn new(iter: DirEntryIter<C>) -> Self {
+        WalkEventIter {
+            iter,
+            open_dirs: Vec::new(),
+            next: None,
+        }
+    }
+
+    fn leave_dir(&mut self) -> Option<Result<WalkEvent<C>>> {
+        let (path, depth) = self.open_dirs.pop()?;
+        Some(Ok(WalkEvent::LeaveDir { path, depth }))
+    }
+}
+
+impl<C: ClientState> Iterator for WalkEventIter<C> {
+    type Item = Result<WalkEvent<C>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let dir_entry = match self.next.take().or_else(|| self.iter.next()) {
+            Some(Ok(dir_entry)) => dir_entry,
+            Some(Err(err)) => return Some(Err(err)),
+            None => return self.leave_dir(),
+        };
+        if self
+            .open_dirs
+            .last()
+            .is_some_and(|(_, depth)| *depth >= dir_entry.depth)
+        {
+            self.next = Some(Ok(dir_entry));
+            return self.leave_dir();
+        }
+        // Roots that are links to directories are read without following them.
+        if dir_entry.file_type.is_dir() || dir_entry.read_children_path.is_some() {
+            self.open_dirs.push((dir_entry.path(), dir_entry.depth));
+            Some(Ok(WalkEvent::EnterDir(dir_entry)))
+        } else {
+            Some(Ok(WalkEvent::File(dir_entry)))
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use super::{ClientState, DirEntry};
+use crate::Result;
+
+/// Statistics of a walk, see
+/// [`DirEntryIter::finish`](struct.DirEntryIter.html#method.finish).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WalkSummary {
+    /// Number of yielded entries.
+    pub entries: usize,
+    /// Number of yielded directories.
+    pub dirs: usize,
+    /// Number of yielded files.
+    pub files: usize,
+    /// Number of yielded symbolic links that weren't followed.
+    pub symlinks: usize,
+    /// Number of yielded errors, including those stored in
+    /// [`read_children_error`](struct.DirEntry.html#structfield.read_children_error).
+    pub errors: usize,
+    /// Total size of yielded files in bytes, as far as their metadata was
+    /// loaded with
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext).
+    pub bytes: u64,
+    /// Time since the iterator was created.
+    pub elapsed: Duration,
+    /// True if the iterator ended at
+    /// [`max_entries`](struct.WalkDirGeneric.html#method.max_entries) while
+    /// entries were left.
+    pub truncated: bool,
+}
+
+impl WalkSummary {
+    pub(crate) fn count<C: ClientState>(&mut self, dir_entry_result: &Result<DirEntry<C>>) {
+        let dir_entry = match dir_entry_result {
+            Ok(dir_entry) => dir_entry,
+            Err(_) => {
+                self.errors += 1;
+                return;
+            }
+        };
+        self.entries += 1;
+        if dir_entry.read_children_error.is_some() {
+            self.errors += 1;
+        }
+        let file_type = dir_entry.file_type();
+        if file_type.is_dir() {
+            self.dirs += 1;
+        } else if file_type.is_file() {
+            self.files += 1;
+            self.bytes += file_size(dir_entry);
+        } else if file_type.is_symlink() {
+            self.symlinks += 1;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn file_size<C: ClientState>(dir_entry: &DirEntry<C>) -> u64 {
+    match dir_entry.ext() {
+        Some(Ok(ext)) => ext.size,
+        _ => 0,
+    }
+}
+
+#[cfg(windows)]
+fn file_size<C: ClientState>(dir_entry: &DirEntry<C>) -> u64 {
+    match dir_entry.ext() {
+        Some(Ok(ext)) => ext.file_size,
+        _ => 0,
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_size<C: ClientState>(_dir_entry: &DirEntry<C>) -> u64 {
+    0
+}
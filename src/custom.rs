@@ -0,0 +1,7 @@
+//! Scheduling of directory reads, for custom directory readers.
+//!
+//! [`WalkDirGeneric`](../struct.WalkDirGeneric.html) reads directories with
+//! `std::fs::read_dir`. Use [`walk`](fn.walk.html) to read them any other way
+//! while keeping the depth first ordering and parallel scheduling.
+
+pub use crate::core::{walk, ReadDir, ReadDirSpec};
@@ -0,0 +1,432 @@
+//! Ready made walks for common tasks.
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{ClientState, DirEntry, Error, Operation, Parallelism, Result, WalkDirGeneric};
+
+/// Options of [`disk_usage`](fn.disk_usage.html).
+#[derive(Clone)]
+pub struct DiskUsageOptions {
+    /// Sum the lengths of files instead of the space allocated to them,
+    /// like `du --apparent-size`. Sparse and compressed files use less
+    /// space than their length, and small files more. Defaults to `false`.
+    ///
+    /// Allocated space is only known on unix, elsewhere lengths are summed.
+    pub apparent_size: bool,
+    /// Count each hard link of a file, instead of only the first one
+    /// encountered. Defaults to `false`.
+    ///
+    /// Hard links are only detected on unix, elsewhere each link is counted.
+    pub count_hardlinks: bool,
+    /// Skip directories on other devices than the root, like `du -x`.
+    /// Defaults to `false`.
+    ///
+    /// This is only supported on unix, elsewhere all directories are counted.
+    pub one_file_system: bool,
+    /// Count the targets of symbolic links instead of the links themselves.
+    /// Defaults to `false`.
+    pub follow_links: bool,
+    /// Degree of parallelism of the walk. Defaults to
+    /// [`Parallelism::RayonDefaultPool`](../enum.Parallelism.html#variant.RayonDefaultPool).
+    pub parallelism: Parallelism,
+}
+
+impl Default for DiskUsageOptions {
+    fn default() -> DiskUsageOptions {
+        DiskUsageOptions {
+            apparent_size: false,
+            count_hardlinks: false,
+            one_file_system: false,
+            follow_links: false,
+            parallelism: Parallelism::RayonDefaultPool {
+                busy_timeout: std::time::Duration::from_secs(1),
+            },
+        }
+    }
+}
+
+/// Space used by a directory tree, as returned by
+/// [`disk_usage`](fn.disk_usage.html).
+#[derive(Debug, Default)]
+pub struct DiskUsage {
+    /// Total size in bytes of all entries, including directories.
+    pub bytes: u64,
+    /// Number of entries that aren't directories.
+    pub files: u64,
+    /// Number of directories, including the root.
+    pub dirs: u64,
+    /// Errors encountered while walking. Entries that couldn't be read or
+    /// whose metadata couldn't be loaded aren't counted.
+    pub errors: Vec<Error>,
+}
+
+/// Compute the space used by the directory tree at `path`, like `du -s`.
+///
+/// Hidden entries are counted, and metadata is loaded by the threads that
+/// read each directory. Errors don't end the walk but are collected into
+/// [`DiskUsage::errors`](struct.DiskUsage.html#structfield.errors).
+pub fn disk_usage(path: impl AsRef<Path>, options: DiskUsageOptions) -> DiskUsage {
+    let one_file_system = options.one_file_system;
+    // The read dir state holds the device of the root once it was processed.
+    let walk_dir = WalkDirGeneric::<(Option<u64>, ())>::new(path)
+        .skip_hidden(false)
+        .follow_links(options.follow_links)
+        .parallelism(options.parallelism)
+        .preload_metadata_ext(true)
+        .track_hardlinks(!options.count_hardlinks)
+        .process_read_dir(move |depth, _, root_device, children| {
+            if !one_file_system {
+                return;
+            }
+            if depth.is_none() {
+                *root_device = children.iter().flatten().find_map(device_of);
+            } else if let Some(root_device) = *root_device {
+                children.retain(|dir_entry_result| {
+                    dir_entry_result.as_ref().map_or(true, |dir_entry| {
+                        device_of(dir_entry).is_none_or(|device| device == root_device)
+                    })
+                });
+            }
+        });
+
+    let mut disk_usage = DiskUsage::default();
+    for dir_entry_result in walk_dir {
+        let dir_entry = match dir_entry_result {
+            Ok(dir_entry) => dir_entry,
+            Err(err) => {
+                disk_usage.errors.push(err);
+                continue;
+            }
+        };
+        if dir_entry.is_hardlink_duplicate() {
+            continue;
+        }
+        let bytes = match dir_entry.ext() {
+            Some(Ok(ext)) => size_of(ext, options.apparent_size),
+            Some(Err(err)) => {
+                disk_usage.errors.push(err.clone());
+                continue;
+            }
+            None => 0,
+        };
+        disk_usage.bytes += bytes;
+        if dir_entry.file_type().is_dir() {
+            disk_usage.dirs += 1;
+        } else {
+            disk_usage.files += 1;
+        }
+    }
+    disk_usage
+}
+
+/// Options of [`tree_digest`](fn.tree_digest.html).
+#[derive(Clone)]
+pub struct TreeDigestOptions {
+    /// Hash the modification times of entries. Defaults to `true`.
+    ///
+    /// Disable it to get the same digest for copies of a tree that didn't
+    /// preserve modification times.
+    pub modified: bool,
+    /// Hash the contents of files. Defaults to `false`.
+    ///
+    /// Contents are read by the threads that read each directory.
+    pub content: bool,
+    /// Hash the targets of symbolic links instead of the links themselves.
+    /// Defaults to `false`.
+    pub follow_links: bool,
+    /// Degree of parallelism of the walk. Defaults to
+    /// [`Parallelism::RayonDefaultPool`](../enum.Parallelism.html#variant.RayonDefaultPool).
+    pub parallelism: Parallelism,
+}
+
+impl Default for TreeDigestOptions {
+    fn default() -> TreeDigestOptions {
+        TreeDigestOptions {
+            modified: true,
+            content: false,
+            follow_links: false,
+            parallelism: Parallelism::RayonDefaultPool {
+                busy_timeout: std::time::Duration::from_secs(1),
+            },
+        }
+    }
+}
+
+/// Fingerprint of a directory tree, as returned by
+/// [`tree_digest`](fn.tree_digest.html).
+#[derive(Debug, Default)]
+pub struct Digest {
+    /// Hash of all entries in walk order. Equal trees have equal hashes
+    /// across runs, processes and versions of this crate.
+    pub hash: u128,
+    /// Number of hashed entries, including the root.
+    pub entries: u64,
+    /// Errors encountered while walking. Entries that couldn't be read
+    /// aren't hashed, so the hash isn't meaningful unless this is empty.
+    pub errors: Vec<Error>,
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.hash)
+    }
+}
+
+/// Compute a fingerprint of the directory tree at `path`, for change
+/// detection and cache keys.
+///
+/// The kind, name and depth of each entry is hashed, together with the
+/// length of files, the target of symbolic links and, as chosen by
+/// `options`, modification times and file contents. The name of the root
+/// isn't hashed, so moved trees keep their digest.
+///
+/// Entries are hashed by the threads that read each directory and
+/// combined in the deterministic order of a sorted walk. Hidden entries
+/// are hashed, errors are collected into
+/// [`Digest::errors`](struct.Digest.html#structfield.errors).
+pub fn tree_digest(path: impl AsRef<Path>, options: TreeDigestOptions) -> Digest {
+    let TreeDigestOptions {
+        modified, content, ..
+    } = options;
+    let walk_dir = WalkDirGeneric::<((), u128)>::new(path)
+        .sort(true)
+        .skip_hidden(false)
+        .follow_links(options.follow_links)
+        .parallelism(options.parallelism)
+        .process_read_dir(move |_, _, _, children| {
+            for dir_entry_result in children.iter_mut() {
+                let hashed = match dir_entry_result {
+                    Ok(dir_entry) => hash_entry(dir_entry, modified, content),
+                    Err(_) => continue,
+                };
+                match hashed {
+                    Ok(hash) => {
+                        if let Ok(dir_entry) = dir_entry_result {
+                            dir_entry.client_state = hash;
+                        }
+                    }
+                    Err(err) => *dir_entry_result = Err(err),
+                }
+            }
+        });
+
+    let mut digest = Digest::default();
+    let mut hasher = Fnv128::new();
+    for dir_entry_result in walk_dir {
+        match dir_entry_result {
+            Ok(dir_entry) => {
+                hasher.write(&dir_entry.client_state.to_le_bytes());
+                digest.entries += 1;
+            }
+            Err(err) => digest.errors.push(err),
+        }
+    }
+    digest.hash = hasher.finish();
+    digest
+}
+
+fn hash_entry(dir_entry: &DirEntry<((), u128)>, modified: bool, content: bool) -> Result<u128> {
+    let mut hasher = Fnv128::new();
+    let file_type = dir_entry.file_type();
+    hasher.write(&(dir_entry.depth() as u64).to_le_bytes());
+    if dir_entry.depth() > 0 {
+        hasher.write_bytes(dir_entry.file_name_bytes());
+    }
+    if file_type.is_dir() {
+        hasher.write(b"d");
+    } else if file_type.is_symlink() {
+        hasher.write(b"l");
+        let target = fs::read_link(dir_entry.path())
+            .map_err(|err| Error::from_entry(dir_entry, Operation::ReadLink, err))?;
+        hasher.write_bytes(target.as_os_str().as_encoded_bytes());
+    } else {
+        hasher.write(if file_type.is_file() { b"f" } else { b"o" });
+    }
+
+    let metadata = dir_entry.metadata_cached()?;
+    if file_type.is_file() {
+        hasher.write(&metadata.len().to_le_bytes());
+    }
+    if modified {
+        // Times before the epoch or unknown to the platform hash as zero.
+        let nanos = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_nanos());
+        hasher.write(&nanos.to_le_bytes());
+    }
+    if content && file_type.is_file() {
+        let mut file = dir_entry.open()?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = file
+                .read(&mut buf)
+                .map_err(|err| Error::from_entry(dir_entry, Operation::Read, err))?;
+            if len == 0 {
+                break;
+            }
+            hasher.write(&buf[..len]);
+        }
+    }
+    Ok(hasher.finish())
+}
+
+// 128 bit FNV-1a, which unlike the hashers of std is specified and thus
+// stable across platforms and versions.
+struct Fnv128(u128);
+
+impl Fnv128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Fnv128 {
+        Fnv128(Fnv128::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u128::from(*byte);
+            self.0 = self.0.wrapping_mul(Fnv128::PRIME);
+        }
+    }
+
+    // Prefix variable length bytes with their length to keep them apart
+    // from what follows.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+/// Client state whose entries hold the [`Totals`](struct.Totals.html) of
+/// themselves, and once folded by
+/// [`DirEntryIter::aggregate`](../struct.DirEntryIter.html#method.aggregate),
+/// of their descendants.
+///
+/// ```no_run
+/// use jwalk::extras::Accumulator;
+///
+/// for dir_entry in Accumulator::walk_dir("foo")
+///     .into_iter()
+///     .aggregate(Accumulator::fold)
+/// {
+///     let dir_entry = dir_entry?;
+///     if dir_entry.file_type().is_dir() {
+///         let totals = dir_entry.client_state;
+///         println!("{}: {} bytes in {} files", dir_entry.path().display(), totals.bytes, totals.files);
+///     }
+/// }
+/// # Ok::<(), jwalk::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct Accumulator;
+
+impl ClientState for Accumulator {
+    type ReadDirState = ();
+    type DirEntryState = Totals;
+}
+
+/// Sums of a directory tree, see [`Accumulator`](struct.Accumulator.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Totals {
+    /// Sum of the lengths of all entries, including directories.
+    pub bytes: u64,
+    /// Number of entries that aren't directories.
+    pub files: u64,
+    /// Number of directories, including the directory itself.
+    pub dirs: u64,
+    /// Latest modification time of all entries, `None` if none is known.
+    pub modified: Option<SystemTime>,
+}
+
+impl Totals {
+    /// Totals of `dir_entry` alone, from its preloaded metadata if any.
+    pub fn of<C: ClientState>(dir_entry: &DirEntry<C>) -> Totals {
+        let ext = match dir_entry.ext() {
+            Some(Ok(ext)) => Some(ext),
+            _ => None,
+        };
+        let is_dir = dir_entry.file_type().is_dir();
+        Totals {
+            bytes: ext.map_or(0, |ext| size_of(ext, true)),
+            files: u64::from(!is_dir),
+            dirs: u64::from(is_dir),
+            modified: ext.and_then(|ext| ext.modified()),
+        }
+    }
+
+    /// Add `other` to these totals, keeping the later modification time.
+    pub fn add(&mut self, other: &Totals) {
+        self.bytes += other.bytes;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.modified = self.modified.max(other.modified);
+    }
+}
+
+impl Accumulator {
+    /// Walk `root` with the metadata of each entry preloaded and its own
+    /// totals filled in by the threads reading its directory.
+    ///
+    /// Setting another
+    /// [`process_read_dir`](../struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback replaces the one filling them in, use
+    /// [`Totals::of`](struct.Totals.html#method.of) to fill them in there.
+    pub fn walk_dir(root: impl AsRef<Path>) -> WalkDirGeneric<Accumulator> {
+        WalkDirGeneric::new(root)
+            .preload_metadata_ext(true)
+            .process_read_dir(|_, _, _, children| {
+                for dir_entry in children.iter_mut().flatten() {
+                    dir_entry.client_state = Totals::of(dir_entry);
+                }
+            })
+    }
+
+    /// Add the totals of `child` to those of its `parent`, to be passed to
+    /// [`DirEntryIter::aggregate`](../struct.DirEntryIter.html#method.aggregate).
+    pub fn fold(parent: &mut DirEntry<Accumulator>, child: &DirEntry<Accumulator>) {
+        parent.client_state.add(&child.client_state);
+    }
+}
+
+#[cfg(unix)]
+fn device_of(dir_entry: &DirEntry<(Option<u64>, ())>) -> Option<u64> {
+    match dir_entry.ext() {
+        Some(Ok(ext)) => Some(ext.dev),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn device_of(_dir_entry: &DirEntry<(Option<u64>, ())>) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn size_of(ext: &crate::DirEntryExt, apparent_size: bool) -> u64 {
+    if apparent_size {
+        ext.size
+    } else {
+        // `blocks` is in units of 512 bytes, regardless of `blksize`
+        ext.blocks * 512
+    }
+}
+
+#[cfg(windows)]
+fn size_of(ext: &crate::DirEntryExt, _apparent_size: bool) -> u64 {
+    ext.file_size
+}
+
+#[cfg(not(any(unix, windows)))]
+fn size_of(_ext: &crate::DirEntryExt, _apparent_size: bool) -> u64 {
+    0
+}
@@ -114,19 +114,43 @@
 //! depth first order.
 
 mod core;
+pub mod custom;
+pub mod extras;
+pub mod ordered;
+pub mod testing;
 
+use rayon::iter::{IndexedParallelIterator as _, IntoParallelIterator as _, ParallelIterator as _};
+use rayon::slice::ParallelSliceMut;
 use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-use crate::core::{ReadDir, ReadDirSpec};
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos", windows)
+))]
+use crate::core::FastReadDir;
+use crate::core::{
+    expand_glob, AdaptiveLimiter, Deepening, DeviceLimiter, DirFd, LinkAncestor, Pruner,
+    QueueLimits, ReadDir, ReadDirCallback, ReadDirSnapshot, ReadDirSpec, RoundState,
+};
 
-pub use crate::core::{DirEntry, DirEntryIter, Error};
+#[cfg(feature = "serde")]
+pub use crate::core::SerializedDirEntry;
+pub use crate::core::{
+    Aggregate, Change, Checkpoint, DiffIter, DirEntry, DirEntryBatchIter, DirEntryExt,
+    DirEntryIter, EnrichedIter, Error, ErrorKind, FileId, FilterEntry, Operation, ParDirEntryIter,
+    PendingEntry, Snapshot, TakeUntil, WalkEvent, WalkEventIter, WalkSummary,
+};
 pub use rayon;
 
 /// Builder for walking a directory.
@@ -159,16 +183,254 @@ pub trait ClientState: Send + Default + Debug + 'static {
 ///
 /// Use [`WalkDir`](type.WalkDir.html) if you don't need to store client state
 /// into yeilded DirEntries.
+///
+/// A configured walk can be cloned and run again with
+/// [`run`](struct.WalkDirGeneric.html#method.run). Clones share the callbacks
+/// passed to them rather than copying them.
 pub struct WalkDirGeneric<C: ClientState> {
     root: PathBuf,
     options: WalkDirOptions<C>,
 }
 
-type ProcessReadDirFunction<C> = dyn Fn(Option<usize>, &Path, &mut <C as ClientState>::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
+type ProcessReadDirFunction<C> = dyn Fn(
+        Option<usize>,
+        &Path,
+        Option<&DirEntry<C>>,
+        &mut <C as ClientState>::ReadDirState,
+        &mut Vec<Result<DirEntry<C>>>,
+    ) + Send
+    + Sync
+    + 'static;
+
+type ProcessFileFunction<C> = dyn Fn(&mut DirEntry<C>) + Send + Sync + 'static;
+
+type InspectRawEntryFunction<C> = dyn Fn(&fs::DirEntry, &mut DirEntry<C>) + Send + Sync + 'static;
+
+type CloneParentFunction<C> = fn(&DirEntry<C>) -> DirEntry<C>;
+
+type VisitDirFunction = dyn Fn(&Path) + Send + Sync + 'static;
+
+type EnrichEntryFunction<C> = dyn Fn(&DirEntry<C>) + Send + Sync + 'static;
+
+type ReadDirCompleteFunction<C> = dyn Fn(usize, &Path, &<C as ClientState>::ReadDirState, &[Result<DirEntry<C>>])
     + Send
     + Sync
     + 'static;
 
+type FilterMetadataFunction = dyn Fn(&Path, &fs::Metadata) -> bool + Send + Sync + 'static;
+
+type SkipHiddenFunction = dyn Fn(&OsStr, &Path) -> bool + Send + Sync + 'static;
+
+type FollowLinkFunction<C> = dyn Fn(&DirEntry<C>) -> bool + Send + Sync + 'static;
+
+/// What is considered a hidden entry when
+/// [`skip_hidden`](struct.WalkDirGeneric.html#method.skip_hidden) is enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HiddenPolicy {
+    /// Entries whose name starts with a `.`.
+    DotPrefix,
+    /// Entries whose name starts with a `.` and, on Windows, entries with the
+    /// `FILE_ATTRIBUTE_HIDDEN` attribute.
+    #[default]
+    Platform,
+    /// Like [`HiddenPolicy::Platform`], but also considers entries with the
+    /// `FILE_ATTRIBUTE_SYSTEM` attribute hidden on Windows.
+    PlatformAndSystem,
+}
+
+/// What to do with symbolic links whose target doesn't exist when
+/// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+/// enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrokenSymlinkBehavior {
+    /// Yield an [`Error`] in place of the link.
+    #[default]
+    Error,
+    /// Yield the link itself, as if it wasn't followed.
+    Yield,
+    /// Skip the link silently.
+    Skip,
+}
+
+/// Whether a root that is a symbolic link is followed, see
+/// [`root_link_behavior`](struct.WalkDirGeneric.html#method.root_link_behavior).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RootLinkBehavior {
+    /// Always read the contents of a root link to a directory. The root is
+    /// yielded as the link unless it is followed like other links, as
+    /// `walkdir` does with `follow_root_links(true)`.
+    #[default]
+    FollowAlways,
+    /// Never follow a root link, yielding it as a symbolic link without
+    /// reading its contents, even if other links are followed.
+    FollowNever,
+    /// Follow a root link like any other link, as set by
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) or
+    /// [`symlink_policy`](struct.WalkDirGeneric.html#method.symlink_policy).
+    UseFollowLinks,
+}
+
+/// Which symbolic links are followed, see
+/// [`symlink_policy`](struct.WalkDirGeneric.html#method.symlink_policy).
+#[derive(Clone, Copy, Debug, Default)]
+pub enum SymlinkPolicy {
+    /// Yield links as symlinks without following them.
+    #[default]
+    Never,
+    /// Follow all links, like
+    /// [`follow_links(true)`](struct.WalkDirGeneric.html#method.follow_links).
+    Always,
+    /// Only follow links to directories, and descend into them.
+    DirectoriesOnly,
+    /// Only follow links to anything but directories, so that they are
+    /// yielded with the type and metadata of their target.
+    FilesOnly,
+    /// Follow the links for whose path the function returns `true`.
+    Custom(fn(&Path) -> bool),
+}
+
+impl SymlinkPolicy {
+    fn follows_any(self) -> bool {
+        !matches!(self, SymlinkPolicy::Never)
+    }
+
+    // Whether the link at `path` may be followed, before its target is
+    // loaded. Links whose target can't be loaded are followed, so that
+    // `BrokenSymlinkBehavior` handles them.
+    fn may_follow(self, path: &Path) -> bool {
+        match self {
+            SymlinkPolicy::Never => false,
+            SymlinkPolicy::Custom(follows) => follows(path),
+            _ => true,
+        }
+    }
+
+    // Whether a link is followed once its target was loaded.
+    fn follows_target(self, target_is_dir: bool) -> bool {
+        match self {
+            SymlinkPolicy::DirectoriesOnly => target_is_dir,
+            SymlinkPolicy::FilesOnly => !target_is_dir,
+            _ => true,
+        }
+    }
+}
+
+// Which symlinks a walk follows, decided by the link before its target is
+// loaded, and then by the target loaded to follow it.
+struct FollowLink<C: ClientState> {
+    may_follow: Box<FollowLinkFunction<C>>,
+    symlink_policy: SymlinkPolicy,
+}
+
+impl<C: ClientState> FollowLink<C> {
+    // The target of the link `dir_entry`, `None` if it isn't followed.
+    fn follow(&self, dir_entry: &DirEntry<C>) -> Option<Result<DirEntry<C>>> {
+        if !(self.may_follow)(dir_entry) {
+            return None;
+        }
+        dir_entry
+            .follow_symlink(|target| {
+                self.symlink_policy
+                    .follows_target(target.file_type.is_dir())
+            })
+            .transpose()
+    }
+}
+
+/// How symbolic links pointing to one of their ancestors are detected when
+/// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+/// enabled, see
+/// [`loop_detection`](struct.WalkDirGeneric.html#method.loop_detection).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoopDetection {
+    /// Compare the device and inode numbers of directories, which costs a
+    /// `stat` call per directory read. Canonical paths are compared on
+    /// platforms without inode numbers.
+    #[default]
+    DevIno,
+    /// Compare the canonical paths of directories, which costs a
+    /// `canonicalize` call per directory read.
+    Canonical,
+    /// Don't detect loops. Walks following links into a loop only end once
+    /// the operating system refuses to resolve paths that long or
+    /// [`max_depth`](struct.WalkDirGeneric.html#method.max_depth) is reached.
+    Off,
+}
+
+/// What the [`file_name`](struct.DirEntry.html#structfield.file_name) of
+/// the root entry contains, see
+/// [`root_file_name`](struct.WalkDirGeneric.html#method.root_file_name).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RootName {
+    /// The last component of the path given to `new`, or the whole path if
+    /// it ends in `.`, `..` or a root like `/`.
+    #[default]
+    AsGiven,
+    /// The name of the directory the root resolves to, like the name of the
+    /// current directory for `.`. The whole canonical path for roots like
+    /// `/` that don't have a name.
+    FileName,
+    /// The absolute, canonical path of the root.
+    Canonical,
+}
+
+/// How directories are listed, see
+/// [`backend`](struct.WalkDirGeneric.html#method.backend).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// List with `std::fs::read_dir`.
+    Std,
+    /// List with the system calls of the platform if the `fast_readdir`
+    /// feature is enabled: `getdents64` on Linux, `getattrlistbulk` on macOS
+    /// and `FindFirstFileExW` on Windows. Falls back to
+    /// [`Backend::Std`](enum.Backend.html#variant.Std) otherwise.
+    Native,
+    /// List like [`Backend::Native`](enum.Backend.html#variant.Native) on
+    /// Linux and macOS, but open each directory with `openat` relative to
+    /// its parent, which is kept open until its subdirectories were read.
+    ///
+    /// Renaming ancestors while the walk runs then doesn't move it to
+    /// other directories, symbolic links swapped in for directories aren't
+    /// followed, and the kernel doesn't resolve the full path of deep
+    /// directories again and again. Yielded entries still have their full
+    /// paths. Roots, targets of followed links and directories spilled by
+    /// [`spill_to_disk`](struct.WalkDirGeneric.html#method.spill_to_disk)
+    /// are opened by path. Falls back to
+    /// [`Backend::Std`](enum.Backend.html#variant.Std) elsewhere, or without
+    /// the `fast_readdir` feature.
+    DirFd,
+}
+
+impl Default for Backend {
+    fn default() -> Backend {
+        if cfg!(feature = "fast_readdir") {
+            Backend::Native
+        } else {
+            Backend::Std
+        }
+    }
+}
+
+/// How metadata is loaded, see
+/// [`prefetch_metadata`](struct.WalkDirGeneric.html#method.prefetch_metadata).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Prefetch {
+    /// Load the metadata of each entry in turn, in the worker that read its
+    /// parent directory.
+    #[default]
+    Serial,
+    /// Load the metadata of the entries of a directory in chunks of `batch`
+    /// entries, which are spread over the threads of the worker pool.
+    Parallel {
+        /// Number of entries loaded by each task.
+        batch: usize,
+    },
+}
+
+/// Function used by [`Parallelism::Custom`] to run a unit of work on an
+/// application provided executor.
+pub type SpawnFunction = dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync;
+
 /// Degree of parallelism to use when performing walk.
 ///
 /// Parallelism happens at the directory level. It will help when walking deep
@@ -197,19 +459,378 @@ pub enum Parallelism {
         /// in case you know that there is at least one free thread available on the pool.
         busy_timeout: Option<std::time::Duration>,
     },
-    /// Run in new rayon thread pool with # threads
+    /// Run in new rayon thread pool with # threads. Same as
+    /// [`RayonNewPoolWith`](enum.Parallelism.html#variant.RayonNewPoolWith)
+    /// without `busy_timeout` and with
+    /// [`PoolFallback::DefaultPool`](enum.PoolFallback.html#variant.DefaultPool).
     RayonNewPool(usize),
+    /// Run in new rayon thread pool with `num_threads` threads, or as many as
+    /// rayon chooses if `0`, and choose what happens if it can't be built.
+    RayonNewPoolWith {
+        /// The number of threads of the pool.
+        num_threads: usize,
+        /// Similar to [`Parallelism::RayonDefaultPool::busy_timeout`] if
+        /// `Some`. A new pool is never busy, but the default pool it may fall
+        /// back to can be.
+        busy_timeout: Option<std::time::Duration>,
+        /// What to do if the pool can't be built.
+        fallback: PoolFallback,
+    },
+    /// Run in new rayon thread pool with `max` threads, but read only `min`
+    /// directories concurrently at first and adjust that number to how the
+    /// file system copes.
+    ///
+    /// More directories are read at once while the time taken per entry
+    /// stays level, and fewer when it rises or when running out of file
+    /// descriptors. This avoids oversubscribing fast local disks as well as
+    /// overloading network file systems without tuning the thread count.
+    Adaptive {
+        /// The number of directories read concurrently at first, and at
+        /// least.
+        min: usize,
+        /// The number of threads of the pool, and directories read
+        /// concurrently at most.
+        max: usize,
+    },
+    /// Run on an executor provided by the application.
+    ///
+    /// The function is called with each unit of work, which it must
+    /// eventually run on a thread of its choosing. Each directory read is
+    /// handed over as a task of its own, and tasks never wait for each
+    /// other, so a single thread is enough to make progress.
+    Custom(Arc<SpawnFunction>),
+}
+
+/// What to do if the thread pool of
+/// [`Parallelism::RayonNewPoolWith`](enum.Parallelism.html#variant.RayonNewPoolWith)
+/// can't be built, like when the system refuses to start more threads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PoolFallback {
+    /// Run in the default rayon thread pool instead.
+    #[default]
+    DefaultPool,
+    /// Fail the walk with an error for which
+    /// [`Error::is_busy`](struct.Error.html#method.is_busy) is true, like
+    /// when a pool is too busy.
+    Error,
+}
+
+/// What to do if the walk can't start because its thread pool is too busy,
+/// see [`busy_fallback`](struct.WalkDirGeneric.html#method.busy_fallback).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BusyFallback {
+    /// Fail the walk with an error for which
+    /// [`Error::is_busy`](struct.Error.html#method.is_busy) is true.
+    #[default]
+    Error,
+    /// Walk on the calling thread instead, like
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial).
+    Serial,
+    /// Walk in a new rayon thread pool with this many threads instead, or
+    /// as many as rayon chooses if `0`. Fails like `Error` if it can't be
+    /// built.
+    NewPool(usize),
+}
+
+/// Order in which the entries of each directory are sorted before they are
+/// yielded.
+///
+/// `bool` converts into `Sort` so that `sort(true)` and `sort(false)` keep
+/// working, mapping to [`Sort::Name`] and [`Sort::Unsorted`] respectively.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sort {
+    /// Keep entries in the order returned by `fs::read_dir`.
+    #[default]
+    Unsorted,
+    /// Sort entries by comparing their `file_name` lexicographically, byte
+    /// by byte like [`DirEntry::file_name_bytes`](struct.DirEntry.html#method.file_name_bytes),
+    /// which is the order of Unicode code points on all platforms.
+    Name,
+    /// Sort entries by `file_name` treating runs of digits as numbers, so
+    /// that `file2` comes before `file10`. Names that aren't valid Unicode
+    /// are compared like their
+    /// [`file_name_lossy_cached`](struct.DirEntry.html#method.file_name_lossy_cached).
+    Natural,
+    /// Sort entries by last modification time, newest first, and by
+    /// `file_name` if they were modified at the same time. Entries whose
+    /// metadata couldn't be loaded come last.
+    ///
+    /// This enables
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext),
+    /// so the time is read from the metadata loaded in the worker, which
+    /// remains available through [`DirEntry::ext`](struct.DirEntry.html#method.ext)
+    /// without a second `stat` call.
+    MTimeDescending,
+    /// Sort entries by `file_name` after bringing them into Unicode
+    /// normalization form C, so names written in decomposed form, as is
+    /// common on macOS, sort like their composed equivalents on Linux.
+    /// Names that aren't valid Unicode are compared like their
+    /// [`file_name_lossy_cached`](struct.DirEntry.html#method.file_name_lossy_cached).
+    ///
+    /// Requires the `unicode_sort` feature.
+    #[cfg(feature = "unicode_sort")]
+    Normalized,
+}
+
+/// Order in which entries are yielded, see
+/// [`yield_order`](struct.WalkDirGeneric.html#method.yield_order).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum YieldOrder {
+    /// Yield entries in depth first order, each directory followed by its
+    /// contents.
+    #[default]
+    Strict,
+    /// Yield the entries of each directory as soon as it was read.
+    ///
+    /// Entries of a single directory stay together and keep the order
+    /// established by [`sort`](struct.WalkDirGeneric.html#method.sort) and
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// but directories are yielded in the order their reads complete. This
+    /// avoids holding back results behind slow or deep subtrees when the
+    /// walk is parallel.
+    ///
+    /// The guarantees are weaker than those of depth first order: a
+    /// directory's contents don't follow it, and may come after entries of
+    /// other directories. So methods of
+    /// [`DirEntryIter`](struct.DirEntryIter.html) relying on that order,
+    /// like [`skip_current_dir`](struct.DirEntryIter.html#method.skip_current_dir)
+    /// or [`checkpoint`](struct.DirEntryIter.html#method.checkpoint), have no
+    /// effect. As a directory entry is yielded before its contents are read,
+    /// errors reading its contents are yielded as items of their own instead
+    /// of being stored in
+    /// [`read_children_error`](struct.DirEntry.html#field.read_children_error).
+    Relaxed,
+}
+
+/// Whether directories or files come first among the entries of each
+/// directory, see
+/// [`group_order`](struct.WalkDirGeneric.html#method.group_order).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupOrder {
+    /// Keep directories and files in the order given by
+    /// [`sort`](struct.WalkDirGeneric.html#method.sort).
+    #[default]
+    Mixed,
+    /// Yield directories before all other entries.
+    DirsFirst,
+    /// Yield directories after all other entries.
+    FilesFirst,
+}
+
+/// Conflicting options found by
+/// [`build`](struct.WalkDirGeneric.html#method.build).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// `min_depth` was set above `max_depth`, so the one set last was
+    /// clamped to the other.
+    MinDepthAboveMaxDepth {
+        /// The requested minimum depth.
+        min_depth: usize,
+        /// The requested maximum depth.
+        max_depth: usize,
+    },
+    /// A `max_depth` below 2 replaced the parallelism chosen before with
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial).
+    ParallelismDiscarded,
+    /// Links are followed with
+    /// [`LoopDetection::Off`](enum.LoopDetection.html#variant.Off) and
+    /// without `max_depth`, so a link loop is walked until paths get too
+    /// long.
+    UnboundedLinkLoops,
+    /// The `busy_timeout` of
+    /// [`Parallelism::RayonNewPoolWith`](enum.Parallelism.html#variant.RayonNewPoolWith)
+    /// is unused, as only the default pool can be busy and
+    /// [`PoolFallback::Error`](enum.PoolFallback.html#variant.Error) never
+    /// falls back to it.
+    UnusedBusyTimeout,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::MinDepthAboveMaxDepth {
+                min_depth,
+                max_depth,
+            } => write!(
+                f,
+                "min_depth {} is above max_depth {}",
+                min_depth, max_depth
+            ),
+            ConfigError::ParallelismDiscarded => {
+                f.write_str("max_depth below 2 replaced the chosen parallelism with Serial")
+            }
+            ConfigError::UnboundedLinkLoops => {
+                f.write_str("links are followed without loop detection or max_depth")
+            }
+            ConfigError::UnusedBusyTimeout => {
+                f.write_str("busy_timeout is unused by a new pool that doesn't fall back")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A walk whose options were checked by
+/// [`build`](struct.WalkDirGeneric.html#method.build).
+pub struct PreparedWalk<C: ClientState> {
+    walk_dir: WalkDirGeneric<C>,
+}
+
+impl<C: ClientState> PreparedWalk<C> {
+    /// Create a rayon `ParallelIterator` over the entries of the walk, see
+    /// [`WalkDirGeneric::into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter).
+    pub fn into_par_iter(self) -> ParDirEntryIter<C> {
+        self.walk_dir.into_par_iter()
+    }
+
+    /// Return the checked builder, to create any of its other iterators.
+    pub fn into_inner(self) -> WalkDirGeneric<C> {
+        self.walk_dir
+    }
+}
+
+impl<C: ClientState> IntoIterator for PreparedWalk<C> {
+    type Item = Result<DirEntry<C>>;
+    type IntoIter = DirEntryIter<C>;
+
+    fn into_iter(self) -> DirEntryIter<C> {
+        self.walk_dir.into_iter()
+    }
+}
+
+/// Types of entries to yield, see
+/// [`file_type_filter`](struct.WalkDirGeneric.html#method.file_type_filter).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileTypes {
+    /// Yield regular files.
+    pub files: bool,
+    /// Yield directories, including links to directories that are followed.
+    pub dirs: bool,
+    /// Yield symbolic links that aren't followed.
+    pub symlinks: bool,
+    /// Yield all other entries, like sockets, pipes or devices.
+    pub other: bool,
+}
+
+/// Listing of a directory stored in a [`ListingCache`].
+#[derive(Clone, Debug)]
+pub struct Listing {
+    /// Modification time of the directory before it was listed.
+    pub modified: SystemTime,
+    /// Names and types of the entries, including hidden ones.
+    pub entries: Vec<(OsString, fs::FileType)>,
+}
+
+/// Store of directory listings to be reused by later walks of the same
+/// tree, see
+/// [`listing_cache`](struct.WalkDirGeneric.html#method.listing_cache).
+///
+/// It is shared by the threads reading directories, so implementations
+/// need interior mutability. A `Mutex<HashMap<PathBuf, Arc<Listing>>>` is
+/// one.
+pub trait ListingCache: Send + Sync {
+    /// The listing last stored for the directory at `path`, if any.
+    fn get(&self, path: &Path) -> Option<Arc<Listing>>;
+
+    /// Store the listing of the directory at `path`, replacing an older one.
+    fn insert(&self, path: &Path, listing: Arc<Listing>);
+}
+
+impl ListingCache for Mutex<HashMap<PathBuf, Arc<Listing>>> {
+    fn get(&self, path: &Path) -> Option<Arc<Listing>> {
+        self.lock().unwrap().get(path).cloned()
+    }
+
+    fn insert(&self, path: &Path, listing: Arc<Listing>) {
+        self.lock().unwrap().insert(path.to_owned(), listing);
+    }
+}
+
+/// Walk `walk_dir` and yield how each entry changed since `snapshot` was
+/// taken, see [`Snapshot`](struct.Snapshot.html).
+///
+/// The workers reading each directory read the metadata of its entries and
+/// compare them to the snapshot, so the caller only receives the changes.
+/// Entries of the snapshot that weren't listed are yielded as removed after
+/// the entries of their directory. The snapshot should be taken with the
+/// same options, as entries that `walk_dir` skips, for example hidden ones,
+/// are removed otherwise.
+pub fn diff<C: ClientState>(
+    snapshot: impl Into<Arc<Snapshot>>,
+    mut walk_dir: WalkDirGeneric<C>,
+) -> DiffIter<C> {
+    let snapshot = snapshot.into();
+    walk_dir.options.diff_snapshot = Some(snapshot.clone());
+    DiffIter::new(walk_dir.into_iter(), snapshot)
 }
 
 struct WalkDirOptions<C: ClientState> {
-    sort: bool,
+    sort: Sort,
+    group_order: GroupOrder,
+    file_type_filter: Option<FileTypes>,
+    dirs_only: bool,
     min_depth: usize,
     max_depth: usize,
+    include_root: bool,
+    // depths as requested, if the last change clamped one to the other
+    depth_conflict: Option<(usize, usize)>,
+    // `max_depth` replaced a chosen parallelism with `Serial`
+    parallelism_discarded: bool,
     skip_hidden: bool,
-    follow_links: bool,
+    hidden_policy: HiddenPolicy,
+    skip_hidden_by: Option<Arc<SkipHiddenFunction>>,
+    symlink_policy: SymlinkPolicy,
+    follow_links_from_depth: usize,
+    follow_link_filter: Option<Arc<FollowLinkFunction<C>>>,
+    root_link_behavior: RootLinkBehavior,
+    loop_detection: LoopDetection,
+    broken_symlink_behavior: BrokenSymlinkBehavior,
+    backend: Backend,
+    relaxed_ordering: bool,
+    max_pending_entries: Option<usize>,
+    spill_threshold: Option<usize>,
+    per_device_concurrency: Option<usize>,
+    preload_metadata: bool,
+    preload_metadata_ext: bool,
+    prefetch: Prefetch,
+    sniff_content: Option<usize>,
+    split_large_dirs: Option<usize>,
+    parallel_sort_threshold: Option<usize>,
+    track_hardlinks: bool,
+    skip_visited_dirs: bool,
+    precompute_paths: bool,
+    long_paths: bool,
+    detect_changes: bool,
+    batch_size: usize,
+    resume_from: Option<Checkpoint>,
+    canonicalize_root: bool,
+    root_file_name: RootName,
+    glob_root: bool,
+    memory_fs: Option<Arc<testing::MemoryFs>>,
+    listing_cache: Option<Arc<dyn ListingCache>>,
+    diff_snapshot: Option<Arc<Snapshot>>,
+    root_dir: Option<Arc<DirFd>>,
     parallelism: Parallelism,
+    busy_fallback: BusyFallback,
     root_read_dir_state: C::ReadDirState,
     process_read_dir: Option<Arc<ProcessReadDirFunction<C>>>,
+    clone_parent: Option<CloneParentFunction<C>>,
+    process_file: Option<Arc<ProcessFileFunction<C>>>,
+    inspect_raw_entry: Option<Arc<InspectRawEntryFunction<C>>>,
+    on_visit_dir: Option<Arc<VisitDirFunction>>,
+    on_read_dir_complete: Option<Arc<ReadDirCompleteFunction<C>>>,
+    enrich_entry: Option<Arc<EnrichEntryFunction<C>>>,
+    errors_to: Option<Sender<Error>>,
+    max_entries: Option<usize>,
+    max_entries_per_dir: Option<usize>,
+    sample: Option<(f64, u64)>,
+    filter_metadata: Option<Arc<FilterMetadataFunction>>,
+    filter_metadata_prunes: bool,
+    prune_paths: Vec<PathBuf>,
+    prune_names: Vec<OsString>,
+    iterative_deepening: bool,
+    round_state: Option<RoundState>,
 }
 
 impl<C: ClientState> WalkDirGeneric<C> {
@@ -225,165 +846,1461 @@ impl<C: ClientState> WalkDirGeneric<C> {
         WalkDirGeneric {
             root: root.as_ref().to_path_buf(),
             options: WalkDirOptions {
-                sort: false,
+                sort: Sort::Unsorted,
+                group_order: GroupOrder::default(),
+                file_type_filter: None,
+                dirs_only: false,
                 min_depth: 0,
-                max_depth: ::std::usize::MAX,
+                include_root: true,
+                max_depth: usize::MAX,
+                depth_conflict: None,
+                parallelism_discarded: false,
                 skip_hidden: true,
-                follow_links: false,
+                hidden_policy: HiddenPolicy::default(),
+                skip_hidden_by: None,
+                symlink_policy: SymlinkPolicy::Never,
+                follow_links_from_depth: 0,
+                follow_link_filter: None,
+                root_link_behavior: RootLinkBehavior::default(),
+                loop_detection: LoopDetection::default(),
+                broken_symlink_behavior: BrokenSymlinkBehavior::default(),
+                backend: Backend::default(),
+                relaxed_ordering: false,
+                max_pending_entries: None,
+                spill_threshold: None,
+                per_device_concurrency: None,
+                preload_metadata: false,
+                preload_metadata_ext: false,
+                prefetch: Prefetch::default(),
+                sniff_content: None,
+                split_large_dirs: None,
+                parallel_sort_threshold: None,
+                track_hardlinks: false,
+                skip_visited_dirs: false,
+                precompute_paths: false,
+                long_paths: false,
+                detect_changes: false,
+                batch_size: 1024,
+                resume_from: None,
+                canonicalize_root: false,
+                root_file_name: RootName::AsGiven,
+                glob_root: false,
+                memory_fs: None,
+                listing_cache: None,
+                diff_snapshot: None,
+                root_dir: None,
                 parallelism: Parallelism::RayonDefaultPool {
                     busy_timeout: std::time::Duration::from_secs(1),
                 },
+                busy_fallback: BusyFallback::default(),
                 root_read_dir_state: C::ReadDirState::default(),
                 process_read_dir: None,
+                clone_parent: None,
+                process_file: None,
+                inspect_raw_entry: None,
+                on_visit_dir: None,
+                on_read_dir_complete: None,
+                enrich_entry: None,
+                errors_to: None,
+                max_entries: None,
+                max_entries_per_dir: None,
+                sample: None,
+                filter_metadata: None,
+                filter_metadata_prunes: false,
+                prune_paths: Vec::new(),
+                prune_names: Vec::new(),
+                iterative_deepening: false,
+                round_state: None,
             },
         }
     }
 
-    /// Try to create an iterator or fail if the rayon threadpool (in any configuration) is busy.
+    /// Create a builder for walking all paths matching the glob `pattern`,
+    /// like `~/projects/*/src`, as the roots of a single walk.
+    ///
+    /// Within a path component, `*` matches any run of characters, `?` a
+    /// single character and `[...]` one of the characters or ranges within,
+    /// or none of them if it starts with `!`. Names starting with `.` are
+    /// only matched by components starting with `.` too, and a leading `~`
+    /// stands for the home directory.
+    ///
+    /// The pattern is expanded when the walk starts. Matching roots are
+    /// yielded at depth `0` in the order of their paths, each followed by
+    /// its contents, and directories of all roots are read in parallel. A
+    /// pattern matching nothing yields nothing.
+    pub fn new_glob<P: AsRef<Path>>(pattern: P) -> Self {
+        let mut walk_dir = WalkDirGeneric::new(pattern);
+        walk_dir.options.glob_root = true;
+        walk_dir
+    }
+
+    /// Create a builder for walking the directory `dir` was opened for, for
+    /// sandboxed programs holding a directory handle but no path to it.
+    ///
+    /// The root is yielded as `.` and all paths are relative to `dir`, like
+    /// `./src/main.rs`. Directories are opened relative to `dir` with
+    /// [`Backend::DirFd`](enum.Backend.html#variant.DirFd), which must not
+    /// be changed. Anything else using paths, like
+    /// [`DirEntry::metadata`](struct.DirEntry.html#method.metadata) or
+    /// following symbolic links, resolves them against the current
+    /// directory instead. Only available on Linux and macOS with the
+    /// `fast_readdir` feature.
+    #[cfg(all(
+        feature = "fast_readdir",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    pub fn from_dir_handle(dir: fs::File) -> Self {
+        let root = Path::new(".");
+        let mut walk_dir = WalkDirGeneric::new(root);
+        walk_dir.options.backend = Backend::DirFd;
+        walk_dir.options.root_dir = Some(Arc::new(DirFd::from_handle(dir, Arc::from(root))));
+        walk_dir
+    }
+
+    /// Try to create an iterator or fail if the root can't be accessed or the
+    /// rayon threadpool (in any configuration) is busy.
+    ///
+    /// Errors accessing the root, like a nonexistent path or missing
+    /// permissions, are returned here instead of being yielded as the first
+    /// item of the iterator. Walks of several roots, like those created by
+    /// [`new_glob`](struct.WalkDirGeneric.html#method.new_glob) or resumed
+    /// with [`resume_from`](struct.WalkDirGeneric.html#method.resume_from),
+    /// still yield such errors, so that the other roots are walked.
     pub fn try_into_iter(self) -> Result<DirEntryIter<C>> {
-        let iter = self.into_iter();
-        if iter.read_dir_iter.is_none() {
+        let mut iter = self.into_iter();
+        if let Some(err) = iter.take_root_error() {
+            Err(err)
+        } else if iter.read_dir_iter.is_none() {
             Err(Error::busy())
         } else {
             Ok(iter)
         }
     }
 
-    /// Root path of the walk.
+    /// Check the options for combinations that are likely mistakes, which
+    /// would otherwise be resolved silently, and prepare the walk.
+    ///
+    /// Setting `min_depth` above `max_depth` clamps one to the other, and a
+    /// `max_depth` below 2 runs the walk serially, replacing a parallelism
+    /// chosen before. Following links without loop detection nor
+    /// `max_depth` keeps walking a link loop until paths get too long, and
+    /// the `busy_timeout` of a new pool that never falls back to the default
+    /// pool has no effect. These are reported as a
+    /// [`ConfigError`](enum.ConfigError.html) here, while iterating the
+    /// builder directly keeps resolving them as before.
+    pub fn build(self) -> std::result::Result<PreparedWalk<C>, ConfigError> {
+        let options = &self.options;
+        if let Some((min_depth, max_depth)) = options.depth_conflict {
+            return Err(ConfigError::MinDepthAboveMaxDepth {
+                min_depth,
+                max_depth,
+            });
+        }
+        if options.parallelism_discarded {
+            return Err(ConfigError::ParallelismDiscarded);
+        }
+        if options.symlink_policy.follows_any()
+            && options.loop_detection == LoopDetection::Off
+            && options.max_depth == usize::MAX
+        {
+            return Err(ConfigError::UnboundedLinkLoops);
+        }
+        if let Parallelism::RayonNewPoolWith {
+            busy_timeout: Some(_),
+            fallback: PoolFallback::Error,
+            ..
+        } = options.parallelism
+        {
+            return Err(ConfigError::UnusedBusyTimeout);
+        }
+        Ok(PreparedWalk { walk_dir: self })
+    }
+
+    /// Create an iterator over a new walk configured like this one, which
+    /// stays available to be run again, like periodically.
+    ///
+    /// This is [`into_iter`](struct.WalkDirGeneric.html#method.into_iter) on
+    /// a clone, and the callbacks are shared between the walks.
+    pub fn run(&self) -> DirEntryIter<C> {
+        self.clone().into_iter()
+    }
+
+    /// Create an iterator yielding the entries of the walk in batches of up
+    /// to [`batch_size`](struct.WalkDirGeneric.html#method.batch_size)
+    /// entries, in the same order as
+    /// [`into_iter`](struct.WalkDirGeneric.html#method.into_iter).
+    ///
+    /// Directory contents are already passed between threads as a whole, so
+    /// this is for consumers that process entries in chunks, like database
+    /// inserts, and avoids handling them one by one.
+    pub fn into_batched_iter(self) -> DirEntryBatchIter<C> {
+        let batch_size = self.options.batch_size;
+        DirEntryBatchIter::new(self.into_iter(), batch_size)
+    }
+
+    /// Run the walk and return the number of entries it yields. Errors
+    /// aren't counted.
+    ///
+    /// Entries are neither sorted nor kept in depth first order, as the
+    /// count doesn't depend on it.
+    pub fn count_entries(self) -> usize {
+        self.into_unordered_iter().flatten().count()
+    }
+
+    /// Run the walk and return the sum of the sizes of the entries it yields
+    /// that aren't directories, from their
+    /// [`metadata`](struct.DirEntry.html#method.metadata). Entries whose
+    /// metadata can't be read, and errors, aren't counted.
+    ///
+    /// Metadata is read by the threads that read each directory, with
+    /// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata),
+    /// and entries are neither sorted nor kept in depth first order.
+    pub fn total_len(self) -> u64 {
+        self.preload_metadata(true)
+            .into_unordered_iter()
+            .flatten()
+            .filter(|dir_entry| !dir_entry.file_type.is_dir())
+            .filter_map(|dir_entry| Some(dir_entry.metadata_cached().ok()?.len()))
+            .sum()
+    }
+
+    /// Run the walk and return the paths of the entries it yields. Errors
+    /// are left out.
+    ///
+    /// The paths are in no particular order, as entries are neither sorted
+    /// nor kept in depth first order. Sort them if needed, which is cheaper
+    /// than sorting each directory during the walk.
+    pub fn collect_paths(self) -> Vec<PathBuf> {
+        self.into_unordered_iter()
+            .flatten()
+            .map(|dir_entry| dir_entry.path())
+            .collect()
+    }
+
+    // Iterator for results that don't depend on the order of entries.
+    fn into_unordered_iter(mut self) -> DirEntryIter<C> {
+        self.options.sort = Sort::Unsorted;
+        self.options.group_order = GroupOrder::Mixed;
+        self.options.relaxed_ordering = true;
+        self.into_iter()
+    }
+
+    /// Create an iterator yielding the entries of the walk as
+    /// [`WalkEvent`](enum.WalkEvent.html)s, in the same order as
+    /// [`into_iter`](struct.WalkDirGeneric.html#method.into_iter), with a
+    /// [`LeaveDir`](enum.WalkEvent.html#variant.LeaveDir) event once all
+    /// contents of a directory were yielded.
+    ///
+    /// This relies on the depth first order of the walk, so directories are
+    /// left too early with
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed).
+    pub fn into_events_iter(self) -> WalkEventIter<C> {
+        WalkEventIter::new(self.into_iter())
+    }
+
+    /// Walk in two phases: the returned walk iterator discovers entries as
+    /// usual, while each entry below the root for which `select` returns
+    /// `true` is passed to `enrich` in the background, with results yielded
+    /// by the returned [`EnrichedIter`](struct.EnrichedIter.html).
+    ///
+    /// Discovery can so skip loading metadata, and only the selected subset
+    /// has its metadata, extended metadata or content hashes loaded while
+    /// the walk continues, rather than everything or in a second traversal.
+    /// `select` runs in the worker that read the entry's directory, and
+    /// `enrich` on a copy of the entry as a task on the same rayon pool,
+    /// or right away in walks that don't run on rayon.
+    ///
+    /// The [`EnrichedIter`](struct.EnrichedIter.html) ends once the walk
+    /// iterator was dropped, so consume them on separate threads, or the
+    /// walk iterator first.
+    pub fn into_enriched<S, E, T>(
+        mut self,
+        select: S,
+        enrich: E,
+    ) -> (DirEntryIter<C>, EnrichedIter<T>)
+    where
+        S: Fn(&DirEntry<C>) -> bool + Send + Sync + 'static,
+        E: Fn(DirEntry<C>) -> T + Send + Sync + 'static,
+        T: Send + 'static,
+        C::DirEntryState: Clone,
+    {
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let enrich = Arc::new(enrich);
+        self.options.enrich_entry = Some(Arc::new(move |dir_entry: &DirEntry<C>| {
+            if !select(dir_entry) {
+                return;
+            }
+            let (sender, enrich, dir_entry) = (sender.clone(), enrich.clone(), dir_entry.clone());
+            let task = move || {
+                // The results are of no use once the iterator was dropped.
+                let _ = sender.send(enrich(dir_entry));
+            };
+            if rayon::current_thread_index().is_some() {
+                rayon::spawn(task);
+            } else {
+                task();
+            }
+        }));
+        (self.into_iter(), EnrichedIter::new(receiver))
+    }
+
+    /// Create a rayon [`ParallelIterator`](rayon::iter::ParallelIterator)
+    /// over the entries of the walk.
+    ///
+    /// Directories are read as rayon tasks that feed the iterator directly,
+    /// so entries reach the consumer without passing through the ordered
+    /// sequential iterator. Collecting into a `Vec` preserves the depth first
+    /// order of [`into_iter`](struct.WalkDirGeneric.html#method.into_iter);
+    /// `for_each` and friends see entries as soon as they are read.
+    ///
+    /// The walk runs on the pool of a
+    /// [`RayonExistingPool`](enum.Parallelism.html#variant.RayonExistingPool)
+    /// or [`RayonNewPool`](enum.Parallelism.html#variant.RayonNewPool), and
+    /// in the current rayon context otherwise. As parents are yielded before
+    /// their contents are read, errors reading a directory are yielded as
+    /// items of their own rather than through
+    /// [`read_children_error`](struct.DirEntry.html#structfield.read_children_error).
+    pub fn into_par_iter(self) -> ParDirEntryIter<C> {
+        let parts = self.into_parts();
+        ParDirEntryIter::new(
+            parts.root_entry_results,
+            parts.parallelism,
+            parts.min_depth,
+            parts.root_read_dir_state,
+            parts.errors_to,
+            parts.core_read_dir_callback,
+        )
+    }
+
+    /// Root path of the walk, or its pattern if it was created with
+    /// [`new_glob`](struct.WalkDirGeneric.html#method.new_glob).
     pub fn root(&self) -> &Path {
         &self.root
     }
 
-    /// Sort entries by `file_name` per directory. Defaults to `false`. Use
+    /// Sort entries per directory. Defaults to
+    /// [`Sort::Unsorted`](enum.Sort.html#variant.Unsorted). Accepts a `bool`
+    /// for plain name sorting or a [`Sort`](enum.Sort.html) mode. Use
     /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir) for custom
     /// sorting or filtering.
-    pub fn sort(mut self, sort: bool) -> Self {
-        self.options.sort = sort;
+    pub fn sort(mut self, sort: impl Into<Sort>) -> Self {
+        self.options.sort = sort.into();
+        self
+    }
+
+    /// Group directories before or after files per directory. Defaults to
+    /// [`GroupOrder::Mixed`](enum.GroupOrder.html#variant.Mixed).
+    ///
+    /// Entries within each group keep the order given by
+    /// [`sort`](struct.WalkDirGeneric.html#method.sort). Grouping happens
+    /// before [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// is called, which sees and may change the grouped order. Links that are
+    /// followed are grouped by the type of their target.
+    pub fn group_order(mut self, group_order: GroupOrder) -> Self {
+        self.options.group_order = group_order;
+        self
+    }
+
+    /// Yield only entries of the given types. Defaults to yielding all.
+    ///
+    /// Entries are dropped by the threads reading their directory, right
+    /// after [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// saw them, so consumers that only want files don't receive the
+    /// directories. Rejected directories are still read and their contents
+    /// yielded. Errors are always yielded.
+    pub fn file_type_filter(mut self, file_types: FileTypes) -> Self {
+        self.options.file_type_filter = Some(file_types);
+        self
+    }
+
+    /// Yield only directories, for tools that only need the skeleton of a
+    /// tree, like project pickers. By default, this is disabled.
+    ///
+    /// Unlike a [`file_type_filter`](struct.WalkDirGeneric.html#method.file_type_filter)
+    /// with only `dirs` set, other entries are dropped right after they were
+    /// listed, without creating entries for them, which saves most of the
+    /// allocations on trees with many files. Symbolic links are kept until
+    /// it is known whether they are followed to a directory.
+    pub fn dirs_only(mut self, dirs_only: bool) -> Self {
+        self.options.dirs_only = dirs_only;
         self
     }
 
     /// Skip hidden entries. Enabled by default.
+    ///
+    /// Use [`hidden_policy`](struct.WalkDirGeneric.html#method.hidden_policy)
+    /// to control which entries are considered hidden.
     pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
         self.options.skip_hidden = skip_hidden;
         self
     }
 
+    /// Define which entries are hidden and skipped by
+    /// [`skip_hidden`](struct.WalkDirGeneric.html#method.skip_hidden).
+    /// Defaults to [`HiddenPolicy::Platform`](enum.HiddenPolicy.html#variant.Platform).
+    ///
+    /// On Windows the attributes are taken from the directory listing, so
+    /// checking them doesn't require additional system calls.
+    pub fn hidden_policy(mut self, hidden_policy: HiddenPolicy) -> Self {
+        self.options.hidden_policy = hidden_policy;
+        self
+    }
+
+    /// Skip the entries at `paths` together with their contents, like
+    /// `target` or `web/node_modules`. Paths are relative to the root, or
+    /// start with it. Can be called several times to add more paths.
+    ///
+    /// Like with [`skip_hidden_by`](struct.WalkDirGeneric.html#method.skip_hidden_by),
+    /// entries are dropped right after they were read, and the paths are
+    /// looked up in a tree of their components, so many of them don't slow
+    /// down the walk.
+    pub fn prune_paths<P: AsRef<Path>>(mut self, paths: &[P]) -> Self {
+        self.options
+            .prune_paths
+            .extend(paths.iter().map(|path| path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Skip entries with any of the file `names`, like `.git`, together with
+    /// their contents wherever they are found below the root. Can be called
+    /// several times to add more names.
+    pub fn prune_names<N: AsRef<OsStr>>(mut self, names: &[N]) -> Self {
+        self.options
+            .prune_names
+            .extend(names.iter().map(|name| name.as_ref().to_os_string()));
+        self
+    }
+
+    /// Skip entries for which `is_hidden` returns `true`, replacing the
+    /// [`hidden_policy`](struct.WalkDirGeneric.html#method.hidden_policy).
+    /// This also enables
+    /// [`skip_hidden`](struct.WalkDirGeneric.html#method.skip_hidden).
+    ///
+    /// `is_hidden` is called with the file name of each entry and the path of
+    /// the directory containing it. Unlike filtering in
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// entries are dropped right after they were read, so directories like
+    /// `node_modules` or `target` are never considered for reading.
+    pub fn skip_hidden_by<F>(mut self, is_hidden: F) -> Self
+    where
+        F: Fn(&OsStr, &Path) -> bool + Send + Sync + 'static,
+    {
+        self.options.skip_hidden = true;
+        self.options.skip_hidden_by = Some(Arc::new(is_hidden));
+        self
+    }
+
     /// Follow symbolic links. By default, this is disabled.
     ///
     /// When `yes` is `true`, symbolic links are followed as if they were normal
     /// directories and files. If a symbolic link is broken or is involved in a
-    /// loop, an error is yielded.
+    /// loop, an error is yielded. Use
+    /// [`broken_symlink_behavior`](struct.WalkDirGeneric.html#method.broken_symlink_behavior)
+    /// to handle broken links differently.
     ///
     /// When enabled, the yielded [`DirEntry`] values represent the target of
     /// the link while the path corresponds to the link. See the [`DirEntry`]
     /// type for more details.
     ///
+    /// On Windows, junctions are followed and checked for loops like
+    /// symbolic links, see
+    /// [`DirEntry::is_junction`](struct.DirEntry.html#method.is_junction).
+    ///
+    /// This sets the [`symlink_policy`](struct.WalkDirGeneric.html#method.symlink_policy)
+    /// to [`Always`](enum.SymlinkPolicy.html#variant.Always) or
+    /// [`Never`](enum.SymlinkPolicy.html#variant.Never).
+    ///
     /// [`DirEntry`]: struct.DirEntry.html
     pub fn follow_links(mut self, follow_links: bool) -> Self {
-        self.options.follow_links = follow_links;
+        self.options.symlink_policy = if follow_links {
+            SymlinkPolicy::Always
+        } else {
+            SymlinkPolicy::Never
+        };
         self
     }
 
-    /// Set the minimum depth of entries yielded by the iterator.
+    /// Follow the symbolic links selected by `symlink_policy`, like following
+    /// links to files but not to directories. Defaults to
+    /// [`SymlinkPolicy::Never`](enum.SymlinkPolicy.html#variant.Never).
     ///
-    /// The smallest depth is `0` and always corresponds to the path given
-    /// to the `new` function on this type. Its direct descendents have depth
-    /// `1`, and their descendents have depth `2`, and so on.
-    pub fn min_depth(mut self, depth: usize) -> Self {
-        self.options.min_depth = depth;
-        if self.options.min_depth > self.options.max_depth {
-            self.options.min_depth = self.options.max_depth;
-        }
+    /// Followed links behave as with
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links), the
+    /// others are yielded as symlinks and not descended into. Links to
+    /// directories are told from others by the `stat` call following them
+    /// takes anyway.
+    pub fn symlink_policy(mut self, symlink_policy: SymlinkPolicy) -> Self {
+        self.options.symlink_policy = symlink_policy;
         self
     }
 
-    /// Set the maximum depth of entries yield by the iterator.
-    ///
-    /// The smallest depth is `0` and always corresponds to the path given
-    /// to the `new` function on this type. Its direct descendents have depth
-    /// `1`, and their descendents have depth `2`, and so on.
-    ///
-    /// A depth < 2 will automatically change `parallelism` to
-    /// `Parallelism::Serial`. Parrallelism happens at the `fs::read_dir` level.
-    /// It only makes sense to use multiple threads when reading more then one
-    /// directory.
+    /// Only follow symbolic links at `depth` or deeper when
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+    /// enabled. By default, links at all depths are followed.
     ///
-    /// Note that this will not simply filter the entries of the iterator, but
-    /// it will actually avoid descending into directories when the depth is
-    /// exceeded.
-    pub fn max_depth(mut self, depth: usize) -> Self {
-        self.options.max_depth = depth;
-        if self.options.max_depth < self.options.min_depth {
-            self.options.max_depth = self.options.min_depth;
-        }
-        if self.options.max_depth < 2 {
-            self.options.parallelism = Parallelism::Serial;
-        }
-        self
-    }
-
-    /// Degree of parallelism to use when performing walk. Defaults to
-    /// [`Parallelism::RayonDefaultPool`](enum.Parallelism.html#variant.RayonDefaultPool).
-    pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
-        self.options.parallelism = parallelism;
+    /// Links above `depth` are yielded as symlinks and not descended into,
+    /// like junctions or mount points among the immediate children of the
+    /// root with a `depth` of 2. Whether a root link is read is set by
+    /// [`root_link_behavior`](struct.WalkDirGeneric.html#method.root_link_behavior).
+    pub fn follow_links_from_depth(mut self, depth: usize) -> Self {
+        self.options.follow_links_from_depth = depth;
         self
     }
 
-    /// Initial ClientState::ReadDirState that is passed to
-    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
-    /// when processing root. Defaults to ClientState::ReadDirState::default().
-    pub fn root_read_dir_state(mut self, read_dir_state: C::ReadDirState) -> Self {
-        self.options.root_read_dir_state = read_dir_state;
+    /// Choose whether a root that is a symbolic link is followed,
+    /// independent of the other links. Defaults to
+    /// [`RootLinkBehavior::FollowAlways`](enum.RootLinkBehavior.html#variant.FollowAlways).
+    pub fn root_link_behavior(mut self, root_link_behavior: RootLinkBehavior) -> Self {
+        self.options.root_link_behavior = root_link_behavior;
         self
     }
 
-    /// A callback function to process (sort/filter/skip/state) each directory
-    /// of entries before they are yielded. Modify the given array to
-    /// sort/filter entries. Use [`entry.read_children_path =
-    /// None`](struct.DirEntry.html#field.read_children_path) to yield a
-    /// directory entry but skip reading its contents. Use
-    /// [`entry.client_state`](struct.DirEntry.html#field.client_state)
-    /// to store custom state with an entry.
-    pub fn process_read_dir<F>(mut self, process_by: F) -> Self
+    /// Only follow the symbolic links for which `follow_by` returns `true`
+    /// when [`follow_links`](struct.WalkDirGeneric.html#method.follow_links)
+    /// is enabled.
+    ///
+    /// `follow_by` is called with the entry of the link itself, before it is
+    /// followed. Links it rejects are yielded as symlinks and not descended
+    /// into.
+    pub fn follow_link_filter<F>(mut self, follow_by: F) -> Self
     where
-        F: Fn(Option<usize>, &Path, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
-            + Send
-            + Sync
-            + 'static,
+        F: Fn(&DirEntry<C>) -> bool + Send + Sync + 'static,
     {
-        self.options.process_read_dir = Some(Arc::new(process_by));
+        self.options.follow_link_filter = Some(Arc::new(follow_by));
         self
     }
-}
+
+    /// How links pointing to one of their ancestors are detected when
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+    /// enabled. Defaults to
+    /// [`LoopDetection::DevIno`](enum.LoopDetection.html#variant.DevIno).
+    ///
+    /// Detected loops are yielded as errors, see
+    /// [`Error::loop_ancestor`](struct.Error.html#method.loop_ancestor).
+    pub fn loop_detection(mut self, loop_detection: LoopDetection) -> Self {
+        self.options.loop_detection = loop_detection;
+        self
+    }
+
+    /// Handling of symbolic links whose target doesn't exist when
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+    /// enabled. Defaults to
+    /// [`BrokenSymlinkBehavior::Error`](enum.BrokenSymlinkBehavior.html#variant.Error).
+    ///
+    /// Yielded broken links report themselves as symlinks, with the metadata
+    /// of the link. Links involved in a loop are always reported as errors.
+    pub fn broken_symlink_behavior(mut self, behavior: BrokenSymlinkBehavior) -> Self {
+        self.options.broken_symlink_behavior = behavior;
+        self
+    }
+
+    /// Select how directories are listed. Defaults to
+    /// [`Backend::Native`](enum.Backend.html#variant.Native) if the
+    /// `fast_readdir` feature is enabled, and to
+    /// [`Backend::Std`](enum.Backend.html#variant.Std) otherwise.
+    ///
+    /// Native listings save system calls on huge directories, most notably on
+    /// macOS where `getattrlistbulk` returns names, types and sizes of many
+    /// entries at once. The listed metadata is used by
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext)
+    /// where available.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.options.backend = backend;
+        self
+    }
+
+    /// Walk the tree held by `memory_fs` instead of the file system, see
+    /// [`MemoryFs`](testing/struct.MemoryFs.html). Pass an `Arc` to share
+    /// one tree between walks.
+    pub fn memory_fs(mut self, memory_fs: impl Into<Arc<testing::MemoryFs>>) -> Self {
+        self.options.memory_fs = Some(memory_fs.into());
+        self
+    }
+
+    /// Reuse directory listings of earlier walks stored in `listing_cache`,
+    /// and store the listings made by this walk in it. By default, every
+    /// directory is listed.
+    ///
+    /// A stored listing is used if the modification time of its directory
+    /// didn't change since, which saves listing unchanged directories when
+    /// the same tree is scanned again and again, as in polling file
+    /// watchers. Entries are only listed from the cache, their metadata is
+    /// still read as configured.
+    ///
+    /// Changes within the resolution of the file system's timestamps may go
+    /// unnoticed. On Windows, directories are always listed when hidden
+    /// entries are skipped by their attributes, and walks of a
+    /// [`memory_fs`](struct.WalkDirGeneric.html#method.memory_fs) don't use
+    /// the cache.
+    pub fn listing_cache(mut self, listing_cache: Arc<dyn ListingCache>) -> Self {
+        self.options.listing_cache = Some(listing_cache);
+        self
+    }
+
+    /// Yield entries in the given `yield_order`. Defaults to
+    /// [`YieldOrder::Strict`](enum.YieldOrder.html#variant.Strict).
+    ///
+    /// [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed) is for
+    /// consumers that count or collect all entries and don't need them depth
+    /// first, but still want the entries of each directory sorted.
+    pub fn yield_order(mut self, yield_order: YieldOrder) -> Self {
+        self.options.relaxed_ordering = yield_order == YieldOrder::Relaxed;
+        self
+    }
+
+    /// Yield the entries of each directory as soon as it was read instead of
+    /// in strict depth first order. By default, this is disabled.
+    ///
+    /// Shorthand for [`yield_order`](struct.WalkDirGeneric.html#method.yield_order)
+    /// with [`YieldOrder::Relaxed`](enum.YieldOrder.html#variant.Relaxed) or
+    /// [`YieldOrder::Strict`](enum.YieldOrder.html#variant.Strict).
+    pub fn relaxed_ordering(self, relaxed_ordering: bool) -> Self {
+        self.yield_order(match relaxed_ordering {
+            true => YieldOrder::Relaxed,
+            false => YieldOrder::Strict,
+        })
+    }
+
+    /// Limit the amount of entries that were read ahead of the consumer of
+    /// the iterator. By default, the amount is unlimited.
+    ///
+    /// Parallel walks read directories as fast as possible, so a slow
+    /// consumer of a huge tree may cause a lot of entries to be buffered.
+    /// Once the limit is reached, workers pause before reading further
+    /// directories until the consumer caught up. The limit is soft: a single
+    /// directory can exceed it, and reads continue while the consumer waits
+    /// for a directory that hasn't been read yet.
+    pub fn max_pending_entries(mut self, max_pending_entries: usize) -> Self {
+        self.options.max_pending_entries = Some(max_pending_entries);
+        self
+    }
+
+    /// Move directories waiting to be read to a temporary file once more than
+    /// `spill_threshold` of them are queued. By default, they are all kept in
+    /// memory.
+    ///
+    /// Parallel walks of very wide trees can queue millions of directories.
+    /// Once the threshold is exceeded, the less urgent half of the queue is
+    /// written to a file in [`std::env::temp_dir`] and read back when the
+    /// queue in memory runs empty. Only the paths are written, any
+    /// [`ReadDirState`](trait.ClientState.html#associatedtype.ReadDirState)
+    /// stays in memory. Results waiting for the consumer aren't spilled,
+    /// use [`max_pending_entries`](struct.WalkDirGeneric.html#method.max_pending_entries)
+    /// to limit those.
+    pub fn spill_threshold(mut self, spill_threshold: usize) -> Self {
+        self.options.spill_threshold = Some(spill_threshold);
+        self
+    }
+
+    /// Limit the number of directories read at the same time from each
+    /// device. By default, there is no limit.
+    ///
+    /// Spinning disks are slower when many directories are read from them
+    /// concurrently. With a limit, workers wait before reading from a busy
+    /// device, while reads from other devices continue, so all threads are
+    /// still used when walking across devices. Finding the device of a
+    /// directory costs a `stat` call.
+    ///
+    /// Devices are only told apart on unix, elsewhere the limit applies to
+    /// all reads.
+    pub fn per_device_concurrency(mut self, per_device_concurrency: usize) -> Self {
+        self.options.per_device_concurrency = Some(per_device_concurrency);
+        self
+    }
+
+    /// Read the metadata of each entry in the worker that reads its parent
+    /// directory. By default, this is disabled.
+    ///
+    /// The metadata is cached in the entry and returned by
+    /// [`DirEntry::metadata_cached`](struct.DirEntry.html#method.metadata_cached)
+    /// without another `stat` call, also within the
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback. [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext)
+    /// reuses it, and [`prefetch_metadata`](struct.WalkDirGeneric.html#method.prefetch_metadata)
+    /// reads it in the same way as the extended metadata.
+    pub fn preload_metadata(mut self, preload_metadata: bool) -> Self {
+        self.options.preload_metadata = preload_metadata;
+        self
+    }
+
+    /// Only yield entries whose metadata passes `filter`, like files modified
+    /// in the last seven days. The metadata is read in the worker that reads
+    /// the parent directory and cached as with
+    /// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata).
+    ///
+    /// Roots and entries whose metadata can't be read are kept. Directories failing
+    /// the filter aren't yielded, but their contents are still read unless
+    /// [`filter_metadata_prunes`](struct.WalkDirGeneric.html#method.filter_metadata_prunes)
+    /// is enabled. The filter runs before
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir).
+    pub fn filter_metadata<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Path, &fs::Metadata) -> bool + Send + Sync + 'static,
+    {
+        self.options.filter_metadata = Some(Arc::new(filter));
+        self
+    }
+
+    /// Skip the contents of directories failing the
+    /// [`filter_metadata`](struct.WalkDirGeneric.html#method.filter_metadata)
+    /// filter instead of reading them. By default, this is disabled.
+    pub fn filter_metadata_prunes(mut self, filter_metadata_prunes: bool) -> Self {
+        self.options.filter_metadata_prunes = filter_metadata_prunes;
+        self
+    }
+
+    /// Load [`DirEntryExt`](struct.DirEntryExt.html) for each entry in the
+    /// worker that reads its parent directory. By default, this is disabled.
+    ///
+    /// The result is available through
+    /// [`DirEntry::ext`](struct.DirEntry.html#method.ext) and within the
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback, saving a `stat` call on the consuming thread.
+    pub fn preload_metadata_ext(mut self, preload_metadata_ext: bool) -> Self {
+        self.options.preload_metadata_ext = preload_metadata_ext;
+        self
+    }
+
+    /// Load [`DirEntryExt`](struct.DirEntryExt.html) for each entry like
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext),
+    /// which this enables, in the way given by `prefetch`.
+    ///
+    /// With [`Prefetch::Parallel`](enum.Prefetch.html#variant.Parallel),
+    /// directories with thousands of files have their entries loaded by all
+    /// threads of the rayon pool running the walk rather than by the single
+    /// worker that read them. Walks that don't run on a rayon pool, like
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) ones,
+    /// load entries serially.
+    pub fn prefetch_metadata(mut self, prefetch: Prefetch) -> Self {
+        self.options.preload_metadata_ext = true;
+        self.options.prefetch = prefetch;
+        self
+    }
+
+    /// Read the first `len` bytes of each regular file in the worker that
+    /// reads its parent directory. By default, nothing is read.
+    ///
+    /// The bytes are available through
+    /// [`DirEntry::content_prefix`](struct.DirEntry.html#method.content_prefix)
+    /// and within the
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback, to tell binary from text files or detect their type without
+    /// opening them again on the consuming thread.
+    pub fn sniff_content(mut self, len: usize) -> Self {
+        self.options.sniff_content = Some(len);
+        self
+    }
+
+    /// Process the entries of directories with more than `chunk_size`
+    /// entries in chunks of at least `chunk_size` entries, which are spread
+    /// over the threads of the rayon pool running the walk. By default, all
+    /// entries of a directory are processed by the worker that read it.
+    ///
+    /// Processing entries covers following links, loading metadata and
+    /// content and detecting hard links and visited directories, which
+    /// dominates walks of trees with a few giant directories once any of
+    /// these are enabled. The directory is still yielded in order as a
+    /// whole, and
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// is called once with all of its entries. Walks that don't run on a
+    /// rayon pool process entries serially.
+    pub fn split_large_dirs(mut self, chunk_size: usize) -> Self {
+        self.options.split_large_dirs = Some(chunk_size);
+        self
+    }
+
+    /// Sort the entries of directories with more than `threshold` entries
+    /// in parallel, spread over the threads of the rayon pool running the
+    /// walk. By default, the worker that read a directory sorts it alone.
+    ///
+    /// Sorting millions of entries otherwise blocks one worker for long,
+    /// and with it yielding everything after the directory in depth first
+    /// order. Entries end up in the same order either way. Walks that don't
+    /// run on a rayon pool sort serially.
+    pub fn parallel_sort_threshold(mut self, threshold: usize) -> Self {
+        self.options.parallel_sort_threshold = Some(threshold);
+        self
+    }
+
+    /// Detect files that were already reached through another hard link.
+    /// By default, this is disabled.
+    ///
+    /// When enabled, the device and inode of each file with more than one
+    /// link are recorded, and
+    /// [`DirEntry::is_hardlink_duplicate`](struct.DirEntry.html#method.is_hardlink_duplicate)
+    /// is set for all but the first link encountered. This costs a `stat`
+    /// call per file unless
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext)
+    /// is enabled as well.
+    ///
+    /// This is only supported on unix, elsewhere no entry is ever flagged.
+    pub fn track_hardlinks(mut self, track_hardlinks: bool) -> Self {
+        self.options.track_hardlinks = track_hardlinks;
+        self
+    }
+
+    /// Descend into each physical directory only once. By default, this is
+    /// disabled.
+    ///
+    /// With [`follow_links`](struct.WalkDirGeneric.html#method.follow_links)
+    /// enabled, distinct symlinks to the same directory cause its contents to
+    /// be walked once per link. When enabled, the device and inode of every
+    /// directory about to be read are recorded, and directories that were
+    /// already read are still yielded but not descended into again. Which
+    /// path wins isn't deterministic for parallel walks. This costs a `stat`
+    /// call per directory.
+    ///
+    /// This is only supported on unix, elsewhere all directories are read.
+    pub fn skip_visited_dirs(mut self, skip_visited_dirs: bool) -> Self {
+        self.options.skip_visited_dirs = skip_visited_dirs;
+        self
+    }
+
+    /// Build the [`full_path`](struct.DirEntry.html#method.full_path) of
+    /// each entry right after
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// ran, on the thread that read its directory. By default, this is
+    /// disabled and paths are built on first use.
+    pub fn precompute_paths(mut self, precompute_paths: bool) -> Self {
+        self.options.precompute_paths = precompute_paths;
+        self
+    }
+
+    /// Read directories through their extended-length `\\?\` form on
+    /// Windows, so that trees nested deeper than `MAX_PATH` can be walked. By
+    /// default, this is disabled.
+    ///
+    /// Yielded entries keep the paths as given, without the prefix. Methods
+    /// like [`DirEntry::metadata`](struct.DirEntry.html#method.metadata) use
+    /// those plain paths and may still fail for very long ones.
+    ///
+    /// This has no effect on other platforms.
+    pub fn long_paths(mut self, long_paths: bool) -> Self {
+        self.options.long_paths = long_paths;
+        self
+    }
+
+    /// Report directories that were modified while their contents were
+    /// yielded. By default, this is disabled.
+    ///
+    /// When enabled, the modification time of each directory is recorded
+    /// when it is read. Once all of its entries were yielded, it is checked
+    /// again and an [`Error`](struct.Error.html) for which
+    /// [`is_changed`](struct.Error.html#method.is_changed) returns `true` is
+    /// yielded if the directory was modified or removed in the meantime, so
+    /// that indexing tools know their snapshot may be inconsistent. This
+    /// costs two `stat` calls per directory.
+    ///
+    /// This only applies to [`into_iter`](struct.WalkDirGeneric.html#method.into_iter),
+    /// [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter)
+    /// doesn't report changes.
+    pub fn detect_changes(mut self, detect_changes: bool) -> Self {
+        self.options.detect_changes = detect_changes;
+        self
+    }
+
+    /// Maximum number of entries per batch yielded by
+    /// [`into_batched_iter`](struct.WalkDirGeneric.html#method.into_batched_iter).
+    /// Defaults to `1024`.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.options.batch_size = batch_size;
+        self
+    }
+
+    /// Continue a walk from a [`Checkpoint`](struct.Checkpoint.html) taken
+    /// with [`DirEntryIter::checkpoint`](struct.DirEntryIter.html#method.checkpoint),
+    /// instead of starting at the root.
+    ///
+    /// The walk should be configured like the one the checkpoint was taken
+    /// from. Pending entries are read from the filesystem again, but not
+    /// passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// a second time, so their `client_state` is reset. Their contents are
+    /// read with the
+    /// [`root_read_dir_state`](struct.WalkDirGeneric.html#method.root_read_dir_state).
+    pub fn resume_from(mut self, checkpoint: Checkpoint) -> Self {
+        self.options.resume_from = Some(checkpoint);
+        self
+    }
+
+    /// Select what the `file_name` of the root entry contains. Defaults to
+    /// [`RootName::AsGiven`](enum.RootName.html#variant.AsGiven), which is
+    /// `.` or `..` for such roots.
+    ///
+    /// Only the name changes, the
+    /// [`path`](struct.DirEntry.html#method.path) of the root entry is
+    /// always the path the walk started from, and the paths of all other
+    /// entries start with it. If the root can't be resolved its name is
+    /// kept as given.
+    pub fn root_file_name(mut self, root_file_name: RootName) -> Self {
+        self.options.root_file_name = root_file_name;
+        self
+    }
+
+    /// Canonicalize the root path before walking it. By default, this is
+    /// disabled.
+    ///
+    /// When enabled, all yielded paths are based on the absolute, canonical
+    /// form of the path given to `new`, with symbolic links in it resolved.
+    /// This is done once when the iterator is created, so later changes to
+    /// the current working directory don't affect the walk. Failing to
+    /// canonicalize the root is reported like any other error reading it.
+    pub fn canonicalize_root(mut self, canonicalize_root: bool) -> Self {
+        self.options.canonicalize_root = canonicalize_root;
+        self
+    }
+
+    /// Set the minimum depth of entries yielded by the iterator.
+    ///
+    /// The smallest depth is `0` and always corresponds to the path given
+    /// to the `new` function on this type. Its direct descendents have depth
+    /// `1`, and their descendents have depth `2`, and so on.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.options.min_depth = depth;
+        self.options.depth_conflict = None;
+        if self.options.min_depth > self.options.max_depth {
+            self.options.depth_conflict = Some((depth, self.options.max_depth));
+            self.options.min_depth = self.options.max_depth;
+        }
+        self
+    }
+
+    /// Yield the root entry at depth `0`. Defaults to `true`.
+    ///
+    /// Unlike [`min_depth(1)`](struct.WalkDirGeneric.html#method.min_depth),
+    /// which also leaves out the root, this doesn't affect any other
+    /// setting: the root is still processed by
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// and read, only not yielded. Errors reading it are yielded either way.
+    pub fn include_root(mut self, include_root: bool) -> Self {
+        self.options.include_root = include_root;
+        self
+    }
+
+    /// Set the maximum depth of entries yield by the iterator.
+    ///
+    /// The smallest depth is `0` and always corresponds to the path given
+    /// to the `new` function on this type. Its direct descendents have depth
+    /// `1`, and their descendents have depth `2`, and so on.
+    ///
+    /// A depth < 2 will automatically change `parallelism` to
+    /// `Parallelism::Serial`. Parrallelism happens at the `fs::read_dir` level.
+    /// It only makes sense to use multiple threads when reading more then one
+    /// directory.
+    ///
+    /// Note that this will not simply filter the entries of the iterator, but
+    /// it will actually avoid descending into directories when the depth is
+    /// exceeded.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = depth;
+        self.options.depth_conflict = None;
+        if self.options.max_depth < self.options.min_depth {
+            self.options.depth_conflict = Some((self.options.min_depth, depth));
+            self.options.max_depth = self.options.min_depth;
+        }
+        if self.options.max_depth < 2 {
+            self.options.parallelism_discarded |= !matches!(
+                self.options.parallelism,
+                Parallelism::Serial | Parallelism::RayonDefaultPool { .. }
+            );
+            self.options.parallelism = Parallelism::Serial;
+        }
+        self
+    }
+
+    /// Degree of parallelism to use when performing walk. Defaults to
+    /// [`Parallelism::RayonDefaultPool`](enum.Parallelism.html#variant.RayonDefaultPool).
+    pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.options.parallelism = parallelism;
+        self.options.parallelism_discarded = false;
+        self
+    }
+
+    /// Choose what happens if the thread pool is too busy to start the walk
+    /// within its `busy_timeout`, or a new pool can't be built with
+    /// [`PoolFallback::Error`](enum.PoolFallback.html#variant.Error).
+    /// Defaults to [`BusyFallback::Error`](enum.BusyFallback.html#variant.Error).
+    ///
+    /// Falling back to a serial walk lets libraries degrade gracefully
+    /// instead of passing the error on to their users.
+    pub fn busy_fallback(mut self, busy_fallback: BusyFallback) -> Self {
+        self.options.busy_fallback = busy_fallback;
+        self
+    }
+
+    /// Initial ClientState::ReadDirState that is passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// when processing root. Defaults to ClientState::ReadDirState::default().
+    pub fn root_read_dir_state(mut self, read_dir_state: C::ReadDirState) -> Self {
+        self.options.root_read_dir_state = read_dir_state;
+        self
+    }
+
+    /// A callback function to process (sort/filter/skip/state) each directory
+    /// of entries before they are yielded. Modify the given array to
+    /// sort/filter entries. Use [`entry.read_children_path =
+    /// None`](struct.DirEntry.html#field.read_children_path) to yield a
+    /// directory entry but skip reading its contents. Use
+    /// [`entry.client_state`](struct.DirEntry.html#field.client_state)
+    /// to store custom state with an entry.
+    /// Use [`entry.read_children_state`](struct.DirEntry.html#field.read_children_state)
+    /// to give a directory a read state other than its parent's.
+    pub fn process_read_dir<F>(mut self, process_by: F) -> Self
+    where
+        F: Fn(Option<usize>, &Path, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.options.process_read_dir = Some(Arc::new(
+            move |depth, path, _parent: Option<&DirEntry<C>>, read_dir_state, children| {
+                process_by(depth, path, read_dir_state, children)
+            },
+        ));
+        self.options.clone_parent = None;
+        self
+    }
+
+    /// Like [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// but passing the entry of the directory whose contents are processed
+    /// instead of its depth and path, to decide by its file type, client
+    /// state or symlink status. Roots are processed with `None`.
+    ///
+    /// The entry is a copy of the directory as the callback processing its
+    /// own directory left it, which costs a clone per directory read.
+    pub fn process_read_dir_with_parent<F>(mut self, process_by: F) -> Self
+    where
+        F: Fn(Option<&DirEntry<C>>, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
+            + Send
+            + Sync
+            + 'static,
+        C::DirEntryState: Clone,
+    {
+        self.options.process_read_dir = Some(Arc::new(
+            move |_depth, _path: &Path, parent, read_dir_state, children| {
+                process_by(parent, read_dir_state, children)
+            },
+        ));
+        self.options.clone_parent = Some(DirEntry::clone);
+        self
+    }
+
+    /// Like [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// but accepting a callback that mutates its own state, like a counter,
+    /// without wrapping it in a `Mutex` or atomics.
+    ///
+    /// Calls are serialized with a lock, so this is meant for
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) walks
+    /// and cheap callbacks. Parallel walks work as well, but their workers
+    /// wait for each other while the callback runs.
+    pub fn process_read_dir_mut<F>(self, process_by: F) -> Self
+    where
+        F: FnMut(Option<usize>, &Path, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
+            + Send
+            + 'static,
+    {
+        let process_by = Mutex::new(process_by);
+        self.process_read_dir(move |depth, path, read_dir_state, children| {
+            let mut process_by = process_by.lock().unwrap_or_else(|err| err.into_inner());
+            process_by(depth, path, read_dir_state, children)
+        })
+    }
+
+    /// A callback function called with each entry that isn't a directory,
+    /// on the thread that read its directory and right after
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// ran for it. Use it for per-file work like hashing or sniffing mime
+    /// types, storing the result in
+    /// [`entry.client_state`](struct.DirEntry.html#field.client_state).
+    ///
+    /// Files are processed in parallel as far as their directories are read
+    /// in parallel, so concurrency is bounded by the
+    /// [`parallelism`](struct.WalkDirGeneric.html#method.parallelism) of the
+    /// walk, and the files of one directory are processed one after another.
+    pub fn process_file<F>(mut self, process_by: F) -> Self
+    where
+        F: Fn(&mut DirEntry<C>) + Send + Sync + 'static,
+    {
+        self.options.process_file = Some(Arc::new(process_by));
+        self
+    }
+
+    /// A callback function called with the `std::fs::DirEntry` of each
+    /// listed entry and the entry created from it, in the worker listing
+    /// the directory, before the `std::fs::DirEntry` is dropped.
+    ///
+    /// Platform specific data like the inode number on Unix can so be
+    /// extracted once and kept in the entry's
+    /// [`client_state`](struct.DirEntry.html#structfield.client_state)
+    /// instead of opening the file again later. Entries are listed with
+    /// [`Backend::Std`](enum.Backend.html#variant.Std) while this is set, as
+    /// only it has `std::fs::DirEntry`s. It isn't called for roots, followed
+    /// links and listings taken from a
+    /// [`listing_cache`](struct.WalkDirGeneric.html#method.listing_cache).
+    pub fn inspect_raw_entry<F>(mut self, inspect_by: F) -> Self
+    where
+        F: Fn(&fs::DirEntry, &mut DirEntry<C>) + Send + Sync + 'static,
+    {
+        self.options.inspect_raw_entry = Some(Arc::new(inspect_by));
+        self
+    }
+
+    /// A callback function called with the path of each directory right
+    /// before it is read, on the thread that reads it.
+    ///
+    /// As the entries of a directory are only yielded once it was read, the
+    /// callback is called before any of them are. File watchers can register
+    /// directories here, so that changes made while the walk reads them are
+    /// reported rather than missed. Directories that aren't read, like those
+    /// at [`max_depth`](struct.WalkDirGeneric.html#method.max_depth), aren't
+    /// passed to it.
+    pub fn on_visit_dir<F>(mut self, visit_by: F) -> Self
+    where
+        F: Fn(&Path) + Send + Sync + 'static,
+    {
+        self.options.on_visit_dir = Some(Arc::new(visit_by));
+        self
+    }
+
+    /// A callback function called with the depth, path, state and final
+    /// entries of each directory once it was read, on the thread that read
+    /// it.
+    ///
+    /// It runs after
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// and all other processing, when the state the subdirectories start
+    /// from is settled, in serial and parallel walks alike. Per directory
+    /// state can be flushed here, like writing an index shard for each
+    /// directory. Roots and directories that aren't read aren't passed to
+    /// it.
+    pub fn on_read_dir_complete<F>(mut self, complete_by: F) -> Self
+    where
+        F: Fn(usize, &Path, &C::ReadDirState, &[Result<DirEntry<C>>]) + Send + Sync + 'static,
+    {
+        self.options.on_read_dir_complete = Some(Arc::new(complete_by));
+        self
+    }
+
+    /// Send errors to `errors_to` instead of yielding them, so the iterators
+    /// only yield entries and pipelines passing them on, like to
+    /// `par_bridge`, don't need to match on each result. Errors are still
+    /// counted in the [`summary`](struct.DirEntryIter.html#method.summary).
+    ///
+    /// Errors are yielded as before once the receiver was dropped. Errors
+    /// reading the contents of a directory yielded by `into_iter` stay in
+    /// its [`read_children_error`](struct.DirEntry.html#structfield.read_children_error)
+    /// and aren't sent.
+    pub fn errors_to(mut self, errors_to: Sender<Error>) -> Self {
+        self.options.errors_to = Some(errors_to);
+        self
+    }
+
+    /// End `into_iter` after yielding `max_entries` entries, and stop
+    /// reading directories in the background. By default, all entries are
+    /// yielded.
+    ///
+    /// Errors don't count towards the limit. If entries were left, the
+    /// [`summary`](struct.DirEntryIter.html#method.summary) of the iterator
+    /// is marked as
+    /// [`truncated`](struct.WalkSummary.html#structfield.truncated). Use
+    /// `take_any` to limit
+    /// [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter).
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.options.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Stop reading each directory after `max_entries_per_dir` entries, to
+    /// protect against directories with millions of files. By default,
+    /// directories are read completely.
+    ///
+    /// Hidden entries that are skipped don't count towards the limit, and
+    /// the remaining entries are left out before
+    /// [`sort`](struct.WalkDirGeneric.html#method.sort), so which are kept
+    /// depends on the order of the directory listing. Directories that were
+    /// cut short are marked with
+    /// [`read_children_truncated`](struct.DirEntry.html#structfield.read_children_truncated).
+    pub fn max_entries_per_dir(mut self, max_entries_per_dir: usize) -> Self {
+        self.options.max_entries_per_dir = Some(max_entries_per_dir);
+        self
+    }
+
+    /// Only yield a pseudo-random `ratio` of the entries that aren't
+    /// directories, like `0.01` for one in a hundred, to analyze giant trees
+    /// statistically. Directories are always yielded and read.
+    ///
+    /// Entries are picked by hashing their
+    /// [`relative_path`](struct.DirEntry.html#method.relative_path) with
+    /// `seed`, so the same `seed` picks the same entries in every walk of a
+    /// tree, wherever it is located. The workers drop the other entries
+    /// before [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// sees them. `ratio` is clamped to `0.0..=1.0`.
+    pub fn sample(mut self, ratio: f64, seed: u64) -> Self {
+        self.options.sample = Some((ratio.clamp(0.0, 1.0), seed));
+        self
+    }
+
+    /// Have `into_iter` yield the entries one level after another: the walk
+    /// first goes to depth 1, then starts over and goes to depth 2 yielding
+    /// only the entries found there, and so on until no directories are
+    /// left or [`max_depth`](struct.WalkDirGeneric.html#method.max_depth)
+    /// is reached. By default, this is disabled.
+    ///
+    /// Tree views can so show shallow levels right away and refine them as
+    /// deeper levels arrive. Each level is walked in the usual order, and
+    /// directories listed in earlier rounds are taken from the
+    /// [`listing_cache`](struct.WalkDirGeneric.html#method.listing_cache),
+    /// or a cache of the walk if none is set, so only the new level is
+    /// listed. Callbacks like
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// run again for each directory in each round, while
+    /// [`on_visit_dir`](struct.WalkDirGeneric.html#method.on_visit_dir) and
+    /// [`detect_changes`](struct.WalkDirGeneric.html#method.detect_changes)
+    /// only apply to the round reading a directory first. Errors are yielded
+    /// once, and hard links and visited directories are tracked across all
+    /// rounds.
+    pub fn iterative_deepening(mut self, iterative_deepening: bool) -> Self {
+        self.options.iterative_deepening = iterative_deepening;
+        self
+    }
+}
 
 fn process_dir_entry_result<C: ClientState>(
     dir_entry_result: Result<DirEntry<C>>,
-    follow_links: bool,
+    follow_link: Option<&FollowLink<C>>,
+    preload_metadata: bool,
+    preload_metadata_ext: bool,
+    seen_hardlinks: Option<&Mutex<HashSet<(u64, u64)>>>,
+    seen_dirs: Option<&Mutex<HashMap<(u64, u64), PathBuf>>>,
+    broken_symlink_behavior: BrokenSymlinkBehavior,
+) -> Option<Result<DirEntry<C>>> {
+    match dir_entry_result {
+        Ok(mut dir_entry) => {
+            let target = match follow_link {
+                Some(follow_link) if dir_entry.file_type.is_symlink() => {
+                    follow_link.follow(&dir_entry)
+                }
+                _ => None,
+            };
+            match target {
+                Some(Ok(target_dir_entry)) => dir_entry = target_dir_entry,
+                Some(Err(err)) if is_broken_symlink_error(&err) => match broken_symlink_behavior {
+                    BrokenSymlinkBehavior::Error => return Some(Err(err)),
+                    BrokenSymlinkBehavior::Yield => {}
+                    BrokenSymlinkBehavior::Skip => return None,
+                },
+                Some(Err(err)) => return Some(Err(err)),
+                None => {}
+            }
+
+            if preload_metadata {
+                // Errors are cached as well, and returned when it is used.
+                let _ = dir_entry.metadata_cached();
+            }
+
+            if preload_metadata_ext {
+                dir_entry.preload_ext();
+            }
+
+            if let Some(seen_hardlinks) = seen_hardlinks {
+                dir_entry.track_hardlink(seen_hardlinks);
+            }
+
+            if let Some(seen_dirs) = seen_dirs {
+                dir_entry.skip_if_visited(seen_dirs);
+            }
+
+            Some(Ok(dir_entry))
+        }
+        Err(err) => Some(Err(err)),
+    }
+}
+
+// As a special case, the contents of a root link to a directory are read
+// even if it isn't followed. We are careful to not let this change the
+// semantics of the DirEntry however. Namely, the DirEntry should still
+// respect the follow_links setting. When it's disabled, it should report
+// itself as a symlink. When it's enabled, it should always report itself as
+// the target.
+fn read_root_link<C: ClientState>(
+    root_entry_result: Result<DirEntry<C>>,
+    follow_link: Option<&FollowLink<C>>,
 ) -> Result<DirEntry<C>> {
-    match dir_entry_result {
-        Ok(mut dir_entry) => {
-            if follow_links && dir_entry.file_type.is_symlink() {
-                dir_entry = dir_entry.follow_symlink()?;
+    let mut root_entry = root_entry_result?;
+    if !root_entry.file_type.is_symlink() {
+        return Ok(root_entry);
+    }
+    let may_follow = follow_link.is_some_and(|f| (f.may_follow)(&root_entry));
+    let metadata = match fs::metadata(root_entry.path()) {
+        Ok(metadata) => metadata,
+        // Left to following it, to be handled by `BrokenSymlinkBehavior`.
+        Err(_) if may_follow => return Ok(root_entry),
+        Err(err) => {
+            return Err(Error::from_path(
+                0,
+                root_entry.path(),
+                Operation::Metadata,
+                err,
+            ))
+        }
+    };
+    let follows = may_follow
+        && follow_link.is_some_and(|f| f.symlink_policy.follows_target(metadata.is_dir()));
+    if !follows && metadata.is_dir() {
+        root_entry.read_children_path = Some(Arc::from(root_entry.path()));
+    }
+    Ok(root_entry)
+}
+
+// The target of a followed symlink doesn't exist.
+fn is_broken_symlink_error(err: &Error) -> bool {
+    err.operation() == Operation::Metadata
+        && err
+            .io_error()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+}
+
+fn process_files<C: ClientState>(
+    process_file: &ProcessFileFunction<C>,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for dir_entry in dir_entry_results.iter_mut().flatten() {
+        if !dir_entry.file_type.is_dir() {
+            process_file(dir_entry);
+        }
+    }
+}
+
+// Drop entries of types that aren't wanted, or only mark them if their
+// contents are still to be read.
+fn filter_file_types<C: ClientState>(
+    file_types: FileTypes,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    dir_entry_results.retain_mut(|dir_entry_result| match dir_entry_result {
+        Ok(dir_entry) if !file_types.matches(dir_entry.file_type) => {
+            dir_entry.is_filtered = true;
+            dir_entry.read_children_path.is_some()
+        }
+        _ => true,
+    });
+}
+
+// Drop entries whose metadata fails the filter, or only mark them if their
+// contents are still to be read.
+fn filter_by_metadata<C: ClientState>(
+    filter_metadata: &FilterMetadataFunction,
+    prune: bool,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    dir_entry_results.retain_mut(|dir_entry_result| match dir_entry_result {
+        Ok(dir_entry) => {
+            let passes = dir_entry.metadata_cached().map_or(true, |metadata| {
+                filter_metadata(&dir_entry.path(), metadata)
+            });
+            if passes {
+                return true;
+            }
+            dir_entry.is_filtered = true;
+            !prune && dir_entry.read_children_path.is_some()
+        }
+        Err(_) => true,
+    });
+}
+
+// Number the results that are yielded, entries only kept to read their
+// contents aren't.
+fn number_siblings<C: ClientState>(dir_entry_results: &mut [Result<DirEntry<C>>]) {
+    let sibling_count = dir_entry_results
+        .iter()
+        .filter(
+            |dir_entry_result| !matches!(dir_entry_result, Ok(dir_entry) if dir_entry.is_filtered),
+        )
+        .count();
+    let mut sibling_index = 0;
+    for dir_entry_result in dir_entry_results.iter_mut() {
+        match dir_entry_result {
+            Ok(dir_entry) if dir_entry.is_filtered => continue,
+            Ok(dir_entry) => {
+                dir_entry.sibling_index = sibling_index;
+                dir_entry.sibling_count = sibling_count;
             }
+            Err(_) => {}
+        }
+        sibling_index += 1;
+    }
+}
 
-            if dir_entry.depth == 0 && dir_entry.file_type.is_symlink() {
-                // As a special case, if we are processing a root entry, then we
-                // always follow it even if it's a symlink and follow_links is
-                // false. We are careful to not let this change the semantics of
-                // the DirEntry however. Namely, the DirEntry should still
-                // respect the follow_links setting. When it's disabled, it
-                // should report itself as a symlink. When it's enabled, it
-                // should always report itself as the target.
-                let metadata = fs::metadata(dir_entry.path())
-                    .map_err(|err| Error::from_path(0, dir_entry.path(), err))?;
-                if metadata.file_type().is_dir() {
-                    dir_entry.read_children_path = Some(Arc::from(dir_entry.path()));
+// Load the metadata of entries in parallel chunks on the current rayon pool.
+// Links that are followed are left to `process_dir_entry_result`, as it
+// replaces them with their target.
+fn prefetch_ext<C: ClientState>(
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+    batch: usize,
+    follow_links: bool,
+    preload_metadata: bool,
+) {
+    if rayon::current_thread_index().is_none() {
+        return;
+    }
+    dir_entry_results
+        .par_chunks_mut(batch.max(1))
+        .for_each(|chunk| {
+            for dir_entry in chunk.iter_mut().flatten() {
+                if !(follow_links && dir_entry.file_type.is_symlink()) {
+                    if preload_metadata {
+                        let _ = dir_entry.metadata_cached();
+                    }
+                    dir_entry.preload_ext();
                 }
             }
+        });
+}
 
-            Ok(dir_entry)
+fn sniff_contents<C: ClientState>(dir_entry_results: &mut [Result<DirEntry<C>>], len: usize) {
+    for dir_entry in dir_entry_results.iter_mut().flatten() {
+        dir_entry.sniff_content(len);
+    }
+}
+
+fn set_clone_parent<C: ClientState>(
+    clone_parent: CloneParentFunction<C>,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for dir_entry in dir_entry_results.iter_mut().flatten() {
+        if dir_entry.read_children_path.is_some() {
+            dir_entry.clone_parent = Some(clone_parent);
+        }
+    }
+}
+
+fn precompute_full_paths<C: ClientState>(dir_entry_results: &[Result<DirEntry<C>>]) {
+    for dir_entry in dir_entry_results.iter().flatten() {
+        dir_entry.full_path();
+    }
+}
+
+// Everything needed to start a walk, shared by the sequential and parallel
+// iterators.
+struct WalkParts<C: ClientState> {
+    root_entry_results: Vec<Result<DirEntry<C>>>,
+    root_read_dir_state: C::ReadDirState,
+    parallelism: Parallelism,
+    busy_fallback: BusyFallback,
+    min_depth: usize,
+    relaxed_ordering: bool,
+    queue_limits: QueueLimits,
+    errors_to: Option<Sender<Error>>,
+    max_entries: Option<usize>,
+    core_read_dir_callback: Arc<ReadDirCallback<C>>,
+}
+
+impl<C: ClientState> Clone for WalkDirGeneric<C> {
+    fn clone(&self) -> WalkDirGeneric<C> {
+        WalkDirGeneric {
+            root: self.root.clone(),
+            options: self.options.clone(),
         }
-        Err(err) => Err(err),
     }
 }
 
@@ -392,46 +2309,285 @@ impl<C: ClientState> IntoIterator for WalkDirGeneric<C> {
     type IntoIter = DirEntryIter<C>;
 
     fn into_iter(self) -> DirEntryIter<C> {
+        if self.options.iterative_deepening {
+            return Deepening::start(self);
+        }
+        let parts = self.into_parts();
+        let mut dir_entry_iter = DirEntryIter::new(
+            parts.root_entry_results,
+            parts.parallelism,
+            parts.min_depth,
+            parts.relaxed_ordering,
+            parts.queue_limits,
+            parts.root_read_dir_state.clone(),
+            parts.core_read_dir_callback.clone(),
+        );
+        dir_entry_iter.fall_back_when_busy(
+            parts.busy_fallback,
+            &parts.root_read_dir_state,
+            parts.queue_limits,
+            parts.core_read_dir_callback,
+        );
+        dir_entry_iter.errors_to = parts.errors_to;
+        dir_entry_iter.max_entries = parts.max_entries;
+        dir_entry_iter
+    }
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    fn into_parts(mut self) -> WalkParts<C> {
         let sort = self.options.sort;
+        let group_order = self.options.group_order;
+        let file_type_filter = match self.options.dirs_only {
+            // Drops what the listing kept to check it, like links to files.
+            true => Some(FileTypes {
+                files: false,
+                dirs: self
+                    .options
+                    .file_type_filter
+                    .is_none_or(|filter| filter.dirs),
+                symlinks: false,
+                other: false,
+            }),
+            false => self.options.file_type_filter,
+        };
+        let dirs_only = self.options.dirs_only;
         let max_depth = self.options.max_depth;
         let min_depth = self.options.min_depth;
         let parallelism = self.options.parallelism;
         let skip_hidden = self.options.skip_hidden;
-        let follow_links = self.options.follow_links;
+        let hidden_policy = self.options.hidden_policy;
+        let skip_hidden_by = self.options.skip_hidden_by.clone();
+        let symlink_policy = self.options.symlink_policy;
+        let follow_links = symlink_policy.follows_any();
+        // Whether a symlink is followed, `None` if none are.
+        let follow_link: Option<Arc<FollowLink<C>>> = if follow_links {
+            let from_depth = self.options.follow_links_from_depth;
+            let filter = self.options.follow_link_filter.clone();
+            Some(Arc::new(FollowLink {
+                may_follow: Box::new(move |dir_entry: &DirEntry<C>| {
+                    dir_entry.depth >= from_depth
+                        && filter.as_ref().is_none_or(|f| f(dir_entry))
+                        && symlink_policy.may_follow(&dir_entry.path())
+                }),
+                symlink_policy,
+            }))
+        } else {
+            None
+        };
+        let root_link_behavior = self.options.root_link_behavior;
+        let root_follow_link = match root_link_behavior {
+            RootLinkBehavior::FollowNever => None,
+            _ => follow_link.clone(),
+        };
+        let broken_symlink_behavior = self.options.broken_symlink_behavior;
+        let loop_detection = self.options.loop_detection;
+        let inspect_raw_entry = self.options.inspect_raw_entry.clone();
+        let backend = match inspect_raw_entry {
+            Some(_) => Backend::Std,
+            None => self.options.backend,
+        };
+        let relaxed_ordering = self.options.relaxed_ordering;
+        let queue_limits = QueueLimits {
+            max_pending_entries: self.options.max_pending_entries,
+            spill_threshold: self.options.spill_threshold,
+        };
+        let device_limiter = self
+            .options
+            .per_device_concurrency
+            .map(|max| Arc::new(DeviceLimiter::new(max)));
+        let adaptive_limiter = match parallelism {
+            Parallelism::Adaptive { min, max } => Some(Arc::new(AdaptiveLimiter::new(min, max))),
+            _ => None,
+        };
+        let preload_metadata = self.options.preload_metadata;
+        let preload_metadata_ext =
+            self.options.preload_metadata_ext || sort == Sort::MTimeDescending;
+        let memory_fs = self.options.memory_fs.clone();
+        let listing_cache = self.options.listing_cache.clone();
+        let diff_snapshot = self.options.diff_snapshot.clone();
+        let root_file_name = self.options.root_file_name;
+        let prefetch = self.options.prefetch;
+        let sniff_content = self.options.sniff_content;
+        let split_large_dirs = self.options.split_large_dirs;
+        let parallel_sort_threshold = self.options.parallel_sort_threshold;
+        let precompute_paths = self.options.precompute_paths;
+        let clone_parent = self.options.clone_parent;
+        let long_paths = self.options.long_paths;
+        let detect_changes = self.options.detect_changes;
+        // Rounds of an iterative deepening walk share what was seen.
+        let round_state = self.options.round_state.take().unwrap_or_default();
+        let read_depth = round_state.read_depth;
+        let seen_hardlinks = self
+            .options
+            .track_hardlinks
+            .then_some(round_state.seen_hardlinks);
+        let seen_dirs = self
+            .options
+            .skip_visited_dirs
+            .then_some(round_state.seen_dirs);
         let process_read_dir = self.options.process_read_dir.clone();
+        let process_file = self.options.process_file.clone();
+        let on_visit_dir = self.options.on_visit_dir.clone();
+        let on_read_dir_complete = self.options.on_read_dir_complete.clone();
+        let enrich_entry = self.options.enrich_entry.clone();
+        #[cfg(all(
+            feature = "fast_readdir",
+            any(target_os = "linux", target_os = "macos")
+        ))]
+        let root_dir = self.options.root_dir.clone();
+        let max_entries_per_dir = self.options.max_entries_per_dir;
+        let sample = self.options.sample;
+        let filter_metadata = self.options.filter_metadata.clone();
+        let filter_metadata_prunes = self.options.filter_metadata_prunes;
+        let pruner = (!self.options.prune_paths.is_empty() || !self.options.prune_names.is_empty())
+            .then(|| {
+                Arc::new(Pruner::new(
+                    &self.root,
+                    &self.options.prune_paths,
+                    &self.options.prune_names,
+                ))
+            });
         let mut root_read_dir_state = self.options.root_read_dir_state;
-        let follow_link_ancestors = if follow_links {
-            Arc::new(vec![Arc::from(self.root.clone()) as Arc<Path>])
+        let mut root_entry_results = if let Some(checkpoint) = self.options.resume_from.take() {
+            // Pending entries went through `process_read_dir` before the
+            // checkpoint was taken
+            checkpoint
+                .pending
+                .into_iter()
+                .filter_map(|pending| {
+                    let mut dir_entry =
+                        DirEntry::from_path(pending.depth, &pending.path, false, Arc::new(vec![]));
+                    let follow_link = match pending.depth {
+                        0 => root_follow_link.as_deref(),
+                        _ => follow_link.as_deref(),
+                    };
+                    if pending.depth == 0 && root_link_behavior == RootLinkBehavior::FollowAlways {
+                        dir_entry = read_root_link(dir_entry, follow_link);
+                    }
+                    let dir_entry_result = process_dir_entry_result(
+                        dir_entry,
+                        follow_link,
+                        preload_metadata,
+                        preload_metadata_ext,
+                        seen_hardlinks.as_deref(),
+                        seen_dirs.as_deref(),
+                        broken_symlink_behavior,
+                    )?;
+                    Some(dir_entry_result.map(|mut dir_entry| {
+                        if !pending.read_children {
+                            dir_entry.read_children_path = None;
+                        }
+                        dir_entry
+                    }))
+                })
+                .collect()
         } else {
-            Arc::new(vec![])
+            let roots = if self.options.glob_root {
+                expand_glob(&self.root)
+            } else {
+                vec![self.root.clone()]
+            };
+            let mut root_entry_results = Vec::with_capacity(roots.len());
+            for root in roots {
+                let root = if self.options.canonicalize_root {
+                    fs::canonicalize(&root)
+                        .map_err(|err| Error::from_path(0, root, Operation::Canonicalize, err))
+                } else {
+                    Ok(root)
+                };
+                // The root becomes an ancestor of links once its contents are read.
+                let root_entry = root.and_then(|root| {
+                    match (
+                        self.options.memory_fs.as_ref(),
+                        self.options.root_dir.as_ref(),
+                    ) {
+                        (Some(memory_fs), _) => memory_fs.root_entry(&root),
+                        (None, Some(root_dir)) => root_dir.root_entry(),
+                        (None, None) => DirEntry::from_path(0, &root, false, Arc::new(vec![])).map(
+                            |mut root_entry| {
+                                root_entry.set_root_file_name(root_file_name);
+                                root_entry
+                            },
+                        ),
+                    }
+                });
+                let root_parent_path = root_entry
+                    .as_ref()
+                    .map(|root| root.parent_path().to_owned())
+                    .unwrap_or_default();
+                let root_entry = match root_link_behavior {
+                    RootLinkBehavior::FollowAlways => {
+                        read_root_link(root_entry, root_follow_link.as_deref())
+                    }
+                    _ => root_entry,
+                };
+                let mut dir_entry_results: Vec<_> = process_dir_entry_result(
+                    root_entry,
+                    root_follow_link.as_deref(),
+                    preload_metadata,
+                    preload_metadata_ext,
+                    seen_hardlinks.as_deref(),
+                    seen_dirs.as_deref(),
+                    broken_symlink_behavior,
+                )
+                .into_iter()
+                .collect();
+                if let Some(len) = sniff_content {
+                    sniff_contents(&mut dir_entry_results, len);
+                }
+                if let Some(process_read_dir) = process_read_dir.as_ref() {
+                    process_read_dir(
+                        None,
+                        &root_parent_path,
+                        None,
+                        &mut root_read_dir_state,
+                        &mut dir_entry_results,
+                    );
+                }
+                root_entry_results.extend(dir_entry_results);
+            }
+            root_entry_results
         };
-
-        let root_entry = DirEntry::from_path(0, &self.root, false, follow_link_ancestors);
-        let root_parent_path = root_entry
-            .as_ref()
-            .map(|root| root.parent_path().to_owned())
-            .unwrap_or_default();
-        let mut root_entry_results = vec![process_dir_entry_result(root_entry, follow_links)];
-        if let Some(process_read_dir) = process_read_dir.as_ref() {
-            process_read_dir(
-                None,
-                &root_parent_path,
-                &mut root_read_dir_state,
-                &mut root_entry_results,
-            );
+        if let Some(file_types) = file_type_filter {
+            filter_file_types(file_types, &mut root_entry_results);
+        }
+        number_siblings(&mut root_entry_results);
+        if !self.options.include_root {
+            for dir_entry in root_entry_results.iter_mut().flatten() {
+                // Read like entries rejected by the file type filter
+                dir_entry.is_filtered |= dir_entry.depth == 0;
+            }
+        }
+        if let Some(process_file) = process_file.as_ref() {
+            process_files(process_file.as_ref(), &mut root_entry_results);
+        }
+        if let Some(clone_parent) = clone_parent {
+            set_clone_parent(clone_parent, &mut root_entry_results);
+        }
+        if precompute_paths {
+            precompute_full_paths(&root_entry_results);
         }
 
-        DirEntryIter::new(
+        WalkParts {
             root_entry_results,
+            root_read_dir_state,
             parallelism,
+            busy_fallback: self.options.busy_fallback,
             min_depth,
-            root_read_dir_state,
-            Arc::new(move |read_dir_spec| {
+            relaxed_ordering,
+            queue_limits,
+            errors_to: self.options.errors_to,
+            max_entries: self.options.max_entries,
+            core_read_dir_callback: Arc::new(move |read_dir_spec| {
                 let ReadDirSpec {
                     path,
                     depth,
                     mut client_read_state,
                     mut follow_link_ancestors,
+                    symlink_chain,
+                    ref parent,
+                    ..
                 } = read_dir_spec;
 
                 let read_dir_depth = depth;
@@ -444,116 +2600,869 @@ impl<C: ClientState> IntoIterator for WalkDirGeneric<C> {
                 follow_link_ancestors = if follow_links {
                     let mut ancestors = Vec::with_capacity(follow_link_ancestors.len() + 1);
                     ancestors.extend(follow_link_ancestors.iter().cloned());
-                    ancestors.push(path.clone());
+                    ancestors.push(LinkAncestor::new(path.clone(), loop_detection));
                     Arc::new(ancestors)
                 } else {
                     follow_link_ancestors
                 };
 
-                let mut dir_entry_results: Vec<_> = fs::read_dir(path.as_ref())
-                    .map_err(|err| Error::from_path(0, path.to_path_buf(), err))?
-                    .filter_map(|dir_entry_result| {
-                        let fs_dir_entry = match dir_entry_result {
-                            Ok(fs_dir_entry) => fs_dir_entry,
-                            Err(err) => {
-                                return Some(Err(Error::from_io(read_dir_contents_depth, err)))
-                            }
-                        };
+                // Earlier rounds of iterative deepening visited it already.
+                let first_read = read_dir_depth >= read_depth;
+
+                if let Some(on_visit_dir) = on_visit_dir.as_ref().filter(|_| first_read) {
+                    on_visit_dir(&path);
+                }
+
+                let read_dir_path = if long_paths {
+                    extended_length_path(&path)
+                } else {
+                    Cow::Borrowed(path.as_ref())
+                };
 
-                        let dir_entry = match DirEntry::from_entry(
+                let snapshot = if detect_changes && first_read {
+                    ReadDirSnapshot::take(read_dir_depth, path.clone(), &read_dir_path)
+                } else {
+                    None
+                };
+
+                let mut adaptive_permit = adaptive_limiter
+                    .as_ref()
+                    .map(|adaptive_limiter| adaptive_limiter.acquire());
+                let device_permit = device_limiter
+                    .as_ref()
+                    .map(|device_limiter| device_limiter.acquire(&read_dir_path));
+
+                let hidden_policy = if skip_hidden && skip_hidden_by.is_none() {
+                    Some(hidden_policy)
+                } else {
+                    None
+                };
+                let list_options = ListOptions {
+                    hidden_policy,
+                    preload_metadata_ext,
+                    // Cached listings must be complete for other walks.
+                    dirs_only: dirs_only && listing_cache.is_none(),
+                };
+                let list = || match backend {
+                    #[cfg(all(
+                        feature = "fast_readdir",
+                        any(target_os = "linux", target_os = "macos")
+                    ))]
+                    Backend::DirFd => list_dir_fd(
+                        &path,
+                        // Roots are listed relative to the handle they stand for
+                        read_dir_spec.parent_dir.as_deref().or(root_dir.as_deref()),
+                        read_dir_contents_depth,
+                        &follow_link_ancestors,
+                        list_options,
+                    ),
+                    _ => (
+                        list_dir(
+                            backend,
+                            &path,
+                            &read_dir_path,
                             read_dir_contents_depth,
-                            path.clone(),
-                            &fs_dir_entry,
-                            follow_link_ancestors.clone(),
-                        ) {
-                            Ok(dir_entry) => dir_entry,
+                            &follow_link_ancestors,
+                            list_options,
+                            inspect_raw_entry.as_ref(),
+                        ),
+                        None,
+                    ),
+                };
+                let (listed_entries, dir_fd) = match (memory_fs.as_ref(), listing_cache.as_ref()) {
+                    (Some(memory_fs), _) => (
+                        memory_fs.list_dir(
+                            &path,
+                            read_dir_contents_depth,
+                            &follow_link_ancestors,
+                            hidden_policy.is_some(),
+                        ),
+                        None,
+                    ),
+                    (None, Some(listing_cache))
+                        if hidden_policy.is_none_or(HiddenPolicy::hides_by_name_only) =>
+                    {
+                        list_dir_cached(
+                            listing_cache.as_ref(),
+                            &path,
+                            &read_dir_path,
+                            read_dir_contents_depth,
+                            &follow_link_ancestors,
+                            hidden_policy.is_some(),
+                            list,
+                        )
+                    }
+                    (None, _) => list(),
+                };
+                if let (Some(adaptive_permit), Err(err)) =
+                    (adaptive_permit.as_mut(), listed_entries.as_ref())
+                {
+                    adaptive_permit.set_error(err);
+                }
+                let pruned_dir = pruner.as_ref().map(|pruner| {
+                    let root = path.ancestors().nth(read_dir_depth).unwrap_or(&path);
+                    pruner.dir(path.strip_prefix(root).unwrap_or(&path))
+                });
+                let mut listed_entries: Vec<_> = listed_entries?
+                    .filter_map(|listed_entry| {
+                        let (mut dir_entry, is_hidden) = match listed_entry {
+                            Ok(listed_entry) => listed_entry,
                             Err(err) => return Some(Err(err)),
                         };
 
-                        if skip_hidden && is_hidden(&dir_entry.file_name) {
-                            return None;
+                        if let Some(pruned_dir) = pruned_dir.as_ref() {
+                            if pruned_dir.prunes(&dir_entry.file_name) {
+                                return None;
+                            }
+                        }
+
+                        if skip_hidden {
+                            let is_hidden = match skip_hidden_by.as_ref() {
+                                Some(is_hidden) => is_hidden(&dir_entry.file_name, &path),
+                                None => is_hidden,
+                            };
+                            if is_hidden {
+                                return None;
+                            }
                         }
 
-                        Some(process_dir_entry_result(Ok(dir_entry), follow_links))
+                        dir_entry.symlink_chain = symlink_chain.clone();
+                        Some(Ok(dir_entry))
                     })
+                    // One more than the limit tells if there were more.
+                    .take(max_entries_per_dir.map_or(usize::MAX, |max| max.saturating_add(1)))
                     .collect();
+                let truncated = match max_entries_per_dir {
+                    Some(max) if listed_entries.len() > max => {
+                        listed_entries.truncate(max);
+                        true
+                    }
+                    _ => false,
+                };
+
+                if let (true, Prefetch::Parallel { batch }) = (preload_metadata_ext, prefetch) {
+                    prefetch_ext(&mut listed_entries, batch, follow_links, preload_metadata);
+                }
+
+                let process = |dir_entry_result| {
+                    let mut dir_entry_result = process_dir_entry_result(
+                        dir_entry_result,
+                        follow_link.as_deref(),
+                        preload_metadata,
+                        preload_metadata_ext,
+                        seen_hardlinks.as_deref(),
+                        seen_dirs.as_deref(),
+                        broken_symlink_behavior,
+                    )?;
+                    if let (Some(len), Ok(dir_entry)) = (sniff_content, dir_entry_result.as_mut()) {
+                        dir_entry.sniff_content(len);
+                    }
+                    Some(dir_entry_result)
+                };
+                let mut dir_entry_results: Vec<_> = match split_large_dirs {
+                    // Chunks are only spread over the pool the walk runs on.
+                    Some(chunk_size)
+                        if listed_entries.len() > chunk_size
+                            && rayon::current_thread_index().is_some() =>
+                    {
+                        listed_entries
+                            .into_par_iter()
+                            .with_min_len(chunk_size.max(1))
+                            .filter_map(process)
+                            .collect()
+                    }
+                    _ => listed_entries.into_iter().filter_map(process).collect(),
+                };
+                drop(device_permit);
+                // Released here, timing the read and loading of the entries.
+                if let Some(mut adaptive_permit) = adaptive_permit {
+                    adaptive_permit.set_entries(dir_entry_results.len());
+                }
+
+                if let Some((ratio, seed)) = sample {
+                    dir_entry_results.retain(|dir_entry_result| match dir_entry_result {
+                        Ok(dir_entry) if dir_entry.read_children_path.is_none() => {
+                            is_sampled(dir_entry, ratio, seed)
+                        }
+                        _ => true,
+                    });
+                }
+
+                if let Some(filter_metadata) = filter_metadata.as_ref() {
+                    filter_by_metadata(
+                        filter_metadata.as_ref(),
+                        filter_metadata_prunes,
+                        &mut dir_entry_results,
+                    );
+                }
 
-                if sort {
-                    dir_entry_results.sort_by(|a, b| match (a, b) {
-                        (Ok(a), Ok(b)) => a.file_name.cmp(&b.file_name),
+                if sort != Sort::Unsorted || group_order != GroupOrder::Mixed {
+                    let compare = |a: &Result<DirEntry<C>>, b: &Result<DirEntry<C>>| match (a, b) {
+                        (Ok(a), Ok(b)) => {
+                            group_order.compare(a, b).then_with(|| sort.compare(a, b))
+                        }
                         (Ok(_), Err(_)) => Ordering::Less,
                         (Err(_), Ok(_)) => Ordering::Greater,
                         (Err(_), Err(_)) => Ordering::Equal,
-                    });
+                    };
+                    match parallel_sort_threshold {
+                        // Both sorts are stable, so the order is the same.
+                        Some(threshold)
+                            if dir_entry_results.len() > threshold
+                                && rayon::current_thread_index().is_some() =>
+                        {
+                            dir_entry_results.par_sort_by(compare)
+                        }
+                        _ => dir_entry_results.sort_by(compare),
+                    }
                 }
 
                 if let Some(process_read_dir) = process_read_dir.as_ref() {
                     process_read_dir(
                         Some(read_dir_depth),
                         path.as_ref(),
+                        parent.as_deref(),
                         &mut client_read_state,
                         &mut dir_entry_results,
                     );
                 }
+                if let Some(file_types) = file_type_filter {
+                    filter_file_types(file_types, &mut dir_entry_results);
+                }
+                number_siblings(&mut dir_entry_results);
+
+                if let Some(process_file) = process_file.as_ref() {
+                    process_files(process_file.as_ref(), &mut dir_entry_results);
+                }
+
+                if let Some(diff_snapshot) = diff_snapshot.as_ref() {
+                    diff_snapshot.diff_read_dir(
+                        &path,
+                        read_dir_contents_depth,
+                        &mut dir_entry_results,
+                        |file_type| file_type_filter.is_none_or(|filter| filter.matches(file_type)),
+                    );
+                }
+
+                if let Some(clone_parent) = clone_parent {
+                    set_clone_parent(clone_parent, &mut dir_entry_results);
+                }
+
+                if precompute_paths {
+                    precompute_full_paths(&dir_entry_results);
+                }
+
+                if let Some(enrich_entry) = enrich_entry.as_ref() {
+                    for dir_entry in dir_entry_results.iter().flatten() {
+                        if !dir_entry.is_filtered {
+                            enrich_entry(dir_entry);
+                        }
+                    }
+                }
+
+                if let Some(on_read_dir_complete) = on_read_dir_complete.as_ref() {
+                    on_read_dir_complete(
+                        read_dir_depth,
+                        &path,
+                        &client_read_state,
+                        &dir_entry_results,
+                    );
+                }
 
-                Ok(ReadDir::new(client_read_state, dir_entry_results))
+                Ok(ReadDir::new(client_read_state, dir_entry_results)
+                    .with_snapshot(snapshot)
+                    .with_truncated(truncated)
+                    .with_dir_fd(dir_fd))
             }),
-        )
+        }
     }
 }
 
 impl<C: ClientState> Clone for WalkDirOptions<C> {
     fn clone(&self) -> WalkDirOptions<C> {
         WalkDirOptions {
-            sort: false,
+            sort: self.sort,
+            group_order: self.group_order,
+            file_type_filter: self.file_type_filter,
+            dirs_only: self.dirs_only,
             min_depth: self.min_depth,
             max_depth: self.max_depth,
+            include_root: self.include_root,
+            depth_conflict: self.depth_conflict,
+            parallelism_discarded: self.parallelism_discarded,
             skip_hidden: self.skip_hidden,
-            follow_links: self.follow_links,
+            hidden_policy: self.hidden_policy,
+            skip_hidden_by: self.skip_hidden_by.clone(),
+            symlink_policy: self.symlink_policy,
+            follow_links_from_depth: self.follow_links_from_depth,
+            follow_link_filter: self.follow_link_filter.clone(),
+            root_link_behavior: self.root_link_behavior,
+            loop_detection: self.loop_detection,
+            broken_symlink_behavior: self.broken_symlink_behavior,
+            backend: self.backend,
+            relaxed_ordering: self.relaxed_ordering,
+            max_pending_entries: self.max_pending_entries,
+            spill_threshold: self.spill_threshold,
+            per_device_concurrency: self.per_device_concurrency,
+            preload_metadata: self.preload_metadata,
+            preload_metadata_ext: self.preload_metadata_ext,
+            prefetch: self.prefetch,
+            sniff_content: self.sniff_content,
+            split_large_dirs: self.split_large_dirs,
+            parallel_sort_threshold: self.parallel_sort_threshold,
+            track_hardlinks: self.track_hardlinks,
+            skip_visited_dirs: self.skip_visited_dirs,
+            precompute_paths: self.precompute_paths,
+            long_paths: self.long_paths,
+            detect_changes: self.detect_changes,
+            batch_size: self.batch_size,
+            resume_from: self.resume_from.clone(),
+            canonicalize_root: self.canonicalize_root,
+            root_file_name: self.root_file_name,
+            glob_root: self.glob_root,
+            memory_fs: self.memory_fs.clone(),
+            listing_cache: self.listing_cache.clone(),
+            diff_snapshot: self.diff_snapshot.clone(),
+            root_dir: self.root_dir.clone(),
             parallelism: self.parallelism.clone(),
+            busy_fallback: self.busy_fallback,
             root_read_dir_state: self.root_read_dir_state.clone(),
             process_read_dir: self.process_read_dir.clone(),
+            clone_parent: self.clone_parent,
+            process_file: self.process_file.clone(),
+            inspect_raw_entry: self.inspect_raw_entry.clone(),
+            on_visit_dir: self.on_visit_dir.clone(),
+            on_read_dir_complete: self.on_read_dir_complete.clone(),
+            enrich_entry: self.enrich_entry.clone(),
+            errors_to: self.errors_to.clone(),
+            max_entries: self.max_entries,
+            max_entries_per_dir: self.max_entries_per_dir,
+            sample: self.sample,
+            filter_metadata: self.filter_metadata.clone(),
+            filter_metadata_prunes: self.filter_metadata_prunes,
+            prune_paths: self.prune_paths.clone(),
+            prune_names: self.prune_names.clone(),
+            iterative_deepening: self.iterative_deepening,
+            round_state: self.round_state.clone(),
         }
     }
 }
 
 impl Parallelism {
-    pub(crate) fn spawn<OP>(&self, op: OP)
+    // The rayon pool to run on, `None` for the default pool and variants not
+    // using rayon. Fails if a new pool couldn't be built and the fallback is
+    // `PoolFallback::Error`.
+    pub(crate) fn thread_pool(&self) -> Result<Option<Arc<ThreadPool>>> {
+        match self {
+            Parallelism::RayonNewPool(num_threads)
+            | Parallelism::RayonNewPoolWith { num_threads, .. }
+            | Parallelism::Adaptive {
+                max: num_threads, ..
+            } => match (new_thread_pool(*num_threads), self.pool_fallback()) {
+                (Some(thread_pool), _) => Ok(Some(Arc::new(thread_pool))),
+                (None, PoolFallback::DefaultPool) => Ok(None),
+                (None, PoolFallback::Error) => Err(Error::busy()),
+            },
+            Parallelism::RayonExistingPool { pool, .. } => Ok(Some(pool.clone())),
+            Parallelism::Serial | Parallelism::RayonDefaultPool { .. } | Parallelism::Custom(_) => {
+                Ok(None)
+            }
+        }
+    }
+
+    // Run `op` on `thread_pool` if any, as returned by `thread_pool()`, and
+    // as configured otherwise.
+    pub(crate) fn spawn<OP>(&self, thread_pool: Option<&ThreadPool>, op: OP)
     where
         OP: FnOnce() + Send + 'static,
     {
-        match self {
-            Parallelism::Serial => op(),
-            Parallelism::RayonDefaultPool { .. } => rayon::spawn(op),
-            Parallelism::RayonNewPool(num_threads) => {
-                let mut thread_pool = ThreadPoolBuilder::new();
-                if *num_threads > 0 {
-                    thread_pool = thread_pool.num_threads(*num_threads);
-                }
-                if let Ok(thread_pool) = thread_pool.build() {
-                    thread_pool.spawn(op);
-                } else {
-                    rayon::spawn(op);
-                }
-            }
-            Parallelism::RayonExistingPool { pool, .. } => pool.spawn(op),
+        match (self, thread_pool) {
+            (_, Some(thread_pool)) => thread_pool.spawn(op),
+            (Parallelism::Serial, None) => op(),
+            (Parallelism::Custom(spawn), None) => spawn(Box::new(op)),
+            _ => rayon::spawn(op),
         }
     }
 
     pub(crate) fn timeout(&self) -> Option<std::time::Duration> {
         match self {
-            Parallelism::Serial | Parallelism::RayonNewPool(_) => None,
+            Parallelism::Serial
+            | Parallelism::RayonNewPool(_)
+            | Parallelism::Adaptive { .. }
+            | Parallelism::Custom(_) => None,
             Parallelism::RayonDefaultPool { busy_timeout } => Some(*busy_timeout),
-            Parallelism::RayonExistingPool { busy_timeout, .. } => *busy_timeout,
+            Parallelism::RayonExistingPool { busy_timeout, .. }
+            | Parallelism::RayonNewPoolWith { busy_timeout, .. } => *busy_timeout,
+        }
+    }
+
+    fn pool_fallback(&self) -> PoolFallback {
+        match self {
+            Parallelism::RayonNewPoolWith { fallback, .. } => *fallback,
+            _ => PoolFallback::DefaultPool,
+        }
+    }
+}
+
+fn new_thread_pool(num_threads: usize) -> Option<ThreadPool> {
+    let mut thread_pool = ThreadPoolBuilder::new();
+    if num_threads > 0 {
+        thread_pool = thread_pool.num_threads(num_threads);
+    }
+    thread_pool.build().ok()
+}
+
+impl FileTypes {
+    fn matches(&self, file_type: fs::FileType) -> bool {
+        if file_type.is_file() {
+            self.files
+        } else if file_type.is_dir() {
+            self.dirs
+        } else if file_type.is_symlink() {
+            self.symlinks
+        } else {
+            self.other
+        }
+    }
+}
+
+impl Sort {
+    fn compare<C: ClientState>(&self, a: &DirEntry<C>, b: &DirEntry<C>) -> Ordering {
+        match self {
+            Sort::Unsorted => Ordering::Equal,
+            Sort::Name => a.file_name.cmp(&b.file_name),
+            Sort::Natural => compare_lossy(a, b, |a, b| alphanumeric_sort::compare_str(a, b)),
+            #[cfg(feature = "unicode_sort")]
+            Sort::Normalized => {
+                use unicode_normalization::UnicodeNormalization;
+                compare_lossy(a, b, |a, b| a.nfc().cmp(b.nfc()))
+            }
+            Sort::MTimeDescending => {
+                let modified = |dir_entry: &DirEntry<C>| match dir_entry.ext() {
+                    Some(Ok(ext)) => ext.modified(),
+                    _ => None,
+                };
+                modified(b)
+                    .cmp(&modified(a))
+                    .then_with(|| a.file_name.cmp(&b.file_name))
+            }
+        }
+    }
+}
+
+// Compare the names of `a` and `b` with `compare`, converted lossily so
+// that names which aren't valid Unicode sort among the others, and by their
+// bytes if they are equal that way.
+fn compare_lossy<C: ClientState>(
+    a: &DirEntry<C>,
+    b: &DirEntry<C>,
+    compare: impl Fn(&str, &str) -> Ordering,
+) -> Ordering {
+    compare(a.file_name_lossy_cached(), b.file_name_lossy_cached())
+        .then_with(|| a.file_name.cmp(&b.file_name))
+}
+
+impl GroupOrder {
+    fn compare<C: ClientState>(&self, a: &DirEntry<C>, b: &DirEntry<C>) -> Ordering {
+        let (a_is_dir, b_is_dir) = (a.file_type.is_dir(), b.file_type.is_dir());
+        match self {
+            GroupOrder::Mixed => Ordering::Equal,
+            GroupOrder::DirsFirst => b_is_dir.cmp(&a_is_dir),
+            GroupOrder::FilesFirst => a_is_dir.cmp(&b_is_dir),
+        }
+    }
+}
+
+impl From<bool> for Sort {
+    fn from(sort: bool) -> Self {
+        if sort {
+            Sort::Name
+        } else {
+            Sort::Unsorted
+        }
+    }
+}
+
+type ListedEntries<C> = Box<dyn Iterator<Item = Result<(DirEntry<C>, bool)>>>;
+
+// How the backends list a directory.
+#[derive(Clone, Copy)]
+struct ListOptions {
+    // Set if hidden entries are skipped, to tell which are.
+    hidden_policy: Option<HiddenPolicy>,
+    #[cfg_attr(
+        not(all(
+            feature = "fast_readdir",
+            any(target_os = "linux", target_os = "macos", windows)
+        )),
+        allow(dead_code)
+    )]
+    preload_metadata_ext: bool,
+    // Drop entries that can't lead to a directory before creating them.
+    dirs_only: bool,
+}
+
+impl ListOptions {
+    fn skips(&self, file_type: fs::FileType) -> bool {
+        self.dirs_only && !file_type.is_dir() && !file_type.is_symlink()
+    }
+}
+
+// Entries of the directory at `read_dir_path`, along with whether
+// `hidden_policy` considers them hidden.
+#[cfg_attr(
+    not(all(
+        feature = "fast_readdir",
+        any(target_os = "linux", target_os = "macos", windows)
+    )),
+    allow(unused_variables)
+)]
+fn list_dir<C: ClientState>(
+    backend: Backend,
+    path: &Arc<Path>,
+    read_dir_path: &Path,
+    depth: usize,
+    follow_link_ancestors: &Arc<Vec<LinkAncestor>>,
+    list_options: ListOptions,
+    inspect_raw_entry: Option<&Arc<InspectRawEntryFunction<C>>>,
+) -> Result<ListedEntries<C>> {
+    match backend {
+        #[cfg(all(
+            feature = "fast_readdir",
+            any(target_os = "linux", target_os = "macos", windows)
+        ))]
+        Backend::Native => {
+            let read_dir = crate::core::fast_read_dir(read_dir_path)
+                .map_err(|err| Error::from_path(0, path.to_path_buf(), Operation::ReadDir, err))?;
+            Ok(list_dir_native(
+                path,
+                read_dir,
+                depth,
+                follow_link_ancestors,
+                list_options,
+            ))
+        }
+        _ => list_dir_std(
+            path,
+            read_dir_path,
+            depth,
+            follow_link_ancestors,
+            list_options,
+            inspect_raw_entry,
+        ),
+    }
+}
+
+fn list_dir_std<C: ClientState>(
+    path: &Arc<Path>,
+    read_dir_path: &Path,
+    depth: usize,
+    follow_link_ancestors: &Arc<Vec<LinkAncestor>>,
+    list_options: ListOptions,
+    inspect_raw_entry: Option<&Arc<InspectRawEntryFunction<C>>>,
+) -> Result<ListedEntries<C>> {
+    let read_dir = fs::read_dir(read_dir_path)
+        .map_err(|err| Error::from_path(0, path.to_path_buf(), Operation::ReadDir, err))?;
+    let path = path.clone();
+    let follow_link_ancestors = follow_link_ancestors.clone();
+    let inspect_raw_entry = inspect_raw_entry.cloned();
+    Ok(Box::new(read_dir.filter_map(move |dir_entry_result| {
+        let fs_dir_entry = match dir_entry_result {
+            Ok(fs_dir_entry) => fs_dir_entry,
+            Err(err) => return Some(Err(Error::from_io(depth, Operation::ReadDir, err))),
+        };
+        // Types come with the listing on most platforms, errors are kept.
+        if let Ok(file_type) = fs_dir_entry.file_type() {
+            if list_options.skips(file_type) {
+                return None;
+            }
+        }
+        let is_hidden = list_options
+            .hidden_policy
+            .is_some_and(|policy| policy.is_hidden(&fs_dir_entry));
+        Some(
+            DirEntry::from_entry(
+                depth,
+                path.clone(),
+                &fs_dir_entry,
+                follow_link_ancestors.clone(),
+            )
+            .map(|mut dir_entry| {
+                if let Some(inspect_raw_entry) = inspect_raw_entry.as_ref() {
+                    inspect_raw_entry(&fs_dir_entry, &mut dir_entry);
+                }
+                (dir_entry, is_hidden)
+            }),
+        )
+    })))
+}
+
+// Listing with `getdents64` on Linux, `getattrlistbulk` on macOS and
+// `FindFirstFileExW` on Windows.
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos", windows)
+))]
+fn list_dir_native<C: ClientState>(
+    path: &Arc<Path>,
+    read_dir: FastReadDir,
+    depth: usize,
+    follow_link_ancestors: &Arc<Vec<LinkAncestor>>,
+    list_options: ListOptions,
+) -> ListedEntries<C> {
+    let path = path.clone();
+    let follow_link_ancestors = follow_link_ancestors.clone();
+    let ListOptions {
+        hidden_policy,
+        preload_metadata_ext,
+        ..
+    } = list_options;
+    Box::new(read_dir.filter_map(move |raw_dir_entry_result| {
+        let raw_dir_entry = match raw_dir_entry_result {
+            Ok(raw_dir_entry) => raw_dir_entry,
+            Err(err) => return Some(Err(Error::from_io(depth, Operation::ReadDir, err))),
+        };
+        let file_type = match raw_dir_entry.file_type {
+            Ok(file_type) if list_options.skips(file_type) => return None,
+            Ok(file_type) => file_type,
+            Err(err) => {
+                let path = path.join(&raw_dir_entry.file_name);
+                return Some(Err(Error::from_path(depth, path, Operation::Metadata, err)));
+            }
+        };
+        let is_hidden = hidden_policy.is_some_and(|policy| {
+            is_hidden(&raw_dir_entry.file_name) || policy.has_hidden_ext(raw_dir_entry.ext)
+        });
+        let mut dir_entry = DirEntry::from_file_type(
+            depth,
+            path.clone(),
+            raw_dir_entry.file_name,
+            file_type,
+            follow_link_ancestors.clone(),
+        );
+        #[cfg(windows)]
+        {
+            dir_entry.file_attributes = raw_dir_entry.ext.map(|ext| ext.file_attributes);
+        }
+        // Listed along with the entry, saving the `stat` of `preload_ext`.
+        if preload_metadata_ext {
+            dir_entry.ext = raw_dir_entry.ext.map(Ok);
+        }
+        Some(Ok((dir_entry, is_hidden)))
+    }))
+}
+
+// Native listing of the directory at `path`, opened relative to the
+// descriptor of `parent_dir` if it is the parent. Returns the directory too,
+// to be kept open for reading its subdirectories.
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos")
+))]
+fn list_dir_fd<C: ClientState>(
+    path: &Arc<Path>,
+    parent_dir: Option<&DirFd>,
+    depth: usize,
+    follow_link_ancestors: &Arc<Vec<LinkAncestor>>,
+    list_options: ListOptions,
+) -> (Result<ListedEntries<C>>, Option<Arc<DirFd>>) {
+    let dir_fd = match DirFd::open(parent_dir, path) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            let err = Error::from_path(0, path.to_path_buf(), Operation::ReadDir, err);
+            return (Err(err), None);
+        }
+    };
+    match dir_fd.read_dir() {
+        Ok(read_dir) => (
+            Ok(list_dir_native(
+                path,
+                read_dir,
+                depth,
+                follow_link_ancestors,
+                list_options,
+            )),
+            Some(Arc::new(dir_fd)),
+        ),
+        Err(err) => {
+            let err = Error::from_path(0, path.to_path_buf(), Operation::ReadDir, err);
+            (Err(err), None)
+        }
+    }
+}
+
+// Listing of the directory at `path` from `listing_cache` if it wasn't
+// modified since it was stored, made by `list` and stored otherwise.
+fn list_dir_cached<C: ClientState>(
+    listing_cache: &dyn ListingCache,
+    path: &Arc<Path>,
+    read_dir_path: &Path,
+    depth: usize,
+    follow_link_ancestors: &Arc<Vec<LinkAncestor>>,
+    skip_hidden: bool,
+    list: impl FnOnce() -> (Result<ListedEntries<C>>, Option<Arc<DirFd>>),
+) -> (Result<ListedEntries<C>>, Option<Arc<DirFd>>) {
+    let modified = match fs::metadata(read_dir_path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return list(),
+    };
+    if let Some(listing) = listing_cache.get(path) {
+        if listing.modified == modified {
+            let path = path.clone();
+            let follow_link_ancestors = follow_link_ancestors.clone();
+            let listed_entries: ListedEntries<C> = Box::new(
+                listing
+                    .entries
+                    .clone()
+                    .into_iter()
+                    .map(move |(file_name, file_type)| {
+                        let is_hidden = skip_hidden && is_hidden(&file_name);
+                        let dir_entry = DirEntry::from_file_type(
+                            depth,
+                            path.clone(),
+                            file_name,
+                            file_type,
+                            follow_link_ancestors.clone(),
+                        );
+                        Ok((dir_entry, is_hidden))
+                    }),
+            );
+            return (Ok(listed_entries), None);
+        }
+    }
+    let (listed_entries, dir_fd) = list();
+    let listed_entries = match listed_entries {
+        Ok(listed_entries) => listed_entries.collect::<Vec<_>>(),
+        Err(err) => return (Err(err), dir_fd),
+    };
+    // Listings with errors are incomplete and not stored
+    let entries: Option<Vec<_>> = listed_entries
+        .iter()
+        .map(|listed_entry| {
+            let (dir_entry, _) = listed_entry.as_ref().ok()?;
+            Some((dir_entry.file_name.clone(), dir_entry.file_type))
+        })
+        .collect();
+    if let Some(entries) = entries {
+        listing_cache.insert(path, Arc::new(Listing { modified, entries }));
+    }
+    (Ok(Box::new(listed_entries.into_iter())), dir_fd)
+}
+
+impl HiddenPolicy {
+    // Hidden entries are recognized without reading their attributes, as
+    // needed for listings from a `ListingCache`.
+    fn hides_by_name_only(self) -> bool {
+        !cfg!(windows) || self == HiddenPolicy::DotPrefix
+    }
+
+    fn is_hidden(&self, fs_dir_entry: &fs::DirEntry) -> bool {
+        is_hidden(&fs_dir_entry.file_name()) || self.has_hidden_attributes(fs_dir_entry)
+    }
+
+    #[cfg(windows)]
+    fn has_hidden_attributes(&self, fs_dir_entry: &fs::DirEntry) -> bool {
+        use std::os::windows::fs::MetadataExt;
+
+        // Served from the directory listing on Windows, no extra system call.
+        fs_dir_entry
+            .metadata()
+            .map(|metadata| self.hides_attributes(metadata.file_attributes()))
+            .unwrap_or(false)
+    }
+
+    #[cfg(not(windows))]
+    fn has_hidden_attributes(&self, _fs_dir_entry: &fs::DirEntry) -> bool {
+        false
+    }
+}
+
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos", windows)
+))]
+impl HiddenPolicy {
+    #[cfg(windows)]
+    fn has_hidden_ext(&self, ext: Option<DirEntryExt>) -> bool {
+        ext.is_some_and(|ext| self.hides_attributes(ext.file_attributes))
+    }
+
+    #[cfg(not(windows))]
+    fn has_hidden_ext(&self, _ext: Option<DirEntryExt>) -> bool {
+        false
+    }
+}
+
+#[cfg(windows)]
+impl HiddenPolicy {
+    fn hides_attributes(&self, file_attributes: u32) -> bool {
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+
+        let mask = match self {
+            HiddenPolicy::DotPrefix => return false,
+            HiddenPolicy::Platform => FILE_ATTRIBUTE_HIDDEN,
+            HiddenPolicy::PlatformAndSystem => FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM,
+        };
+        file_attributes & mask != 0
+    }
+}
+
+// Convert `path` to the `\\?\` form that lifts the `MAX_PATH` limit.
+#[cfg(windows)]
+fn extended_length_path(path: &Path) -> Cow<'_, Path> {
+    use std::path::{Component, Prefix};
+
+    let absolute = match std::path::absolute(path) {
+        Ok(absolute) => absolute,
+        Err(_) => return Cow::Borrowed(path),
+    };
+    let prefix = match absolute.components().next() {
+        Some(Component::Prefix(prefix)) => prefix.kind(),
+        _ => return Cow::Borrowed(path),
+    };
+    match prefix {
+        Prefix::Disk(_) => {
+            let mut extended = std::ffi::OsString::from(r"\\?\");
+            extended.push(absolute.as_os_str());
+            Cow::Owned(PathBuf::from(extended))
+        }
+        Prefix::UNC(..) => match absolute.to_str() {
+            // `\\server\share` becomes `\\?\UNC\server\share`
+            Some(unc) => Cow::Owned(PathBuf::from(format!(r"\\?\UNC{}", &unc[1..]))),
+            None => Cow::Borrowed(path),
+        },
+        // Already verbatim or a device path
+        _ => Cow::Borrowed(path),
+    }
+}
+
+#[cfg(not(windows))]
+fn extended_length_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+// Whether `dir_entry` is among the `ratio` of entries picked with `seed`,
+// hashing the components of its relative path with FNV-1a, separated by `/`
+// on all platforms, and mixing the result so that its top bits are uniform.
+fn is_sampled<C: ClientState>(dir_entry: &DirEntry<C>, ratio: f64, seed: u64) -> bool {
+    let relative_path = dir_entry.relative_path();
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ seed;
+    for component in relative_path.components() {
+        for byte in component.as_os_str().as_encoded_bytes().iter().chain(b"/") {
+            hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
         }
     }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    ((hash >> 11) as f64) < ratio * (1u64 << 53) as f64
 }
 
+// Names that aren't valid Unicode still start with a `.` byte in the
+// encoding of `OsStr` on all platforms.
 fn is_hidden(file_name: &OsStr) -> bool {
-    file_name
-        .to_str()
-        .map(|s| s.starts_with('.'))
-        .unwrap_or(false)
+    file_name.as_encoded_bytes().first() == Some(&b'.')
 }
 
 impl<B, E> ClientState for (B, E)
@@ -0,0 +1,46 @@
+//! Queues yielding the items of trees produced in parallel in depth first
+//! order, as used by the walk, for traversing other trees like archives or
+//! databases.
+//!
+//! Workers take items of a [`Relaxed`](enum.Ordering.html#variant.Relaxed)
+//! queue, push their children to it and their results to a
+//! [`Strict`](enum.Ordering.html#variant.Strict) one, along with the number
+//! of children they have.
+//!
+//! ```
+//! use jwalk::ordered::{ordered_queue, IndexPath, Ordered, Ordering};
+//! use std::thread;
+//!
+//! struct Node {
+//!     name: &'static str,
+//!     children: Vec<Node>,
+//! }
+//! let leaf = |name| Node { name, children: vec![] };
+//! let tree = Node {
+//!     name: "a",
+//!     children: vec![Node { name: "b", children: vec![leaf("c")] }, leaf("d")],
+//! };
+//!
+//! let (work, work_iter) = ordered_queue::<&Node>(Ordering::Relaxed);
+//! let (results, results_iter) = ordered_queue::<&str>(Ordering::Strict);
+//! work.push(Ordered::new(&tree, IndexPath::new(vec![0]), 0)).ok();
+//! thread::scope(|scope| {
+//!     for Ordered { value: node, index_path, .. } in work_iter {
+//!         let (work, results) = (work.clone(), results.clone());
+//!         scope.spawn(move || {
+//!             for (i, child) in node.children.iter().enumerate() {
+//!                 work.push(Ordered::new(child, index_path.adding(i), 0)).ok();
+//!             }
+//!             let result = Ordered::new(node.name, index_path, node.children.len());
+//!             results.push(result).ok();
+//!             work.complete_item();
+//!         });
+//!     }
+//! });
+//! let names: Vec<_> = results_iter.map(|ordered| ordered.value).collect();
+//! assert_eq!(names, ["a", "b", "c", "d"]);
+//! ```
+
+pub use crate::core::index_path::IndexPath;
+pub use crate::core::ordered::Ordered;
+pub use crate::core::ordered_queue::{ordered_queue, OrderedQueue, OrderedQueueIter, Ordering};
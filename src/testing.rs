@@ -0,0 +1,236 @@
+//! Walk synthetic directory trees held in memory.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, FileType};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use crate::core::LinkAncestor;
+use crate::{is_hidden, ClientState, DirEntry, Error, ListedEntries, Operation, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// A directory tree held in memory, to be walked with
+/// [`memory_fs`](../struct.WalkDirGeneric.html#method.memory_fs) instead of
+/// the file system.
+///
+/// Directories list their entries in the order they were added, so walks
+/// of the same tree always see the same listings, which makes the
+/// [`process_read_dir`](../struct.WalkDirGeneric.html#method.process_read_dir)
+/// callback and ordering guarantees testable without creating files.
+///
+/// Only listing is simulated. Options reading metadata or contents, like
+/// [`preload_metadata_ext`](../struct.WalkDirGeneric.html#method.preload_metadata_ext),
+/// [`follow_links`](../struct.WalkDirGeneric.html#method.follow_links) or
+/// [`DirEntry::metadata`](../struct.DirEntry.html#method.metadata), still
+/// access the file system and fail. As `FileType` can't be created
+/// otherwise, a file, directory and symbolic link are created once in the
+/// temporary directory to learn their types.
+///
+/// ```
+/// use jwalk::testing::MemoryFs;
+/// use jwalk::WalkDir;
+///
+/// let memory_fs = MemoryFs::new()
+///     .file("root/a/x")
+///     .dir("root/b")
+///     .tree("root/c", 1, 2, 3);
+/// let walk_dir = WalkDir::new("root").memory_fs(memory_fs);
+/// // `root`, `a`, `x` and `b`, then `c` with 3 files and 2 directories of 3 files
+/// assert_eq!(4 + 12, walk_dir.into_iter().count());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFs {
+    dirs: HashMap<PathBuf, Children>,
+}
+
+// Entries of a directory in the order they were added, indexed by name.
+#[derive(Clone, Debug, Default)]
+struct Children {
+    entries: Vec<(OsString, Kind)>,
+    index: HashMap<OsString, usize>,
+}
+
+impl MemoryFs {
+    /// Create an empty tree.
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    /// Add a file at `path`, and directories for all of its ancestors.
+    pub fn file(self, path: impl AsRef<Path>) -> MemoryFs {
+        self.add(path.as_ref(), Kind::File)
+    }
+
+    /// Add a directory at `path`, and directories for all of its ancestors.
+    pub fn dir(self, path: impl AsRef<Path>) -> MemoryFs {
+        self.add(path.as_ref(), Kind::Dir)
+    }
+
+    /// Add a symbolic link at `path`, and directories for all of its
+    /// ancestors. Links are listed but can't be followed.
+    pub fn symlink(self, path: impl AsRef<Path>) -> MemoryFs {
+        self.add(path.as_ref(), Kind::Symlink)
+    }
+
+    /// Add a tree at `path` of the given `depth`, where each directory
+    /// contains `dirs` directories named `dir<n>` followed by `files` files
+    /// named `file<n>`. Directories at `depth` only contain files.
+    pub fn tree(
+        mut self,
+        path: impl AsRef<Path>,
+        depth: usize,
+        dirs: usize,
+        files: usize,
+    ) -> MemoryFs {
+        let path = path.as_ref();
+        self = self.dir(path);
+        if depth > 0 {
+            for n in 0..dirs {
+                self = self.tree(path.join(format!("dir{}", n)), depth - 1, dirs, files);
+            }
+        }
+        for n in 0..files {
+            self = self.file(path.join(format!("file{}", n)));
+        }
+        self
+    }
+
+    fn add(mut self, path: &Path, kind: Kind) -> MemoryFs {
+        if let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) {
+            if !parent.as_os_str().is_empty() {
+                self = self.add(parent, Kind::Dir);
+            }
+            let children = self.dirs.entry(parent.to_owned()).or_default();
+            match children.index.get(file_name) {
+                Some(&i) => children.entries[i].1 = kind,
+                None => {
+                    children
+                        .index
+                        .insert(file_name.to_owned(), children.entries.len());
+                    children.entries.push((file_name.to_owned(), kind));
+                }
+            }
+        }
+        if kind == Kind::Dir {
+            self.dirs.entry(path.to_owned()).or_default();
+        } else {
+            self.dirs.remove(path);
+        }
+        self
+    }
+
+    fn kind_of(&self, path: &Path) -> Option<Kind> {
+        if self.dirs.contains_key(path) {
+            return Some(Kind::Dir);
+        }
+        let file_name = path.file_name()?;
+        let children = self.dirs.get(path.parent()?)?;
+        let &i = children.index.get(file_name)?;
+        Some(children.entries[i].1)
+    }
+
+    pub(crate) fn root_entry<C: ClientState>(&self, root: &Path) -> Result<DirEntry<C>> {
+        let kind = self.kind_of(root).ok_or_else(|| {
+            let err = io::Error::from(io::ErrorKind::NotFound);
+            Error::from_path(0, root.to_owned(), Operation::Metadata, err)
+        })?;
+        let file_type = file_type(kind)
+            .map_err(|err| Error::from_path(0, root.to_owned(), Operation::Metadata, err))?;
+        let (root_name, parent_path) = match root.file_name() {
+            Some(file_name) => (file_name, root.parent().unwrap_or(Path::new(""))),
+            None => (root.as_os_str(), Path::new("")),
+        };
+        let mut dir_entry = DirEntry::from_file_type(
+            0,
+            Arc::from(parent_path),
+            root_name.to_owned(),
+            file_type,
+            Arc::new(vec![]),
+        );
+        if kind == Kind::Dir {
+            dir_entry.read_children_path = Some(Arc::from(root));
+        }
+        Ok(dir_entry)
+    }
+
+    pub(crate) fn list_dir<C: ClientState>(
+        &self,
+        path: &Arc<Path>,
+        depth: usize,
+        follow_link_ancestors: &Arc<Vec<LinkAncestor>>,
+        skip_hidden: bool,
+    ) -> Result<ListedEntries<C>> {
+        let children = self.dirs.get(path.as_ref()).ok_or_else(|| {
+            let err = match self.kind_of(path) {
+                Some(_) => io::Error::from(io::ErrorKind::NotADirectory),
+                None => io::Error::from(io::ErrorKind::NotFound),
+            };
+            Error::from_path(0, path.to_path_buf(), Operation::ReadDir, err)
+        })?;
+        let path = path.clone();
+        let follow_link_ancestors = follow_link_ancestors.clone();
+        let children = children.entries.clone();
+        Ok(Box::new(children.into_iter().map(
+            move |(file_name, kind)| {
+                let file_type = file_type(kind).map_err(|err| {
+                    Error::from_path(depth, path.join(&file_name), Operation::Metadata, err)
+                })?;
+                let is_hidden = skip_hidden && is_hidden(&file_name);
+                let dir_entry = DirEntry::from_file_type(
+                    depth,
+                    path.clone(),
+                    file_name,
+                    file_type,
+                    follow_link_ancestors.clone(),
+                );
+                Ok((dir_entry, is_hidden))
+            },
+        )))
+    }
+}
+
+fn file_type(kind: Kind) -> io::Result<FileType> {
+    static FILE_TYPES: OnceLock<[Option<FileType>; 3]> = OnceLock::new();
+
+    let file_types = FILE_TYPES.get_or_init(probe_file_types);
+    let index = match kind {
+        Kind::File => 0,
+        Kind::Dir => 1,
+        Kind::Symlink => 2,
+    };
+    file_types[index].ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("couldn't learn the file type of a {:?}", kind),
+        )
+    })
+}
+
+fn probe_file_types() -> [Option<FileType>; 3] {
+    let scratch = env::temp_dir().join(format!("jwalk-memory-fs-{}", std::process::id()));
+    let _ = fs::create_dir_all(&scratch);
+    let file = scratch.join("file");
+    let link = scratch.join("link");
+    let _ = fs::write(&file, b"");
+    #[cfg(unix)]
+    let _ = std::os::unix::fs::symlink(&file, &link);
+    #[cfg(windows)]
+    let _ = std::os::windows::fs::symlink_file(&file, &link);
+    let type_of = |path: &Path| fs::symlink_metadata(path).ok().map(|m| m.file_type());
+    let file_types = [
+        type_of(&file).filter(FileType::is_file),
+        type_of(&scratch).filter(FileType::is_dir),
+        type_of(&link).filter(FileType::is_symlink),
+    ];
+    let _ = fs::remove_dir_all(&scratch);
+    file_types
+}
@@ -3,7 +3,7 @@ use rayon::iter::ParallelIterator;
 use rayon::prelude::*;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 mod util;
@@ -366,6 +366,143 @@ fn sym_root_dir_nofollow() {
     assert!(!link_zzz.path_is_symlink());
 }
 
+#[test]
+fn sym_root_link_behavior() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.symlink_dir("a", "a-link");
+    dir.touch("a/zzz");
+
+    let walk = |behavior: RootLinkBehavior, follow_links: bool| {
+        let wd = WalkDir::new(dir.join("a-link"))
+            .root_link_behavior(behavior)
+            .follow_links(follow_links)
+            .sort(true);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+        let ents = r.ents();
+        assert_eq!(follow_links, ents[0].file_type().is_dir());
+        ents.len()
+    };
+
+    assert_eq!(2, walk(RootLinkBehavior::FollowAlways, false));
+    assert_eq!(2, walk(RootLinkBehavior::FollowAlways, true));
+    assert_eq!(1, walk(RootLinkBehavior::FollowNever, false));
+    assert_eq!(2, walk(RootLinkBehavior::UseFollowLinks, true));
+    assert_eq!(1, walk(RootLinkBehavior::UseFollowLinks, false));
+
+    let wd = WalkDir::new(dir.join("a-link"))
+        .root_link_behavior(RootLinkBehavior::FollowNever)
+        .follow_links(true);
+    let r = dir.run_recursive(wd);
+    let ents = r.ents();
+    assert_eq!(1, ents.len());
+    assert!(ents[0].file_type().is_symlink());
+}
+
+#[test]
+fn sym_policy() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/x", "f"]);
+    dir.symlink_dir("a", "a-link");
+    dir.symlink_file("f", "f-link");
+
+    // Relative paths of the entries, marking those yielded as symlinks
+    let walk = |symlink_policy: SymlinkPolicy| -> Vec<String> {
+        let wd = WalkDir::new(dir.path())
+            .sort(true)
+            .symlink_policy(symlink_policy);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+        r.ents()
+            .iter()
+            .skip(1)
+            .map(|ent| {
+                let path = ent.path();
+                let path = path.strip_prefix(dir.path()).unwrap().to_str().unwrap();
+                let link = if ent.file_type().is_symlink() {
+                    " @"
+                } else {
+                    ""
+                };
+                format!("{}{}", path.replace('\\', "/"), link)
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        walk(SymlinkPolicy::Never),
+        vec!["a", "a/x", "a-link @", "f", "f-link @"]
+    );
+    assert_eq!(
+        walk(SymlinkPolicy::Always),
+        vec!["a", "a/x", "a-link", "a-link/x", "f", "f-link"]
+    );
+    assert_eq!(
+        walk(SymlinkPolicy::DirectoriesOnly),
+        vec!["a", "a/x", "a-link", "a-link/x", "f", "f-link @"]
+    );
+    assert_eq!(
+        walk(SymlinkPolicy::FilesOnly),
+        vec!["a", "a/x", "a-link @", "f", "f-link"]
+    );
+    assert_eq!(
+        walk(SymlinkPolicy::Custom(|path| path.ends_with("f-link"))),
+        walk(SymlinkPolicy::FilesOnly)
+    );
+}
+
+#[test]
+fn sym_policy_root_link() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("a/x");
+    dir.symlink_dir("a", "a-link");
+
+    let walk = |symlink_policy: SymlinkPolicy| {
+        let wd = WalkDir::new(dir.join("a-link"))
+            .root_link_behavior(RootLinkBehavior::FollowAlways)
+            .symlink_policy(symlink_policy);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+        assert_eq!(2, r.ents().len());
+        r.ents()[0].file_type().is_symlink()
+    };
+
+    // The contents of the root are read whether the link is followed or not
+    assert!(!walk(SymlinkPolicy::DirectoriesOnly));
+    assert!(walk(SymlinkPolicy::FilesOnly));
+}
+
+#[test]
+fn sym_open() {
+    let dir = Dir::tmp();
+    fs::write(dir.join("f"), "content").unwrap();
+    dir.symlink_file("f", "f-link");
+
+    let open = |follow_links: bool| -> Vec<Result<String>> {
+        WalkDir::new(dir.path())
+            .sort(true)
+            .follow_links(follow_links)
+            .min_depth(1)
+            .into_iter()
+            .map(|dir_entry| {
+                let mut file = dir_entry.unwrap().open()?;
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut file, &mut content).unwrap();
+                Ok(content)
+            })
+            .collect()
+    };
+
+    let opened = open(false);
+    assert_eq!(opened[0].as_ref().unwrap(), "content");
+    assert_eq!(opened[1].as_ref().unwrap_err().operation(), Operation::Open);
+    let opened: Vec<_> = open(true).into_iter().map(Result::unwrap).collect();
+    assert_eq!(opened, vec!["content", "content"]);
+}
+
 #[test]
 fn sym_root_dir_follow() {
     let dir = Dir::tmp();
@@ -404,6 +541,94 @@ fn sym_root_dir_follow() {
     assert!(!link_zzz.path_is_symlink());
 }
 
+#[test]
+fn sym_dir_follow_from_depth() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.mkdirp("b");
+    dir.touch("a/zzz");
+    dir.symlink_dir(dir.join("a"), "a-link");
+    dir.symlink_dir(dir.join("a"), "b/b-link");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .follow_links_from_depth(2)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a").join("zzz"),
+        dir.join("a-link"),
+        dir.join("b"),
+        dir.join("b").join("b-link"),
+        dir.join("b").join("b-link").join("zzz"),
+    ];
+    assert_eq!(expected, r.paths());
+    let a_link = r
+        .ents()
+        .iter()
+        .find(|ent| ent.file_name == "a-link")
+        .unwrap();
+    assert!(a_link.file_type().is_symlink());
+}
+
+#[test]
+fn sym_dir_follow_filter() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("a/zzz");
+    dir.symlink_dir("a", "a-link");
+    dir.symlink_dir("a", "a-followed");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .follow_link_filter(|dir_entry| dir_entry.file_name != "a-link")
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a").join("zzz"),
+        dir.join("a-followed"),
+        dir.join("a-followed").join("zzz"),
+        dir.join("a-link"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn sym_dir_follow_chain() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.mkdirp("b");
+    dir.touch("a/zzz");
+    dir.symlink_dir(dir.join("a"), "b/a-link");
+    dir.symlink_dir(dir.join("b"), "b-link");
+
+    let wd = WalkDir::new(dir.path()).follow_links(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let chain = |path: PathBuf| {
+        let ent = r.ents().iter().find(|ent| ent.path() == path).unwrap();
+        ent.symlink_chain()
+            .iter()
+            .map(|link| link.to_path_buf())
+            .collect::<Vec<_>>()
+    };
+    let b_link = dir.join("b-link");
+    let a_link = b_link.join("a-link");
+    assert!(chain(dir.join("a").join("zzz")).is_empty());
+    assert_eq!(vec![b_link.clone()], chain(b_link.clone()));
+    assert_eq!(vec![b_link.clone(), a_link.clone()], chain(a_link.clone()));
+    assert_eq!(vec![b_link, a_link.clone()], chain(a_link.join("zzz")));
+}
+
 #[test]
 fn sym_file_nofollow() {
     let dir = Dir::tmp();
@@ -556,6 +781,33 @@ fn sym_dir_follow() {
     assert!(!link_zzz.path_is_symlink());
 }
 
+#[test]
+fn sym_resolved_path() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("b");
+    dir.symlink_dir("a", "a-link");
+    dir.symlink_file("b", "b-link");
+
+    let wd = WalkDir::new(dir.path()).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert!(r.ents().iter().all(|ent| ent.resolved_path().is_none()));
+
+    let wd = WalkDir::new(dir.path()).follow_links(true).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let resolved: Vec<_> = r
+        .ents()
+        .iter()
+        .map(|ent| ent.resolved_path().map(Path::to_path_buf))
+        .collect();
+    assert_eq!(
+        vec![None, None, Some(dir.join("a")), None, Some(dir.join("b"))],
+        resolved
+    );
+}
+
 #[test]
 fn sym_noloop() {
     let dir = Dir::tmp();
@@ -595,6 +847,61 @@ fn sym_loop_detect() {
     assert!(err.io_error().is_none());
 }
 
+#[test]
+fn sym_loop_detection() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.symlink_dir("a", "a/b/c/a-link");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .loop_detection(LoopDetection::Canonical);
+    let r = dir.run_recursive(wd);
+    assert_eq!(4, r.ents().len());
+    assert_eq!(1, r.errs().len());
+    let expected = dir.join("a");
+    assert_eq!(Some(&*expected), r.errs()[0].loop_ancestor());
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .loop_detection(LoopDetection::Off)
+        .max_depth(7);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(8, r.ents().len());
+}
+
+#[test]
+fn sym_broken_behavior() {
+    let dir = Dir::tmp();
+    dir.touch("a");
+    dir.symlink_file("missing", "b");
+
+    let walk_dir = |behavior| {
+        WalkDir::new(dir.path())
+            .follow_links(true)
+            .broken_symlink_behavior(behavior)
+            .sort(true)
+    };
+
+    let r = dir.run_recursive(walk_dir(BrokenSymlinkBehavior::Error));
+    assert_eq!(2, r.ents().len());
+    assert_eq!(1, r.errs().len());
+    assert_eq!(Some(&*dir.join("b")), r.errs()[0].path());
+
+    let r = dir.run_recursive(walk_dir(BrokenSymlinkBehavior::Yield));
+    r.assert_no_errors();
+    assert_eq!(
+        vec![dir.path().to_path_buf(), dir.join("a"), dir.join("b")],
+        r.paths()
+    );
+    assert!(r.ents()[2].file_type().is_symlink());
+
+    let r = dir.run_recursive(walk_dir(BrokenSymlinkBehavior::Skip));
+    r.assert_no_errors();
+    assert_eq!(vec![dir.path().to_path_buf(), dir.join("a")], r.paths());
+}
+
 #[test]
 fn sym_self_loop_no_error() {
     let dir = Dir::tmp();
@@ -663,118 +970,633 @@ fn sym_dir_self_loop_io_error() {
 }
 
 #[test]
-fn min_depth_1() {
+fn error_operation() {
     let dir = Dir::tmp();
-    dir.mkdirp("a/b");
+    dir.mkdirp("a/b/c");
+    dir.symlink_dir("a", "a/b/c/a-link");
+    dir.symlink_file("f", "f");
+    dir.symlink_dir("d", "d");
 
-    let wd = WalkDir::new(dir.path()).min_depth(1).sort(true);
+    let wd = WalkDir::new(dir.path()).follow_links(true).sort(true);
     let r = dir.run_recursive(wd);
-    r.assert_no_errors();
-
-    let expected = vec![dir.join("a"), dir.join("a").join("b")];
-    assert_eq!(expected, r.paths());
+    let mut operations: Vec<_> = r
+        .errs()
+        .iter()
+        .map(|err| (err.path().unwrap().to_path_buf(), err.operation()))
+        .collect();
+    operations.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        vec![
+            (dir.join("a/b/c/a-link"), Operation::Loop),
+            (dir.join("d"), Operation::Metadata),
+            (dir.join("f"), Operation::Metadata),
+        ],
+        operations
+    );
 }
 
 #[test]
-fn min_depth_2() {
+fn error_kind() {
     let dir = Dir::tmp();
-    dir.mkdirp("a/b");
+    dir.mkdirp("a/b/c");
+    dir.symlink_dir("a", "a/b/c/a-link");
+    dir.symlink_file("f", "f");
+    dir.symlink_dir("d", "d");
 
-    let wd = WalkDir::new(dir.path()).min_depth(2).sort(true);
+    let wd = WalkDir::new(dir.path()).follow_links(true).sort(true);
     let r = dir.run_recursive(wd);
-    r.assert_no_errors();
-
-    let expected = vec![dir.join("a").join("b")];
-    assert_eq!(expected, r.paths());
+    let mut kinds: Vec<_> = r
+        .errs()
+        .iter()
+        .map(|err| (err.path().unwrap().to_path_buf(), err.kind()))
+        .collect();
+    kinds.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        vec![
+            (dir.join("a/b/c/a-link"), ErrorKind::Loop),
+            (dir.join("d"), ErrorKind::Io),
+            (dir.join("f"), ErrorKind::Io),
+        ],
+        kinds
+    );
 }
 
 #[test]
-fn max_depth_0() {
+fn preload_metadata_ext() {
     let dir = Dir::tmp();
-    dir.mkdirp("a/b");
+    dir.touch("a");
 
-    let wd = WalkDir::new(dir.path()).max_depth(0).sort(true);
+    let wd = WalkDir::new(dir.path())
+        .preload_metadata_ext(true)
+        .sort(true);
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
-    let expected = vec![dir.path().to_path_buf()];
-    assert_eq!(expected, r.paths());
+    for ent in r.ents() {
+        assert!(ent.ext().unwrap().is_ok());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let ext = ent.ext().unwrap().as_ref().unwrap();
+            assert_eq!(ent.metadata().unwrap().ino(), ext.ino);
+        }
+    }
+
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd);
+    assert!(r.ents().iter().all(|ent| ent.ext().is_none()));
 }
 
 #[test]
-fn max_depth_1() {
+fn prefetch_metadata_parallel() {
     let dir = Dir::tmp();
-    dir.mkdirp("a/b");
+    dir.mkdirp("a");
+    for i in 0..100 {
+        dir.touch(format!("a/{}", i));
+    }
 
-    let wd = WalkDir::new(dir.path()).max_depth(1).sort(true);
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(4)] {
+        let wd = WalkDir::new(dir.path())
+            .prefetch_metadata(Prefetch::Parallel { batch: 8 })
+            .parallelism(parallelism);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+
+        assert_eq!(102, r.ents().len());
+        for ent in r.ents() {
+            assert!(ent.ext().unwrap().is_ok());
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let ext = ent.ext().unwrap().as_ref().unwrap();
+                assert_eq!(ent.metadata().unwrap().ino(), ext.ino);
+            }
+        }
+    }
+}
+
+#[test]
+// The cached full path of entries doesn't take part in hashing.
+#[allow(clippy::mutable_key_type)]
+fn dir_entry_clone_eq_hash() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("a/b");
+
+    let wd = WalkDir::new(dir.path()).sort(true);
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
-    let expected = vec![dir.path().to_path_buf(), dir.join("a")];
-    assert_eq!(expected, r.paths());
+    let ents = r.ents();
+    let clones = ents.to_vec();
+    assert_eq!(ents, &clones[..]);
+    assert_ne!(ents[1], ents[2]);
+
+    let set: std::collections::HashSet<_> = ents.iter().chain(&clones).cloned().collect();
+    assert_eq!(ents.len(), set.len());
+    assert!(set.contains(&clones[2]));
 }
 
+#[cfg(feature = "serde")]
 #[test]
-fn max_depth_2() {
+fn serde_round_trip() {
     let dir = Dir::tmp();
-    dir.mkdirp("a/b");
+    dir.mkdirp("a");
+    dir.touch("a/b");
+    dir.symlink_dir("a", "a-link");
 
-    let wd = WalkDir::new(dir.path()).max_depth(2).sort(true);
+    let wd = WalkDir::new(dir.path()).sort(true);
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
-    let expected = vec![
-        dir.path().to_path_buf(),
-        dir.join("a"),
-        dir.join("a").join("b"),
-    ];
-    assert_eq!(expected, r.paths());
+    let json = serde_json::to_string(r.ents()).unwrap();
+    let ents: Vec<SerializedDirEntry<((), ())>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(r.ents().len(), ents.len());
+    for (ent, expected) in ents.iter().zip(r.ents()) {
+        assert_eq!(expected.path(), ent.path());
+        assert_eq!(expected.depth(), ent.depth());
+        assert_eq!(expected.file_type().is_dir(), ent.is_dir());
+        assert_eq!(expected.file_type().is_file(), ent.is_file());
+        assert_eq!(expected.file_type().is_symlink(), ent.is_symlink());
+    }
+
+    let wd = WalkDir::new(dir.join("missing"));
+    let err = wd.into_iter().next().unwrap().unwrap_err();
+    let err_json = serde_json::to_string(&err).unwrap();
+    let deserialized: Error = serde_json::from_str(&err_json).unwrap();
+    assert_eq!(err.to_string(), deserialized.to_string());
+    assert_eq!(err.path(), deserialized.path());
+    assert_eq!(err.operation(), deserialized.operation());
+    assert_eq!(
+        err.io_error().unwrap().kind(),
+        deserialized.io_error().unwrap().kind()
+    );
+
+    // Reading entries back doesn't touch the file system
+    drop(dir);
+    let ents: Vec<SerializedDirEntry<((), ())>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(r.ents().len(), ents.len());
 }
 
 #[test]
-fn min_max_depth_diff_0() {
+fn walk_glob() {
     let dir = Dir::tmp();
-    dir.mkdirp("a/b/c");
+    dir.mkdirp("p1/src");
+    dir.mkdirp("p2/src");
+    dir.mkdirp("p3/doc");
+    dir.mkdirp("q1/src");
+    dir.mkdirp(".p4/src");
+    dir.touch("p1/src/x");
+    dir.touch("p2/src/y");
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let wd = WalkDir::new_glob(dir.join("*").join("src"))
+            .parallelism(parallelism.clone())
+            .sort(true);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+        let expected = vec![
+            dir.join("p1/src"),
+            dir.join("p1/src/x"),
+            dir.join("p2/src"),
+            dir.join("p2/src/y"),
+            dir.join("q1/src"),
+        ];
+        assert_eq!(expected, r.paths());
+        assert!(r.ents().iter().step_by(2).all(|ent| ent.depth() == 0));
+        let roots: Vec<_> = r.ents().iter().map(|ent| ent.root().to_owned()).collect();
+        let expected = vec![
+            dir.join("p1/src"),
+            dir.join("p1/src"),
+            dir.join("p2/src"),
+            dir.join("p2/src"),
+            dir.join("q1/src"),
+        ];
+        assert_eq!(expected, roots);
+
+        let wd = WalkDir::new_glob(dir.join("[!q]?").join("s*"))
+            .parallelism(parallelism)
+            .max_depth(0);
+        let r = dir.run_recursive(wd);
+        assert_eq!(vec![dir.join("p1/src"), dir.join("p2/src")], r.paths());
+    }
 
-    let wd = WalkDir::new(dir.path())
-        .min_depth(2)
-        .max_depth(2)
-        .sort(true);
+    let wd = WalkDir::new_glob(dir.join("*").join("missing"));
+    assert!(wd.into_iter().next().is_none());
+}
+
+#[test]
+fn sniff_content() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    fs::write(dir.join("a/long"), b"0123456789").unwrap();
+    fs::write(dir.join("a/short"), b"01").unwrap();
+
+    let wd = WalkDir::new(dir.path()).sniff_content(4).sort(true);
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
-    let expected = vec![dir.join("a").join("b")];
-    assert_eq!(expected, r.paths());
+    let prefixes: Vec<_> = r.ents().iter().map(|ent| ent.content_prefix()).collect();
+    let expected: Vec<Option<&[u8]>> = vec![None, None, Some(b"0123"), Some(b"01")];
+    assert_eq!(expected, prefixes);
+
+    let wd = WalkDir::new(dir.join("a/long")).sniff_content(2);
+    let r = dir.run_recursive(wd);
+    assert_eq!(Some(&b"01"[..]), r.ents()[0].content_prefix());
+
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd);
+    assert!(r.ents().iter().all(|ent| ent.content_prefix().is_none()));
 }
 
 #[test]
-fn min_max_depth_diff_1() {
+fn split_large_dirs() {
     let dir = Dir::tmp();
-    dir.mkdirp("a/b/c");
+    dir.mkdirp("a");
+    for i in 0..200 {
+        fs::write(dir.join(format!("a/{}", i)), i.to_string()).unwrap();
+    }
 
-    let wd = WalkDir::new(dir.path())
-        .min_depth(1)
-        .max_depth(2)
-        .sort(true);
-    let r = dir.run_recursive(wd);
+    let walk_dir = || {
+        WalkDir::new(dir.path())
+            .parallelism(Parallelism::RayonNewPool(4))
+            .sniff_content(8)
+            .sort(true)
+    };
+    let expected = dir.run_recursive(walk_dir());
+    let r = dir.run_recursive(walk_dir().split_large_dirs(16));
     r.assert_no_errors();
 
-    let expected = vec![dir.join("a"), dir.join("a").join("b")];
-    assert_eq!(expected, r.paths());
+    assert_eq!(202, r.ents().len());
+    assert_eq!(expected.paths(), r.paths());
+    for ent in r.ents().iter().filter(|ent| ent.file_type().is_file()) {
+        assert_eq!(
+            ent.file_name().to_str().unwrap().as_bytes(),
+            ent.content_prefix().unwrap()
+        );
+    }
 }
 
 #[test]
-fn sort() {
+fn parallel_sort_threshold() {
     let dir = Dir::tmp();
-    dir.mkdirp("foo/bar/baz/abc");
-    dir.mkdirp("quux");
+    dir.mkdirp("a/sub");
+    for i in 0..300 {
+        dir.touch(format!("a/file{}", i));
+    }
 
-    let wd = WalkDir::new(dir.path()).sort(true);
-    let r = dir.run_recursive(wd);
+    let walk_dir = || {
+        WalkDir::new(dir.path())
+            .parallelism(Parallelism::RayonNewPool(4))
+            .sort(Sort::Natural)
+            .group_order(GroupOrder::DirsFirst)
+    };
+    let expected = dir.run_recursive(walk_dir());
+    let r = dir.run_recursive(walk_dir().parallel_sort_threshold(16));
     r.assert_no_errors();
 
-    let expected = vec![
-        dir.path().to_path_buf(),
+    assert_eq!(303, r.ents().len());
+    assert_eq!(expected.paths(), r.paths());
+    assert!(r.paths()[2].ends_with("a/sub"));
+    assert!(r.paths()[4].ends_with("a/file1"));
+}
+
+#[test]
+fn file_id() {
+    let dir = Dir::tmp();
+    dir.touch("a");
+    fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+    dir.touch("c");
+
+    let wd = WalkDir::new(dir.path())
+        .preload_metadata_ext(true)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let ids: Vec<_> = r.ents()[1..].iter().map(|ent| ent.file_id()).collect();
+    if cfg!(unix) {
+        assert!(ids.iter().all(Option::is_some));
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+    } else {
+        assert!(ids.iter().all(Option::is_none));
+    }
+
+    let wd = WalkDir::new(dir.path());
+    let r = dir.run_recursive(wd);
+    assert!(r.ents().iter().all(|ent| ent.file_id().is_none()));
+}
+
+#[test]
+fn full_path() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("a/b");
+
+    for precompute_paths in [false, true] {
+        let wd = WalkDir::new(dir.path())
+            .precompute_paths(precompute_paths)
+            .sort(true);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+
+        for ent in r.ents() {
+            assert_eq!(ent.path(), ent.full_path().as_ref());
+            assert!(std::sync::Arc::ptr_eq(ent.full_path(), ent.full_path()));
+        }
+    }
+}
+
+#[test]
+fn long_paths() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch("a/b/c");
+
+    let wd = WalkDir::new(dir.path()).long_paths(true).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a").join("b"),
+        dir.join("a").join("b").join("c"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn detect_changes() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/x", "a/y"]);
+
+    let wd = WalkDir::new(dir.path()).detect_changes(true).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let mut changed = Vec::new();
+    for result in WalkDir::new(dir.path()).detect_changes(true).sort(true) {
+        match result {
+            Ok(ent) if ent.file_name() == "x" => dir.touch("a/z"),
+            Ok(_) => {}
+            Err(err) => {
+                assert!(err.is_changed());
+                changed.push(err.path().unwrap().to_path_buf());
+            }
+        }
+    }
+    assert_eq!(vec![dir.join("a")], changed);
+}
+
+#[test]
+#[cfg(unix)]
+fn track_hardlinks() {
+    let dir = Dir::tmp();
+    dir.touch("a");
+    fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+    dir.touch("c");
+
+    let wd = WalkDir::new(dir.path()).track_hardlinks(true).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let duplicates: Vec<_> = r
+        .ents()
+        .iter()
+        .filter(|ent| ent.is_hardlink_duplicate())
+        .map(|ent| ent.path())
+        .collect();
+    assert_eq!(1, duplicates.len());
+    assert!(duplicates[0] == dir.join("a") || duplicates[0] == dir.join("b"));
+}
+
+#[test]
+#[cfg(unix)]
+fn disk_usage() {
+    use jwalk::extras::{disk_usage, DiskUsageOptions};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    fs::write(dir.join("a/x"), vec![0; 1000]).unwrap();
+    fs::hard_link(dir.join("a/x"), dir.join("a/y")).unwrap();
+    dir.touch("z");
+
+    let usage = disk_usage(
+        dir.path(),
+        DiskUsageOptions {
+            apparent_size: true,
+            ..Default::default()
+        },
+    );
+    assert!(usage.errors.is_empty(), "{:?}", usage.errors);
+    assert_eq!(2, usage.dirs);
+    assert_eq!(2, usage.files);
+
+    let with_hardlinks = disk_usage(
+        dir.path(),
+        DiskUsageOptions {
+            apparent_size: true,
+            count_hardlinks: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(3, with_hardlinks.files);
+    assert_eq!(usage.bytes + 1000, with_hardlinks.bytes);
+
+    let allocated = disk_usage(
+        dir.path(),
+        DiskUsageOptions {
+            one_file_system: true,
+            parallelism: Parallelism::Serial,
+            ..Default::default()
+        },
+    );
+    assert_eq!(2, allocated.files);
+    assert_eq!(0, allocated.bytes % 512);
+}
+
+#[test]
+fn tree_digest() {
+    use jwalk::extras::{tree_digest, TreeDigestOptions};
+
+    let dir = Dir::tmp();
+    for root in ["a", "b"] {
+        dir.mkdirp(format!("{}/x", root));
+        fs::write(dir.join(root).join("x/y"), "same").unwrap();
+        dir.touch(format!("{}/z", root));
+    }
+    let options = || TreeDigestOptions {
+        modified: false,
+        content: true,
+        parallelism: Parallelism::RayonNewPool(2),
+        ..Default::default()
+    };
+
+    let a = tree_digest(dir.join("a"), options());
+    assert!(a.errors.is_empty(), "{:?}", a.errors);
+    assert_eq!(4, a.entries);
+    assert_eq!(a.hash, tree_digest(dir.join("a"), options()).hash);
+    assert_eq!(a.hash, tree_digest(dir.join("b"), options()).hash);
+    assert_eq!(32, a.to_string().len());
+
+    fs::write(dir.join("b/x/y"), "diff").unwrap();
+    assert_ne!(a.hash, tree_digest(dir.join("b"), options()).hash);
+    let without_content = TreeDigestOptions {
+        content: false,
+        ..options()
+    };
+    assert_eq!(
+        tree_digest(dir.join("a"), without_content.clone()).hash,
+        tree_digest(dir.join("b"), without_content).hash
+    );
+
+    fs::rename(dir.join("b/z"), dir.join("b/w")).unwrap();
+    assert_ne!(a.hash, tree_digest(dir.join("b"), options()).hash);
+}
+
+#[test]
+#[cfg(unix)]
+fn skip_visited_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/x", "a/y"]);
+    dir.symlink_dir("a", "b");
+    dir.symlink_dir("a", "c");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .skip_visited_dirs(true)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let descended: Vec<_> = r
+        .ents()
+        .iter()
+        .filter(|ent| ent.depth() == 2)
+        .map(|ent| ent.parent_path().to_owned())
+        .collect();
+    assert_eq!(2, descended.len());
+    assert_eq!(descended[0], descended[1]);
+    assert_eq!(6, r.ents().len());
+}
+
+#[test]
+fn min_depth_1() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+
+    let wd = WalkDir::new(dir.path()).min_depth(1).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![dir.join("a"), dir.join("a").join("b")];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn min_depth_2() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+
+    let wd = WalkDir::new(dir.path()).min_depth(2).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![dir.join("a").join("b")];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn max_depth_0() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+
+    let wd = WalkDir::new(dir.path()).max_depth(0).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf()];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn max_depth_1() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+
+    let wd = WalkDir::new(dir.path()).max_depth(1).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![dir.path().to_path_buf(), dir.join("a")];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn max_depth_2() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+
+    let wd = WalkDir::new(dir.path()).max_depth(2).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a").join("b"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn min_max_depth_diff_0() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+
+    let wd = WalkDir::new(dir.path())
+        .min_depth(2)
+        .max_depth(2)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![dir.join("a").join("b")];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn min_max_depth_diff_1() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+
+    let wd = WalkDir::new(dir.path())
+        .min_depth(1)
+        .max_depth(2)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![dir.join("a"), dir.join("a").join("b")];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn sort() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar/baz/abc");
+    dir.mkdirp("quux");
+
+    let wd = WalkDir::new(dir.path()).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
         dir.join("foo"),
         dir.join("foo").join("bar"),
         dir.join("foo").join("bar").join("baz"),
@@ -784,43 +1606,1953 @@ fn sort() {
     assert_eq!(expected, r.paths());
 }
 
-fn test_dir() -> (PathBuf, tempfile::TempDir) {
-    let template = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/assets/test_dir");
-    let temp_dir = tempfile::tempdir().unwrap();
-    let options = fs_extra::dir::CopyOptions::new();
-    fs_extra::dir::copy(&template, &temp_dir, &options).unwrap();
-    let mut test_dir = temp_dir.path().to_path_buf();
-    test_dir.push(template.file_name().unwrap());
-    (test_dir, temp_dir)
-}
+#[test]
+fn sort_natural() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["file10", "file2", "file1"]);
+
+    let wd = WalkDir::new(dir.path()).sort(Sort::Natural);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("file1"),
+        dir.join("file2"),
+        dir.join("file10"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+#[cfg(all(feature = "unicode_sort", target_os = "linux"))]
+fn sort_normalized() {
+    let dir = Dir::tmp();
+    // "éb" composed, "éa" decomposed
+    dir.touch_all(&["\u{e9}b", "e\u{301}a", "f"]);
+
+    let names = |sort| {
+        let r = dir.run_recursive(WalkDir::new(dir.path()).sort(sort));
+        r.assert_no_errors();
+        r.ents()[1..]
+            .iter()
+            .map(|ent| ent.file_name().to_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec!["e\u{301}a", "f", "\u{e9}b"], names(Sort::Name));
+    assert_eq!(vec!["f", "e\u{301}a", "\u{e9}b"], names(Sort::Normalized));
+}
+
+#[test]
+fn sort_mtime_descending() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = Dir::tmp();
+    dir.touch_all(&["a", "b", "c"]);
+    let now = SystemTime::now();
+    for (name, age) in [("a", 20), ("b", 0), ("c", 10)] {
+        let file = fs::File::options()
+            .write(true)
+            .open(dir.join(name))
+            .unwrap();
+        file.set_modified(now - Duration::from_secs(age)).unwrap();
+    }
+
+    let wd = WalkDir::new(dir.path()).sort(Sort::MTimeDescending);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("b"),
+        dir.join("c"),
+        dir.join("a"),
+    ];
+    assert_eq!(expected, r.paths());
+    for ent in &r.ents()[1..] {
+        let ext = ent.ext().unwrap().as_ref().unwrap();
+        assert_eq!(
+            fs::metadata(ent.path()).unwrap().modified().ok(),
+            ext.modified()
+        );
+    }
+}
+
+#[test]
+fn memory_fs() {
+    use jwalk::testing::MemoryFs;
+
+    let memory_fs = MemoryFs::new()
+        .file("root/b")
+        .file("root/.hidden")
+        .symlink("root/link")
+        .tree("root/a", 2, 2, 2);
+    let paths = |parallelism| {
+        WalkDir::new("root")
+            .memory_fs(memory_fs.clone())
+            .parallelism(parallelism)
+            .into_iter()
+            .map(|dir_entry_result| {
+                let dir_entry = dir_entry_result.unwrap();
+                (dir_entry.path(), dir_entry.file_type())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let serial = paths(Parallelism::Serial);
+    assert_eq!(1 + 2 + 1 + 2 + 2 * (1 + 2 + 2 * 3), serial.len());
+    assert_eq!(PathBuf::from("root/b"), serial[1].0);
+    assert!(serial[1].1.is_file());
+    assert_eq!(PathBuf::from("root/link"), serial[2].0);
+    assert!(serial[2].1.is_symlink());
+    assert_eq!(PathBuf::from("root/a/dir0"), serial[4].0);
+    assert!(serial[4].1.is_dir());
+    assert_eq!(serial, paths(Parallelism::RayonNewPool(4)));
+
+    let missing = WalkDir::new("missing")
+        .memory_fs(memory_fs)
+        .into_iter()
+        .next();
+    assert!(matches!(missing, Some(Err(_))));
+}
+
+#[test]
+fn walk_events() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["a/b/x", "a/y", "z"]);
+
+    let events: Vec<_> = WalkDir::new(dir.path())
+        .sort(true)
+        .into_events_iter()
+        .map(|event| match event.unwrap() {
+            WalkEvent::EnterDir(dir_entry) => format!("enter {}", dir_entry.depth()),
+            WalkEvent::File(dir_entry) => format!("{:?}", dir_entry.file_name()),
+            WalkEvent::LeaveDir { depth, .. } => format!("leave {}", depth),
+        })
+        .collect();
+    let expected = vec![
+        "enter 0", "enter 1", "enter 2", "\"x\"", "leave 2", "\"y\"", "leave 1", "\"z\"", "leave 0",
+    ];
+    assert_eq!(expected, events);
+}
+
+#[test]
+fn sibling_index() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/x", "a/y", "a/z", "b", "c"]);
+
+    let wd = WalkDir::new(dir.path())
+        .sort(true)
+        .process_read_dir(|_, _, _, children| {
+            children.retain(|dir_entry_result| {
+                dir_entry_result
+                    .as_ref()
+                    .map_or(true, |dir_entry| dir_entry.file_name() != "c")
+            });
+        });
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let siblings: Vec<_> = r
+        .ents()
+        .iter()
+        .map(|ent| (ent.sibling_index(), ent.sibling_count()))
+        .collect();
+    assert_eq!(
+        vec![(0, 1), (0, 2), (0, 3), (1, 3), (2, 3), (1, 2)],
+        siblings
+    );
+
+    // Directories only read for their contents aren't counted
+    let dir = Dir::tmp();
+    dir.mkdirp("b");
+    dir.mkdirp("c");
+    dir.touch("a1");
+
+    let files = FileTypes {
+        files: true,
+        dirs: false,
+        symlinks: false,
+        other: false,
+    };
+    let wd = WalkDir::new(dir.path()).sort(true).file_type_filter(files);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(vec![dir.join("a1")], r.paths());
+    assert_eq!(
+        (0, 1),
+        (r.ents()[0].sibling_index(), r.ents()[0].sibling_count())
+    );
+}
+
+#[test]
+fn file_type_filter() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["a/b/x", "a/y", "z"]);
+    dir.symlink_file("z", "link");
+
+    let files = FileTypes {
+        files: true,
+        dirs: false,
+        symlinks: false,
+        other: false,
+    };
+    let wd = WalkDir::new(dir.path()).sort(true).file_type_filter(files);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![dir.join("a/b/x"), dir.join("a/y"), dir.join("z")];
+    assert_eq!(expected, r.paths());
+
+    let par_count = WalkDir::new(dir.path())
+        .file_type_filter(files)
+        .into_par_iter()
+        .count();
+    assert_eq!(3, par_count);
+
+    let dirs_and_symlinks = FileTypes {
+        files: false,
+        dirs: true,
+        symlinks: true,
+        other: false,
+    };
+    let wd = WalkDir::new(dir.path())
+        .sort(true)
+        .file_type_filter(dirs_and_symlinks);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a/b"),
+        dir.join("link"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn errors_to() {
+    let dir = Dir::tmp();
+    dir.touch("a");
+    let missing = dir.join("missing");
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut iter = WalkDir::new(&missing).errors_to(tx.clone()).into_iter();
+    assert!(iter.next().is_none());
+    assert_eq!(1, iter.summary().errors);
+    assert_eq!(Some(missing.as_path()), rx.try_recv().unwrap().path());
+
+    let par_count = WalkDir::new(&missing).errors_to(tx).into_par_iter().count();
+    assert_eq!(0, par_count);
+    assert_eq!(Some(missing.as_path()), rx.try_recv().unwrap().path());
+
+    // Errors are yielded once the receiver is gone
+    let (tx, rx) = std::sync::mpsc::channel();
+    drop(rx);
+    let results: Vec<_> = WalkDir::new(&missing).errors_to(tx).into_iter().collect();
+    assert_eq!(1, results.len());
+    assert!(results[0].is_err());
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let wd = WalkDir::new(dir.path()).errors_to(tx);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(2, r.ents().len());
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn build_config_errors() {
+    let (test_dir, _temp_dir) = test_dir();
+    let build = |walk_dir: WalkDir| walk_dir.build().err();
+
+    assert_eq!(
+        Some(ConfigError::MinDepthAboveMaxDepth {
+            min_depth: 3,
+            max_depth: 2
+        }),
+        build(WalkDir::new(&test_dir).min_depth(3).max_depth(2))
+    );
+    assert_eq!(
+        Some(ConfigError::MinDepthAboveMaxDepth {
+            min_depth: 3,
+            max_depth: 2
+        }),
+        build(WalkDir::new(&test_dir).max_depth(2).min_depth(3))
+    );
+    assert_eq!(
+        None,
+        build(
+            WalkDir::new(&test_dir)
+                .min_depth(3)
+                .max_depth(2)
+                .max_depth(4)
+        )
+    );
+    assert_eq!(
+        Some(ConfigError::ParallelismDiscarded),
+        build(
+            WalkDir::new(&test_dir)
+                .parallelism(Parallelism::RayonNewPool(2))
+                .max_depth(1)
+        )
+    );
+    assert_eq!(None, build(WalkDir::new(&test_dir).max_depth(1)));
+    assert_eq!(
+        Some(ConfigError::UnboundedLinkLoops),
+        build(
+            WalkDir::new(&test_dir)
+                .follow_links(true)
+                .loop_detection(LoopDetection::Off)
+        )
+    );
+    assert_eq!(
+        None,
+        build(
+            WalkDir::new(&test_dir)
+                .follow_links(true)
+                .loop_detection(LoopDetection::Off)
+                .max_depth(10)
+        )
+    );
+    assert_eq!(
+        Some(ConfigError::UnusedBusyTimeout),
+        build(
+            WalkDir::new(&test_dir).parallelism(Parallelism::RayonNewPoolWith {
+                num_threads: 2,
+                busy_timeout: Some(std::time::Duration::from_secs(1)),
+                fallback: PoolFallback::Error,
+            })
+        )
+    );
+
+    let prepared = WalkDir::new(&test_dir).sort(true).build().unwrap();
+    assert_eq!(8, prepared.into_iter().count());
+}
+
+#[test]
+fn path_matching() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/x.rs", "b"]);
+
+    let r = dir.run_recursive(WalkDir::new(dir.path()).sort(true));
+    r.assert_no_errors();
+    for ent in r.ents() {
+        let path = ent.path();
+        assert_eq!(
+            path.file_name().unwrap().as_encoded_bytes(),
+            ent.file_name_bytes()
+        );
+        for other in ["a", "a/x.rs", "x.rs", "b", "", "x"] {
+            assert_eq!(path.ends_with(other), ent.path_ends_with(other));
+            let other = dir.path().join(other);
+            assert_eq!(path.starts_with(&other), ent.path_starts_with(&other));
+        }
+    }
+    assert!(r.ents()[2].path_ends_with("a/x.rs"));
+    assert!(!r.ents()[2].path_ends_with("x"));
+    assert!(r.ents()[2].path_starts_with(dir.join("a")));
+}
+
+#[test]
+fn process_read_dir_mut() {
+    let (test_dir, _temp_dir) = test_dir();
+    let mut entry_count = 0;
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let wd = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .process_read_dir_mut(move |_, _, _, children| {
+            entry_count += children.len();
+            sender.send(entry_count).unwrap();
+        });
+    let yielded = wd.into_iter().count();
+    let counts: Vec<_> = receiver.try_iter().collect();
+    assert_eq!(Some(&yielded), counts.last());
+}
+
+#[test]
+fn read_children_state() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.mkdirp("b");
+    dir.touch_all(&["a/x", "b/y"]);
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let seen = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let seen_in_callback = seen.clone();
+        let wd = WalkDirGeneric::<(String, ())>::new(dir.path())
+            .parallelism(parallelism)
+            .root_read_dir_state("root".to_owned())
+            .process_read_dir(move |_, path, read_dir_state, children| {
+                let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+                seen_in_callback
+                    .lock()
+                    .unwrap()
+                    .push((name, read_dir_state.clone()));
+                for dir_entry in children.iter_mut().flatten() {
+                    if dir_entry.file_name() == "b" {
+                        dir_entry.read_children_state = Some("b".to_owned());
+                    }
+                }
+            });
+        dir.run_recursive(wd).assert_no_errors();
+
+        let mut seen: Vec<_> = seen
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == "a" || name == "b")
+            .cloned()
+            .collect();
+        seen.sort();
+        let expected = vec![
+            ("a".to_owned(), "root".to_owned()),
+            ("b".to_owned(), "b".to_owned()),
+        ];
+        assert_eq!(expected, seen);
+    }
+}
+
+#[test]
+fn group_order() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["a", "c"]);
+    dir.mkdirp("b");
+    dir.mkdirp("d");
+
+    let names = |group_order| {
+        let wd = WalkDir::new(dir.path()).sort(true).group_order(group_order);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+        r.ents()[1..]
+            .iter()
+            .map(|ent| ent.file_name().to_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec!["a", "b", "c", "d"], names(GroupOrder::Mixed));
+    assert_eq!(vec!["b", "d", "a", "c"], names(GroupOrder::DirsFirst));
+    assert_eq!(vec!["a", "c", "b", "d"], names(GroupOrder::FilesFirst));
+}
+
+fn test_dir() -> (PathBuf, tempfile::TempDir) {
+    let template = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/assets/test_dir");
+    let temp_dir = tempfile::tempdir().unwrap();
+    let options = fs_extra::dir::CopyOptions::new();
+    fs_extra::dir::copy(&template, &temp_dir, &options).unwrap();
+    let mut test_dir = temp_dir.path().to_path_buf();
+    test_dir.push(template.file_name().unwrap());
+    (test_dir, temp_dir)
+}
+
+fn local_paths(walk_dir: WalkDir) -> Vec<String> {
+    let root = walk_dir.root().to_owned();
+    walk_dir
+        .into_iter()
+        .map(|each_result| {
+            let each_entry = each_result.unwrap();
+            if let Some(err) = each_entry.read_children_error.as_ref() {
+                panic!("should not encounter any child errors :{:?}", err);
+            }
+            let path = each_entry.path();
+            let path = path.strip_prefix(&root).unwrap().to_path_buf();
+            let mut path_string = path.to_str().unwrap().to_string();
+            path_string.push_str(&format!(" ({})", each_entry.depth));
+            path_string
+        })
+        .collect()
+}
+
+#[test]
+fn walk_serial() {
+    let (test_dir, _temp_dir) = test_dir();
+
+    let paths = local_paths(
+        WalkDir::new(test_dir)
+            .parallelism(Parallelism::Serial)
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn sort_by_name_rayon_custom_2_threads() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(test_dir)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn walk_custom_executor() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(test_dir)
+            .parallelism(Parallelism::Custom(std::sync::Arc::new(|op| {
+                std::thread::spawn(op);
+            })))
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn walk_relaxed_ordering() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let mut paths = local_paths(
+            WalkDir::new(&test_dir)
+                .parallelism(parallelism.clone())
+                .relaxed_ordering(true)
+                .sort(true),
+        );
+        let yield_order_paths = local_paths(
+            WalkDir::new(&test_dir)
+                .parallelism(parallelism)
+                .yield_order(YieldOrder::Relaxed)
+                .sort(true),
+        );
+        // Entries of a directory stay together and sorted
+        let position = |path: &str| paths.iter().position(|each| each == path).unwrap();
+        assert_eq!(position("b.txt (1)"), position("a.txt (1)") + 1);
+        assert_eq!(position("c.txt (1)"), position("b.txt (1)") + 1);
+        assert_eq!(yield_order_paths.len(), paths.len());
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                " (0)",
+                "a.txt (1)",
+                "b.txt (1)",
+                "c.txt (1)",
+                "group 1 (1)",
+                "group 1/d.txt (2)",
+                "group 2 (1)",
+                "group 2/e.txt (2)",
+            ]
+        );
+    }
+}
+
+#[test]
+fn walk_into_par_iter() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let paths: Vec<_> = WalkDir::new(&test_dir)
+            .parallelism(parallelism)
+            .sort(true)
+            .min_depth(1)
+            .into_par_iter()
+            .map(|each_result| {
+                let each_entry = each_result.unwrap();
+                let path = each_entry.path();
+                let path = path.strip_prefix(&test_dir).unwrap().to_path_buf();
+                format!("{} ({})", path.to_str().unwrap(), each_entry.depth)
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "a.txt (1)",
+                "b.txt (1)",
+                "c.txt (1)",
+                "group 1 (1)",
+                "group 1/d.txt (2)",
+                "group 2 (1)",
+                "group 2/e.txt (2)",
+            ]
+        );
+    }
+}
+
+#[test]
+fn walk_per_device_concurrency() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let dir = Dir::tmp();
+    for i in 0..8 {
+        dir.mkdirp(format!("{}", i));
+        dir.touch_all(&[format!("{}/a", i), format!("{}/b", i)]);
+    }
+
+    // Entries are checked while their directory is read, so checks of
+    // different directories only overlap if their reads do.
+    let active = Arc::new(AtomicUsize::new(0));
+    let max_active = Arc::new(AtomicUsize::new(0));
+    let (active_by, max_active_by) = (active.clone(), max_active.clone());
+    let wd = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(4))
+        .per_device_concurrency(1)
+        .skip_hidden_by(move |_, _| {
+            let now_active = active_by.fetch_add(1, Ordering::SeqCst) + 1;
+            max_active_by.fetch_max(now_active, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            active_by.fetch_sub(1, Ordering::SeqCst);
+            false
+        });
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(25, r.ents().len());
+    assert_eq!(1, max_active.load(Ordering::SeqCst));
+}
+
+#[test]
+fn walk_summary() {
+    let (test_dir, _temp_dir) = test_dir();
+    let mut iter = WalkDir::new(&test_dir)
+        .skip_hidden(false)
+        .preload_metadata_ext(true)
+        .into_iter();
+    assert_eq!(2, iter.by_ref().take(2).count());
+    assert_eq!(2, iter.summary().entries);
+
+    let summary = iter.finish();
+    assert_eq!(9, summary.entries);
+    assert_eq!(3, summary.dirs);
+    assert_eq!(6, summary.files);
+    assert_eq!(0, summary.symlinks);
+    assert_eq!(0, summary.errors);
+    #[cfg(any(unix, windows))]
+    assert_eq!(2 + 9 + 2 + 7 + 7, summary.bytes);
+}
+
+#[test]
+fn walk_into_batched_iter() {
+    let (test_dir, _temp_dir) = test_dir();
+    let batches: Vec<_> = WalkDir::new(&test_dir)
+        .batch_size(3)
+        .sort(true)
+        .into_batched_iter()
+        .collect();
+    assert_eq!(
+        batches.iter().map(Vec::len).collect::<Vec<_>>(),
+        vec![3, 3, 2]
+    );
+
+    let batched_paths: Vec<_> = batches
+        .into_iter()
+        .flatten()
+        .map(|each_result| each_result.unwrap().path())
+        .collect();
+    let paths: Vec<_> = WalkDir::new(&test_dir)
+        .sort(true)
+        .into_iter()
+        .map(|each_result| each_result.unwrap().path())
+        .collect();
+    assert_eq!(batched_paths, paths);
+}
+
+#[test]
+fn walk_resume_from_checkpoint() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected: Vec<_> = local_paths(WalkDir::new(&test_dir).sort(true))
+        .into_iter()
+        .map(|path| path.rsplit_once(' ').unwrap().0.to_string())
+        .collect();
+    let walk_dir = |parallelism: &Parallelism| {
+        WalkDir::new(&test_dir)
+            .parallelism(parallelism.clone())
+            .sort(true)
+    };
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        for taken in 0..=expected.len() {
+            let mut iter = walk_dir(&parallelism).into_iter();
+            let mut paths: Vec<_> = iter
+                .by_ref()
+                .take(taken)
+                .map(|each_result| each_result.unwrap().path())
+                .collect();
+            let checkpoint = iter.checkpoint().unwrap();
+            assert_eq!(checkpoint.is_complete(), taken == expected.len());
+            drop(iter);
+
+            paths.extend(
+                walk_dir(&parallelism)
+                    .resume_from(checkpoint)
+                    .into_iter()
+                    .map(|each_result| each_result.unwrap().path()),
+            );
+            let paths: Vec<_> = paths
+                .iter()
+                .map(|path| path.strip_prefix(&test_dir).unwrap().to_str().unwrap())
+                .collect();
+            assert_eq!(paths, expected);
+        }
+    }
+}
+
+#[test]
+fn walk_skip_until_take_until() {
+    let (test_dir, _temp_dir) = test_dir();
+    let local = |each_result: Result<DirEntry<((), ())>>| {
+        let path = each_result.unwrap().path();
+        let path = path.strip_prefix(&test_dir).unwrap().to_path_buf();
+        path.to_str().unwrap().to_string()
+    };
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = || {
+            WalkDir::new(&test_dir)
+                .parallelism(parallelism.clone())
+                .sort(true)
+        };
+
+        let mut iter = walk_dir().into_iter();
+        assert!(iter.skip_until(test_dir.join("group 1/d.txt")));
+        let paths: Vec<_> = iter.map(local).collect();
+        assert_eq!(paths, vec!["group 1/d.txt", "group 2", "group 2/e.txt"]);
+
+        let mut iter = walk_dir().into_iter();
+        assert!(iter.skip_until(test_dir.join("b.txt")));
+        let page: Vec<_> = iter
+            .take_until(test_dir.join("group 2"))
+            .map(local)
+            .collect();
+        assert_eq!(page, vec!["b.txt", "c.txt", "group 1", "group 1/d.txt"]);
+
+        let mut iter = walk_dir().into_iter();
+        assert!(!iter.skip_until(test_dir.join("group 1/missing.txt")));
+        let paths: Vec<_> = iter.map(local).collect();
+        assert_eq!(paths, vec!["group 2", "group 2/e.txt"]);
+    }
+}
+
+#[test]
+fn walk_skip_current_dir() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk = |skip_at: &str| {
+            let mut iter = WalkDir::new(&test_dir)
+                .parallelism(parallelism.clone())
+                .sort(true)
+                .into_iter();
+            let mut paths = Vec::new();
+            while let Some(each_result) = iter.next() {
+                let path = each_result.unwrap().path();
+                let path = path.strip_prefix(&test_dir).unwrap().to_path_buf();
+                let path = path.to_str().unwrap().to_string();
+                if path == skip_at {
+                    iter.skip_current_dir();
+                }
+                paths.push(path);
+            }
+            paths
+        };
+
+        assert_eq!(
+            walk("group 1"),
+            vec![
+                "",
+                "a.txt",
+                "b.txt",
+                "c.txt",
+                "group 1",
+                "group 2",
+                "group 2/e.txt"
+            ]
+        );
+        assert_eq!(walk("b.txt"), vec!["", "a.txt", "b.txt"]);
+        assert_eq!(walk(""), vec![""]);
+    }
+
+    // Scheduled reads below the skipped directory must not be mistaken for
+    // those of its siblings.
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.mkdirp("a/d/e");
+    dir.mkdirp("f/g");
+    let mut iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(2))
+        .sort(true)
+        .into_iter();
+    let mut paths = Vec::new();
+    while let Some(each_result) = iter.next() {
+        let path = each_result.unwrap().path();
+        if path == dir.join("a") {
+            iter.skip_current_dir();
+        }
+        paths.push(path);
+    }
+    assert_eq!(
+        paths,
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("a"),
+            dir.join("f"),
+            dir.join("f").join("g"),
+        ]
+    );
+}
+
+#[test]
+fn walk_filter_entry() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.mkdirp("a/d");
+    dir.mkdirp("e/f");
+    dir.touch("e/a");
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let paths: Vec<_> = WalkDir::new(dir.path())
+            .parallelism(parallelism)
+            .sort(true)
+            .into_iter()
+            .filter_entry(|dir_entry| dir_entry.file_name() != "a")
+            .map(|each_result| each_result.unwrap().path())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().to_path_buf(),
+                dir.join("e"),
+                dir.join("e").join("f"),
+            ]
+        );
+    }
+}
+
+#[test]
+fn walk_max_pending_entries() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(test_dir)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .max_pending_entries(1)
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn walk_max_pending_entries_frees_pool() {
+    let (test_dir, _temp_dir) = test_dir();
+    let pool = std::sync::Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap(),
+    );
+    let mut iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonExistingPool {
+            pool: pool.clone(),
+            busy_timeout: None,
+        })
+        .max_pending_entries(1)
+        .into_iter();
+    assert!(iter.next().is_some());
+
+    // Other work on the pool runs while the consumer is stalled.
+    let (tx, rx) = std::sync::mpsc::channel();
+    pool.spawn(move || tx.send(()).unwrap());
+    assert!(rx.recv_timeout(std::time::Duration::from_secs(5)).is_ok());
+
+    assert_eq!(7, iter.count());
+}
+
+#[test]
+fn walk_backend() {
+    let (test_dir, _temp_dir) = test_dir();
+    let std = local_paths(WalkDir::new(&test_dir).backend(Backend::Std));
+    let native = local_paths(WalkDir::new(&test_dir).backend(Backend::Native));
+    assert_eq!(std, native);
+    assert!(!native.is_empty());
+}
+
+#[test]
+fn walk_backend_dir_fd() {
+    let (test_dir, _temp_dir) = test_dir();
+    let std = local_paths(WalkDir::new(&test_dir).backend(Backend::Std));
+    let dir_fd = local_paths(WalkDir::new(&test_dir).backend(Backend::DirFd));
+    assert_eq!(std, dir_fd);
+
+    let mut par_paths: Vec<_> = WalkDir::new(&test_dir)
+        .backend(Backend::DirFd)
+        .into_par_iter()
+        .map(|each| each.unwrap().path())
+        .collect();
+    par_paths.sort();
+    let mut paths: Vec<_> = WalkDir::new(&test_dir)
+        .into_iter()
+        .map(|each| each.unwrap().path())
+        .collect();
+    paths.sort();
+    assert_eq!(paths, par_paths);
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch("a/b/zzz");
+    dir.symlink_dir(dir.join("a"), "a-link");
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a").join("b"),
+        dir.join("a").join("b").join("zzz"),
+        dir.join("a-link"),
+        dir.join("a-link").join("b"),
+        dir.join("a-link").join("b").join("zzz"),
+    ];
+    let wd = WalkDir::new(dir.path())
+        .backend(Backend::DirFd)
+        .follow_links(true)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(expected, r.paths());
+
+    let mut par_paths: Vec<_> = WalkDir::new(dir.path())
+        .backend(Backend::DirFd)
+        .follow_links(true)
+        .into_par_iter()
+        .map(|each| each.unwrap().path())
+        .collect();
+    par_paths.sort();
+    assert_eq!(expected, par_paths);
+}
+
+#[cfg(all(
+    feature = "fast_readdir",
+    any(target_os = "linux", target_os = "macos")
+))]
+#[test]
+fn walk_from_dir_handle() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected: Vec<_> = WalkDir::new(&test_dir)
+        .sort(true)
+        .into_iter()
+        .map(|each| {
+            let path = each.unwrap().path();
+            let path = path.strip_prefix(&test_dir).unwrap();
+            Path::new(".").join(path).components().collect::<PathBuf>()
+        })
+        .collect();
+
+    let walk_dir = WalkDir::from_dir_handle(fs::File::open(&test_dir).unwrap()).sort(true);
+    for _ in 0..2 {
+        let paths: Vec<_> = walk_dir.run().map(|each| each.unwrap().path()).collect();
+        assert_eq!(paths, expected);
+    }
+}
+
+#[test]
+fn walk_listing_cache() {
+    let (test_dir, _temp_dir) = test_dir();
+    let listings = std::sync::Arc::new(Mutex::new(std::collections::HashMap::new()));
+    let walk = || local_paths(WalkDir::new(&test_dir).listing_cache(listings.clone()));
+    let expected = local_paths(WalkDir::new(&test_dir));
+    assert_eq!(walk(), expected);
+    assert_eq!(listings.lock().unwrap().len(), 3);
+
+    // Unchanged directories are listed from the cache
+    let group_1 = test_dir.join("group 1");
+    let mut listing = ListingCache::get(listings.as_ref(), &group_1)
+        .unwrap()
+        .as_ref()
+        .clone();
+    let file_type = listing.entries[0].1;
+    listing.entries.push(("cached.txt".into(), file_type));
+    ListingCache::insert(
+        listings.as_ref(),
+        &group_1,
+        std::sync::Arc::new(listing.clone()),
+    );
+    assert!(walk().contains(&"group 1/cached.txt (2)".to_string()));
+
+    // and listed again once their modification time differs
+    listing.modified = std::time::SystemTime::UNIX_EPOCH;
+    ListingCache::insert(listings.as_ref(), &group_1, std::sync::Arc::new(listing));
+    assert_eq!(walk(), expected);
+}
+
+#[test]
+fn walk_diff() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.mkdirp("b/c");
+    dir.touch_all(&[
+        "a/keep.txt",
+        "a/edit.txt",
+        "a/gone.txt",
+        "b/x.txt",
+        "b/c/y.txt",
+    ]);
+    dir.touch("same.txt");
+    let snapshot: Snapshot = WalkDir::new(dir.path()).into_iter().flatten().collect();
+    assert_eq!(snapshot.len(), 10);
+
+    fs::write(dir.join("a/edit.txt"), "more").unwrap();
+    fs::remove_file(dir.join("a/gone.txt")).unwrap();
+    dir.touch("a/new.txt");
+    fs::remove_dir_all(dir.join("b")).unwrap();
+
+    let changes: Vec<_> = diff(snapshot, WalkDir::new(dir.path()).sort(true))
+        .map(|change| {
+            let change = change.unwrap();
+            let kind = match &change {
+                Change::Added(_) => "added",
+                Change::Removed(_) => "removed",
+                Change::Modified(_) => "modified",
+                Change::Unchanged(_) => "unchanged",
+            };
+            let path = change.dir_entry().path();
+            let path = path.strip_prefix(dir.path()).unwrap().to_path_buf();
+            (kind, path.to_str().unwrap().to_string())
+        })
+        .collect();
+    let expected = [
+        ("modified", ""),
+        ("modified", "a"),
+        ("modified", "a/edit.txt"),
+        ("unchanged", "a/keep.txt"),
+        ("added", "a/new.txt"),
+        ("removed", "a/gone.txt"),
+        ("unchanged", "same.txt"),
+        ("removed", "b"),
+        ("removed", "b/c"),
+        ("removed", "b/c/y.txt"),
+        ("removed", "b/x.txt"),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(kind, path)| (*kind, path.to_string()))
+        .collect();
+    assert_eq!(changes, expected);
+}
+
+#[test]
+fn walk_max_entries() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        for yield_order in [YieldOrder::Strict, YieldOrder::Relaxed] {
+            let walk_dir = || {
+                WalkDir::new(&test_dir)
+                    .parallelism(parallelism.clone())
+                    .yield_order(yield_order)
+            };
+
+            let mut iter = walk_dir().max_entries(3).into_iter();
+            assert_eq!(iter.by_ref().count(), 3);
+            assert!(iter.summary().truncated);
+            assert_eq!(iter.next().map(|_| ()), None);
+
+            let summary = walk_dir().max_entries(8).into_iter().finish();
+            assert_eq!(summary.entries, 8);
+            assert!(!summary.truncated);
+        }
+    }
+}
+
+#[test]
+fn walk_iterative_deepening() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk = |walk_dir: WalkDir| -> Vec<String> {
+        let mut iter = walk_dir.sort(true).iterative_deepening(true).into_iter();
+        let paths = iter
+            .by_ref()
+            .map(|each| {
+                let path = each.unwrap().path();
+                let path = path.strip_prefix(&test_dir).unwrap().to_path_buf();
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(
+            iter.summary().entries,
+            iter.summary().dirs + iter.summary().files
+        );
+        paths
+    };
+
+    assert_eq!(
+        walk(WalkDir::new(&test_dir)),
+        vec![
+            "",
+            "a.txt",
+            "b.txt",
+            "c.txt",
+            "group 1",
+            "group 2",
+            "group 1/d.txt",
+            "group 2/e.txt",
+        ]
+    );
+    assert_eq!(
+        walk(WalkDir::new(&test_dir).min_depth(2)),
+        vec!["group 1/d.txt", "group 2/e.txt"]
+    );
+    assert_eq!(walk(WalkDir::new(&test_dir).max_depth(1)).len(), 6);
+    assert_eq!(walk(WalkDir::new(&test_dir).max_depth(0)), vec![""]);
+}
+
+#[test]
+fn walk_process_read_dir_with_parent() {
+    let (test_dir, _temp_dir) = test_dir();
+    // Entries are ignored if their directory is named "group 1" or ignored
+    let ignored: Vec<_> = WalkDirGeneric::<((), bool)>::new(&test_dir)
+        .sort(true)
+        .process_read_dir_with_parent(|parent, _, children| {
+            let parent_ignored = parent.is_some_and(|parent| {
+                assert!(parent.file_type().is_dir());
+                parent.client_state || parent.file_name() == "group 1"
+            });
+            children.iter_mut().flatten().for_each(|dir_entry| {
+                assert_eq!(dir_entry.depth == 0, parent.is_none());
+                dir_entry.client_state = parent_ignored;
+            });
+        })
+        .into_iter()
+        .map(|each| each.unwrap())
+        .filter(|dir_entry| dir_entry.client_state)
+        .map(|dir_entry| dir_entry.file_name().to_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(ignored, vec!["d.txt"]);
+}
+
+#[test]
+fn walk_preload_metadata() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/b.txt", "c.txt"]);
+    fs::write(dir.join("a/b.txt"), "data").unwrap();
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let entries: Vec<_> = WalkDir::new(dir.path())
+            .sort(true)
+            .parallelism(parallelism)
+            .preload_metadata(true)
+            .into_iter()
+            .map(|each| each.unwrap())
+            .collect();
+        fs::remove_file(dir.join("a/b.txt")).unwrap();
+        // Read before the file was removed
+        let b_txt = &entries[2];
+        assert_eq!(b_txt.file_name(), "b.txt");
+        assert!(b_txt.metadata().is_err());
+        assert_eq!(b_txt.metadata_cached().unwrap().len(), 4);
+        assert!(entries
+            .iter()
+            .all(|dir_entry| dir_entry.metadata_cached().unwrap().file_type()
+                == dir_entry.file_type()));
+        fs::write(dir.join("a/b.txt"), "data").unwrap();
+    }
+
+    // Without preloading, the first call reads it and later ones don't
+    let entries: Vec<_> = WalkDir::new(dir.path())
+        .sort(true)
+        .into_iter()
+        .map(|each| each.unwrap())
+        .collect();
+    assert_eq!(entries[2].metadata_cached().unwrap().len(), 4);
+    fs::remove_file(dir.join("a/b.txt")).unwrap();
+    assert_eq!(entries[2].metadata_cached().unwrap().len(), 4);
+    assert!(entries[3].metadata_cached().is_ok());
+}
+
+#[test]
+fn walk_include_root() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let roots_processed = std::sync::Arc::new(Mutex::new(0));
+        let roots = roots_processed.clone();
+        let paths = local_paths(
+            WalkDir::new(&test_dir)
+                .sort(true)
+                .parallelism(parallelism)
+                .include_root(false)
+                .process_read_dir(move |depth, _, _, _| {
+                    if depth.is_none() {
+                        *roots.lock().unwrap() += 1;
+                    }
+                }),
+        );
+        assert_eq!(
+            paths,
+            vec![
+                "a.txt (1)",
+                "b.txt (1)",
+                "c.txt (1)",
+                "group 1 (1)",
+                "group 1/d.txt (2)",
+                "group 2 (1)",
+                "group 2/e.txt (2)",
+            ]
+        );
+        assert_eq!(*roots_processed.lock().unwrap(), 1);
+    }
+
+    let file_root = WalkDir::new(test_dir.join("a.txt")).include_root(false);
+    assert_eq!(file_root.into_iter().count(), 0);
+    let missing_root = WalkDir::new(test_dir.join("missing")).include_root(false);
+    assert!(missing_root.into_iter().next().unwrap().is_err());
+}
+
+#[test]
+fn walk_terminal_operations() {
+    let (test_dir, _temp_dir) = test_dir();
+    for (path, contents) in [
+        ("a.txt", "aaa"),
+        ("b.txt", ""),
+        ("c.txt", ""),
+        ("group 1/d.txt", "dddd"),
+        ("group 2/e.txt", ""),
+    ] {
+        fs::write(test_dir.join(path), contents).unwrap();
+    }
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = || {
+            WalkDir::new(&test_dir)
+                .sort(true)
+                .parallelism(parallelism.clone())
+        };
+        assert_eq!(walk_dir().count_entries(), 8);
+        assert_eq!(walk_dir().min_depth(2).count_entries(), 2);
+        assert_eq!(walk_dir().total_len(), 7);
+        assert_eq!(walk_dir().max_depth(1).total_len(), 3);
+
+        let mut paths = walk_dir().collect_paths();
+        paths.sort();
+        let expected: Vec<_> = walk_dir()
+            .into_iter()
+            .map(|each| each.unwrap().path())
+            .collect();
+        assert_eq!(paths, expected);
+    }
+    assert_eq!(WalkDir::new(test_dir.join("missing")).count_entries(), 0);
+}
+
+#[test]
+fn walk_relative_path() {
+    let (test_dir, _temp_dir) = test_dir();
+    let relative = |root: &Path| -> Vec<PathBuf> {
+        WalkDir::new(root)
+            .sort(true)
+            .into_iter()
+            .map(|each| {
+                let dir_entry = each.unwrap();
+                let relative_path = dir_entry.relative_path();
+                assert_eq!(relative_path, dir_entry.path().strip_prefix(root).unwrap());
+                relative_path
+            })
+            .collect()
+    };
+    let expected: Vec<PathBuf> = vec![
+        "".into(),
+        "a.txt".into(),
+        "b.txt".into(),
+        "c.txt".into(),
+        "group 1".into(),
+        ["group 1", "d.txt"].iter().collect(),
+        "group 2".into(),
+        ["group 2", "e.txt"].iter().collect(),
+    ];
+    assert_eq!(relative(&test_dir), expected);
+    assert_eq!(relative(&test_dir.join("group 1").join("..")), expected);
+    assert_eq!(
+        relative(&test_dir.join("group 2")),
+        vec![PathBuf::new(), PathBuf::from("e.txt")]
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn walk_non_utf8_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = Dir::tmp();
+    let names = [&b"x2"[..], b"x10", b"x1\xff", b".\xff"];
+    for name in names {
+        fs::write(dir.path().join(OsStr::from_bytes(name)), "").unwrap();
+    }
+    let file_names = |walk_dir: WalkDir| -> Vec<String> {
+        walk_dir
+            .min_depth(1)
+            .into_iter()
+            .map(|each| each.unwrap().file_name_lossy_cached().to_owned())
+            .collect()
+    };
+
+    assert_eq!(
+        file_names(WalkDir::new(dir.path()).sort(Sort::Natural)),
+        vec!["x1\u{fffd}", "x2", "x10"]
+    );
+    assert_eq!(
+        file_names(WalkDir::new(dir.path()).skip_hidden(false).sort(Sort::Name)),
+        vec![".\u{fffd}", "x10", "x1\u{fffd}", "x2"]
+    );
+}
+
+#[test]
+fn walk_iterative_deepening_once() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.symlink_file("missing", "broken");
+
+    let visited = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let visited_by = visited.clone();
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .iterative_deepening(true)
+        .on_visit_dir(move |path| visited_by.lock().unwrap().push(path.to_owned()));
+    let r = dir.run_recursive(wd);
+    assert_eq!(4, r.ents().len());
+    assert_eq!(1, r.errs().len());
+    assert_eq!(Some(&*dir.join("broken")), r.errs()[0].path());
+
+    let mut visited = visited.lock().unwrap().clone();
+    visited.sort();
+    assert_eq!(
+        visited,
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("a"),
+            dir.join("a/b"),
+            dir.join("a/b/c"),
+        ]
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn walk_iterative_deepening_skip_visited_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/sub");
+    dir.touch_all(&["a/x", "a/sub/y"]);
+    dir.symlink_dir("a", "b");
+    dir.symlink_dir("a", "c");
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .skip_visited_dirs(true)
+        .iterative_deepening(true)
+        .parallelism(Parallelism::RayonNewPool(2));
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    // Each round descends through the link the first round descended into
+    let parents: std::collections::HashSet<_> = r
+        .ents()
+        .iter()
+        .filter(|ent| ent.depth() >= 2)
+        .map(|ent| {
+            let path = ent.path();
+            let link = path.strip_prefix(dir.path()).unwrap().iter().next();
+            link.unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(1, parents.len());
+    assert_eq!(7, r.ents().len());
+}
+
+#[test]
+fn walk_on_visit_dir() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let visited = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let visited_by = visited.clone();
+        let walk_dir = WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(parallelism)
+            .on_visit_dir(move |path| visited_by.lock().unwrap().push(path.to_owned()));
+        for dir_entry in walk_dir {
+            let dir_entry = dir_entry.unwrap();
+            if dir_entry.depth > 0 {
+                // The directory of each entry was visited before it is yielded
+                let parent_path = dir_entry.parent_path().to_owned();
+                assert!(visited.lock().unwrap().contains(&parent_path));
+            }
+        }
+        let mut visited = visited.lock().unwrap().clone();
+        visited.sort();
+        assert_eq!(
+            visited,
+            vec![
+                test_dir.clone(),
+                test_dir.join("group 1"),
+                test_dir.join("group 2")
+            ]
+        );
+    }
+
+    let visited = std::sync::Arc::new(Mutex::new(0));
+    let visited_by = visited.clone();
+    let walk_dir = WalkDir::new(&test_dir)
+        .max_depth(1)
+        .on_visit_dir(move |_| *visited_by.lock().unwrap() += 1);
+    assert_eq!(walk_dir.into_iter().count(), 6);
+    assert_eq!(*visited.lock().unwrap(), 1);
+}
+
+#[test]
+fn walk_on_read_dir_complete() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let completed = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let completed_by = completed.clone();
+        let root = test_dir.clone();
+        let walk_dir = WalkDirGeneric::<(usize, ())>::new(&test_dir)
+            .parallelism(parallelism)
+            .process_read_dir(|_, _, read_dir_state, children| {
+                *read_dir_state = children.len();
+            })
+            .on_read_dir_complete(move |depth, path, read_dir_state, children| {
+                assert_eq!(*read_dir_state, children.len());
+                let path = path.strip_prefix(&root).unwrap().to_owned();
+                completed_by
+                    .lock()
+                    .unwrap()
+                    .push((depth, path, children.len()));
+            });
+        assert_eq!(walk_dir.into_iter().count(), 8);
+        let mut completed = completed.lock().unwrap().clone();
+        completed.sort();
+        assert_eq!(
+            completed,
+            vec![
+                (0, PathBuf::new(), 5),
+                (1, PathBuf::from("group 1"), 1),
+                (1, PathBuf::from("group 2"), 1)
+            ]
+        );
+    }
+}
+
+#[test]
+fn walk_run_again() {
+    let (test_dir, _temp_dir) = test_dir();
+    let processed = std::sync::Arc::new(Mutex::new(0));
+    let processed_by = processed.clone();
+    let walk_dir = WalkDir::new(&test_dir)
+        .sort(true)
+        .process_read_dir(move |_, _, _, _| *processed_by.lock().unwrap() += 1);
+    let paths = |iter: DirEntryIter<((), ())>| -> Vec<PathBuf> {
+        iter.map(|each| each.unwrap().path()).collect()
+    };
+
+    let first = paths(walk_dir.run());
+    assert_eq!(first.len(), 8);
+    fs::write(test_dir.join("0.txt"), "").unwrap();
+    let second = paths(walk_dir.run());
+    assert_eq!(second[1], test_dir.join("0.txt"));
+    assert_eq!(second[2..], first[1..]);
+    // Clones keep the order and share the callback
+    assert_eq!(paths(walk_dir.clone().into_iter()), second);
+    assert_eq!(*processed.lock().unwrap(), 3 * 4);
+}
+
+#[test]
+fn walk_max_entries_per_dir() {
+    let (test_dir, _temp_dir) = test_dir();
+    let entries: Vec<_> = WalkDir::new(&test_dir)
+        .max_entries_per_dir(2)
+        .into_iter()
+        .map(|each| each.unwrap())
+        .collect();
+    let root = &entries[0];
+    assert!(root.read_children_truncated);
+    assert_eq!(entries.iter().filter(|e| e.depth == 1).count(), 2);
+    for dir_entry in &entries[1..] {
+        assert!(!dir_entry.read_children_truncated);
+    }
+
+    let entries: Vec<_> = WalkDir::new(&test_dir)
+        .max_entries_per_dir(5)
+        .into_iter()
+        .map(|each| each.unwrap())
+        .collect();
+    assert_eq!(entries.len(), 8);
+    assert!(entries.iter().all(|e| !e.read_children_truncated));
+
+    let paths = local_paths(WalkDir::new(&test_dir).max_entries_per_dir(0));
+    assert_eq!(paths, vec![" (0)"]);
+}
+
+#[test]
+fn walk_sample() {
+    let dir = Dir::tmp();
+    for i in 0..20 {
+        dir.mkdirp(format!("d{}", i));
+        for j in 0..50 {
+            dir.touch(format!("d{}/f{}", i, j));
+        }
+    }
+    let sample = |ratio: f64, seed: u64| -> (usize, Vec<PathBuf>) {
+        let mut dirs = 0;
+        let mut files = Vec::new();
+        for dir_entry in WalkDir::new(dir.path()).sort(true).sample(ratio, seed) {
+            let dir_entry = dir_entry.unwrap();
+            if dir_entry.file_type().is_dir() {
+                dirs += 1;
+            } else {
+                files.push(dir_entry.relative_path());
+            }
+        }
+        (dirs, files)
+    };
+
+    let (dirs, files) = sample(0.1, 7);
+    assert_eq!(dirs, 21);
+    assert!((50..150).contains(&files.len()), "{}", files.len());
+    assert_eq!(sample(0.1, 7).1, files);
+    assert_ne!(sample(0.1, 8).1, files);
+    assert_eq!(sample(0.0, 7), (21, Vec::new()));
+    assert_eq!(sample(1.0, 7).1.len(), 1000);
+}
+
+#[test]
+fn walk_filter_metadata() {
+    let dir = Dir::tmp();
+    dir.mkdirp("keep");
+    dir.mkdirp("skip");
+    dir.touch("keep/empty");
+    fs::write(dir.join("keep/a"), "a").unwrap();
+    fs::write(dir.join("skip/b"), "b").unwrap();
+
+    let walk_dir = |prunes: bool| {
+        WalkDir::new(dir.path())
+            .sort(true)
+            .filter_metadata(|path, metadata| match metadata.is_dir() {
+                true => !path.ends_with("skip"),
+                false => metadata.len() > 0,
+            })
+            .filter_metadata_prunes(prunes)
+    };
+    let walk = |prunes: bool| -> Vec<PathBuf> {
+        walk_dir(prunes)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().relative_path())
+            .collect()
+    };
+    let expected: Vec<PathBuf> = vec!["".into(), "keep".into(), "keep/a".into()];
+    assert_eq!(walk(true), expected);
+    let mut expected = expected;
+    expected.push("skip/b".into());
+    assert_eq!(walk(false), expected);
+
+    // Entries failing the filter aren't counted as siblings
+    let siblings: Vec<_> = walk_dir(false)
+        .into_iter()
+        .map(|dir_entry| {
+            let dir_entry = dir_entry.unwrap();
+            (dir_entry.sibling_index(), dir_entry.sibling_count())
+        })
+        .collect();
+    assert_eq!(vec![(0, 1), (0, 1), (0, 1), (0, 1)], siblings);
+}
+
+#[test]
+fn walk_prune() {
+    let dir = Dir::tmp();
+    for path in [".git", "target/debug", "src/target", "web/node_modules/lib"] {
+        dir.mkdirp(path);
+    }
+    dir.touch_all(&[
+        ".git/HEAD",
+        "target/debug/app",
+        "src/main.rs",
+        "src/target/mod.rs",
+        "web/app.js",
+        "web/node_modules/lib/index.js",
+    ]);
+
+    let paths: Vec<PathBuf> = WalkDir::new(dir.path())
+        .sort(true)
+        .skip_hidden(false)
+        .prune_names(&[".git"])
+        .prune_paths(&[PathBuf::from("target"), dir.join("web/node_modules")])
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().relative_path())
+        .collect();
+    let expected: Vec<PathBuf> = [
+        "",
+        "src",
+        "src/main.rs",
+        "src/target",
+        "src/target/mod.rs",
+        "web",
+        "web/app.js",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+    assert_eq!(paths, expected);
+}
+
+#[test]
+fn walk_pending_and_completed_dirs() {
+    let (test_dir, _temp_dir) = test_dir();
+
+    let mut iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .into_iter();
+    assert_eq!((iter.pending_dirs(), iter.completed_dirs()), (1, 0));
+    for _ in iter.by_ref() {}
+    assert_eq!((iter.pending_dirs(), iter.completed_dirs()), (0, 3));
+
+    let mut iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonNewPool(2))
+        .into_iter();
+    assert!(iter.pending_dirs() + iter.completed_dirs() >= 1);
+    for _ in iter.by_ref() {}
+    assert_eq!(iter.completed_dirs(), 3);
+}
+
+#[test]
+fn walk_into_enriched() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let (walk, enriched) = WalkDir::new(&test_dir)
+            .parallelism(parallelism)
+            .skip_hidden(false)
+            .into_enriched(
+                |dir_entry| dir_entry.file_type().is_file(),
+                |dir_entry| (dir_entry.path(), dir_entry.metadata().unwrap().len()),
+            );
+        assert_eq!(walk.count(), 9);
+        let mut enriched: Vec<_> = enriched.collect();
+        enriched.sort();
+        for (path, len) in &enriched {
+            assert_eq!(*len, fs::metadata(path).unwrap().len());
+        }
+        let names: Vec<_> = enriched
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "a.txt",
+                "b.txt",
+                "c.txt",
+                "d.txt",
+                ".hidden_file.txt",
+                "e.txt"
+            ]
+        );
+    }
+}
+
+#[test]
+fn walk_dirs_only() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["f", "a/g", "a/b/h"]);
+    dir.symlink_dir("a/b", "b-link");
+    dir.symlink_file("f", "f-link");
+
+    let walk = |follow_links: bool, backend: Backend| -> Vec<PathBuf> {
+        WalkDir::new(dir.path())
+            .sort(true)
+            .backend(backend)
+            .follow_links(follow_links)
+            .dirs_only(true)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().relative_path())
+            .collect()
+    };
+    for backend in [Backend::Std, Backend::Native] {
+        let expected: Vec<PathBuf> = vec!["".into(), "a".into(), "a/b".into()];
+        assert_eq!(walk(false, backend), expected);
+        let mut expected = expected;
+        expected.push("b-link".into());
+        assert_eq!(walk(true, backend), expected);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_inspect_raw_entry() {
+    use std::os::unix::fs::{DirEntryExt, MetadataExt};
+
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDirGeneric::<((), Option<u64>)>::new(&test_dir)
+        .backend(Backend::Native)
+        .inspect_raw_entry(|fs_dir_entry, dir_entry| {
+            assert_eq!(fs_dir_entry.file_name(), dir_entry.file_name);
+            dir_entry.client_state = Some(fs_dir_entry.ino());
+        });
+    let mut count = 0;
+    for dir_entry in walk_dir.min_depth(1) {
+        let dir_entry = dir_entry.unwrap();
+        let ino = fs::symlink_metadata(dir_entry.path()).unwrap().ino();
+        assert_eq!(dir_entry.client_state, Some(ino));
+        count += 1;
+    }
+    assert_eq!(count, 7);
+}
+
+#[test]
+fn walk_busy_fallback() {
+    let (test_dir, _temp_dir) = test_dir();
+    let pool = std::sync::Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap(),
+    );
+    // The only thread of the pool is running the walk, so it can't start.
+    let walk = |busy_fallback: BusyFallback| {
+        pool.install(|| {
+            WalkDir::new(&test_dir)
+                .parallelism(Parallelism::RayonExistingPool {
+                    pool: pool.clone(),
+                    busy_timeout: Some(std::time::Duration::from_millis(10)),
+                })
+                .busy_fallback(busy_fallback)
+                .into_iter()
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let busy = walk(BusyFallback::Error);
+    assert!(busy.iter().any(|r| matches!(r, Err(err) if err.is_busy())));
+
+    let serial = walk(BusyFallback::Serial);
+    assert_eq!(8, serial.len());
+    assert!(serial.iter().all(Result::is_ok));
+
+    let new_pool = walk(BusyFallback::NewPool(2));
+    assert_eq!(8, new_pool.len());
+    assert!(new_pool.iter().all(Result::is_ok));
+}
+
+#[test]
+fn walk_size_hint() {
+    let (test_dir, _temp_dir) = test_dir();
+    let mut iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .sort(true)
+        .into_iter();
+    assert_eq!((1, None), iter.size_hint());
+    iter.next().unwrap().unwrap();
+    // The contents of the root were read with it.
+    assert_eq!((5, None), iter.size_hint());
+    assert_eq!(7, iter.by_ref().count());
+    assert_eq!((0, Some(0)), iter.size_hint());
+    assert!(iter.next().is_none());
+
+    let mut iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonNewPool(2))
+        .max_entries(2)
+        .into_iter();
+    assert_eq!((1, None), iter.size_hint());
+    assert_eq!(2, iter.by_ref().count());
+    assert_eq!((0, Some(0)), iter.size_hint());
+    assert!(iter.next().is_none());
+
+    // Errors don't count towards `max_entries`
+    let dir = Dir::tmp();
+    for name in ["a", "b", "c"] {
+        dir.symlink_file("missing", name);
+    }
+    let mut iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::Serial)
+        .follow_links(true)
+        .max_entries(2)
+        .into_iter();
+    iter.next().unwrap().unwrap();
+    assert_eq!((1, None), iter.size_hint());
+    assert_eq!(3, iter.by_ref().count());
+
+    let (errors_to, errors) = std::sync::mpsc::channel();
+    let mut iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::Serial)
+        .follow_links(true)
+        .max_entries(2)
+        .errors_to(errors_to)
+        .into_iter();
+    iter.next().unwrap().unwrap();
+    assert_eq!((0, Some(1)), iter.size_hint());
+    assert_eq!(0, iter.by_ref().count());
+    assert_eq!(3, errors.try_iter().count());
+
+    // Errors read again by later rounds of iterative deepening are dropped
+    dir.mkdirp("0/1");
+    let mut iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::Serial)
+        .follow_links(true)
+        .iterative_deepening(true)
+        .sort(true)
+        .into_iter();
+    let mut lower_bounds = vec![iter.size_hint().0];
+    while iter.next().is_some() {
+        lower_bounds.push(iter.size_hint().0);
+    }
+    let count = lower_bounds.len() - 1;
+    assert_eq!(6, count);
+    for (yielded, lower_bound) in lower_bounds.into_iter().enumerate() {
+        assert!(lower_bound <= count - yielded);
+    }
+}
+
+#[test]
+fn walk_spill_threshold() {
+    let dir = Dir::tmp();
+    for i in 0..20 {
+        for j in 0..5 {
+            dir.mkdirp(format!("{}/{}", i, j));
+        }
+    }
 
-fn local_paths(walk_dir: WalkDir) -> Vec<String> {
-    let root = walk_dir.root().to_owned();
-    walk_dir
-        .into_iter()
-        .map(|each_result| {
-            let each_entry = each_result.unwrap();
-            if let Some(err) = each_entry.read_children_error.as_ref() {
-                panic!("should not encounter any child errors :{:?}", err);
-            }
-            let path = each_entry.path();
-            let path = path.strip_prefix(&root).unwrap().to_path_buf();
-            let mut path_string = path.to_str().unwrap().to_string();
-            path_string.push_str(&format!(" ({})", each_entry.depth));
-            path_string
-        })
-        .collect()
+    let walk = |walk_dir: WalkDir| -> Vec<_> {
+        walk_dir
+            .sort(true)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .collect()
+    };
+    let expected = walk(WalkDir::new(dir.path()).parallelism(Parallelism::Serial));
+    let spilled = walk(
+        WalkDir::new(dir.path())
+            .parallelism(Parallelism::RayonNewPool(2))
+            .spill_threshold(4),
+    );
+    assert_eq!(121, expected.len());
+    assert_eq!(expected, spilled);
 }
 
 #[test]
-fn walk_serial() {
+fn walk_core_custom_read_dir() {
     let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let specs = vec![jwalk::custom::ReadDirSpec::<((), ())>::new(
+            &test_dir,
+            0,
+            (),
+        )];
+        let read_dirs = jwalk::custom::walk(specs, parallelism, |spec| {
+            let mut entries: Vec<_> = fs::read_dir(&spec.path)
+                .unwrap()
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    let file_type = entry.file_type().unwrap();
+                    Ok(DirEntry::new(
+                        spec.depth + 1,
+                        entry.file_name(),
+                        file_type,
+                        spec.path.clone(),
+                    ))
+                })
+                .collect();
+            entries.sort_by_key(|entry: &Result<DirEntry<((), ())>>| {
+                entry.as_ref().unwrap().file_name.clone()
+            });
+            Ok(jwalk::custom::ReadDir::new((), entries))
+        });
+        let paths: Vec<_> = read_dirs
+            .flat_map(|read_dir| read_dir.unwrap().into_results())
+            .map(|dir_entry| {
+                let dir_entry = dir_entry.unwrap();
+                let path = dir_entry.path();
+                let path = path.strip_prefix(&test_dir).unwrap().to_path_buf();
+                format!("{} ({})", path.display(), dir_entry.depth)
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "a.txt (1)",
+                "b.txt (1)",
+                "c.txt (1)",
+                "group 1 (1)",
+                "group 2 (1)",
+                "group 1/d.txt (2)",
+                "group 2/.hidden_file.txt (2)",
+                "group 2/e.txt (2)",
+            ]
+        );
+    }
+}
 
-    let paths = local_paths(
-        WalkDir::new(test_dir)
-            .parallelism(Parallelism::Serial)
-            .sort(true),
-    );
+#[test]
+fn walk_core_no_specs() {
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let specs: Vec<jwalk::custom::ReadDirSpec<((), ())>> = vec![];
+        let mut read_dirs = jwalk::custom::walk(specs, parallelism, |_| unreachable!());
+        assert!(read_dirs.next().is_none());
+    }
+
+    let (_queue, mut iter) = jwalk::ordered::ordered_queue::<()>(jwalk::ordered::Ordering::Strict);
+    iter.expect_roots(0);
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn ordered_queue_strict_priority() {
+    use jwalk::ordered::{ordered_queue, IndexPath, Ordered, Ordering};
+
+    let (queue, iter) = ordered_queue::<&str>(Ordering::Strict);
+    queue
+        .push(Ordered::new("root", IndexPath::new(vec![0]), 2))
+        .unwrap();
+    queue
+        .push(Ordered::new("b", IndexPath::new(vec![0, 1]), 0).with_priority(5))
+        .unwrap();
+    queue
+        .push(Ordered::new("a", IndexPath::new(vec![0, 0]), 0))
+        .unwrap();
+    drop(queue);
+    let values: Vec<_> = iter.map(|ordered| ordered.value).collect();
+    assert_eq!(values, ["root", "a", "b"]);
+}
+
+#[test]
+fn walk_rayon_global() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(test_dir).sort(true));
     assert_eq!(
         paths,
         vec![
@@ -837,45 +3569,123 @@ fn walk_serial() {
 }
 
 #[test]
-fn sort_by_name_rayon_custom_2_threads() {
+fn walk_rayon_new_pool_with() {
     let (test_dir, _temp_dir) = test_dir();
+    let parallelism = Parallelism::RayonNewPoolWith {
+        num_threads: 2,
+        busy_timeout: Some(std::time::Duration::from_secs(1)),
+        fallback: PoolFallback::Error,
+    };
     let paths = local_paths(
-        WalkDir::new(test_dir)
-            .parallelism(Parallelism::RayonNewPool(2))
-            .sort(true),
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(parallelism.clone()),
     );
-    assert_eq!(
-        paths,
-        vec![
-            " (0)",
-            "a.txt (1)",
-            "b.txt (1)",
-            "c.txt (1)",
-            "group 1 (1)",
-            "group 1/d.txt (2)",
-            "group 2 (1)",
-            "group 2/e.txt (2)",
-        ]
+    assert_eq!(8, paths.len());
+    let par_count = WalkDir::new(test_dir)
+        .parallelism(parallelism)
+        .into_par_iter()
+        .filter(|dir_entry_result| dir_entry_result.is_ok())
+        .count();
+    assert_eq!(8, par_count);
+}
+
+#[test]
+fn walk_rayon_adaptive() {
+    let (test_dir, _temp_dir) = test_dir();
+    let serial = local_paths(
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(Parallelism::Serial),
+    );
+    let adaptive = local_paths(
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(Parallelism::Adaptive { min: 1, max: 4 }),
     );
+    assert_eq!(serial, adaptive);
 }
 
 #[test]
-fn walk_rayon_global() {
+fn walk_thread_pool() {
     let (test_dir, _temp_dir) = test_dir();
-    let paths = local_paths(WalkDir::new(test_dir).sort(true));
+    let iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonNewPool(2))
+        .into_iter();
+    assert!(matches!(iter.parallelism(), Parallelism::RayonNewPool(2)));
+    let thread_pool = iter.thread_pool().unwrap().clone();
+    assert_eq!(2, thread_pool.current_num_threads());
+    assert_eq!(8, thread_pool.install(|| iter.count()));
+
+    let pool = std::sync::Arc::new(rayon::ThreadPoolBuilder::new().build().unwrap());
+    let iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonExistingPool {
+            pool: pool.clone(),
+            busy_timeout: None,
+        })
+        .into_iter();
+    assert!(std::sync::Arc::ptr_eq(&pool, iter.thread_pool().unwrap()));
+
+    let iter = WalkDir::new(&test_dir).into_iter();
+    assert!(iter.thread_pool().is_none());
+    let iter = WalkDir::new(&test_dir).max_depth(1).into_iter();
+    assert!(matches!(iter.parallelism(), Parallelism::Serial));
+}
+
+#[test]
+fn walk_pause_resume() {
+    let (test_dir, _temp_dir) = test_dir();
+    let reads = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let walk_dir = |reads: std::sync::Arc<std::sync::atomic::AtomicUsize>| {
+        WalkDir::new(&test_dir)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .process_read_dir(move |_, _, _, _| {
+                reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            })
+    };
+
+    let iter = walk_dir(reads.clone()).into_iter();
+    iter.pause();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    let paused_reads = reads.load(std::sync::atomic::Ordering::SeqCst);
+    std::thread::sleep(std::time::Duration::from_millis(50));
     assert_eq!(
-        paths,
-        vec![
-            " (0)",
-            "a.txt (1)",
-            "b.txt (1)",
-            "c.txt (1)",
-            "group 1 (1)",
-            "group 1/d.txt (2)",
-            "group 2 (1)",
-            "group 2/e.txt (2)",
-        ]
+        paused_reads,
+        reads.load(std::sync::atomic::Ordering::SeqCst)
+    );
+    iter.resume();
+    assert_eq!(8, iter.count());
+
+    // Iterating while paused reads what the next entry needs
+    let iter = walk_dir(reads).into_iter();
+    iter.pause();
+    assert_eq!(8, iter.count());
+}
+
+#[test]
+fn walk_pause_frees_pool() {
+    let (test_dir, _temp_dir) = test_dir();
+    let pool = std::sync::Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap(),
     );
+    let iter = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonExistingPool {
+            pool: pool.clone(),
+            busy_timeout: None,
+        })
+        .into_iter();
+    iter.pause();
+
+    // Other work on the pool runs while the walk is paused.
+    let (tx, rx) = std::sync::mpsc::channel();
+    pool.spawn(move || tx.send(()).unwrap());
+    assert!(rx.recv_timeout(std::time::Duration::from_secs(5)).is_ok());
+
+    iter.resume();
+    assert_eq!(8, iter.count());
 }
 
 #[test]
@@ -946,6 +3756,71 @@ fn see_hidden_files() {
     assert!(paths.contains(&"group 2/.hidden_file.txt (2)".to_string()));
 }
 
+#[test]
+fn hidden_policy_dot_prefix() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(test_dir)
+            .hidden_policy(HiddenPolicy::DotPrefix)
+            .sort(true),
+    );
+    assert!(!paths.contains(&"group 2/.hidden_file.txt (2)".to_string()));
+}
+
+#[test]
+fn skip_hidden_by() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(test_dir)
+            .skip_hidden_by(|file_name, _| file_name == "group 1")
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 2 (1)",
+            "group 2/.hidden_file.txt (2)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+#[cfg(windows)]
+fn hidden_policy_windows_attribute() {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    let dir = Dir::tmp();
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .attributes(0x2)
+        .open(dir.join("hidden"))
+        .unwrap();
+    dir.touch("visible");
+
+    let r = dir.run_recursive(WalkDir::new(dir.path()).sort(true));
+    r.assert_no_errors();
+    assert_eq!(
+        vec![dir.path().to_path_buf(), dir.join("visible")],
+        r.paths()
+    );
+
+    let r = dir.run_recursive(
+        WalkDir::new(dir.path())
+            .hidden_policy(HiddenPolicy::DotPrefix)
+            .sort(true),
+    );
+    assert_eq!(3, r.paths().len());
+    let ents = r.ents();
+    assert_eq!(Some(0x2), ents[1].file_attributes().map(|a| a & 0x2));
+    assert_eq!(Some(0), ents[2].file_attributes().map(|a| a & 0x2));
+}
+
 #[test]
 fn walk_file() {
     let (test_dir, _temp_dir) = test_dir();
@@ -979,6 +3854,72 @@ fn error_when_path_does_not_exist() {
     assert!(iter.next().is_none());
 }
 
+#[test]
+fn try_into_iter_error_when_path_does_not_exist() {
+    let (test_dir, _temp_dir) = test_dir();
+    let missing = test_dir.join("path_does_not_exist");
+    let err = match WalkDir::new(&missing).try_into_iter() {
+        Ok(_) => panic!("root doesn't exist"),
+        Err(err) => err,
+    };
+    assert_eq!(Some(missing.as_path()), err.path());
+    assert_eq!(std::io::ErrorKind::NotFound, err.io_error().unwrap().kind());
+
+    let err = match WalkDir::new(&missing)
+        .canonicalize_root(true)
+        .try_into_iter()
+    {
+        Ok(_) => panic!("root doesn't exist"),
+        Err(err) => err,
+    };
+    assert_eq!(Operation::Canonicalize, err.operation());
+
+    assert!(WalkDir::new(&test_dir).try_into_iter().is_ok());
+}
+
+#[test]
+fn try_into_iter_several_roots() {
+    let dir = Dir::tmp();
+    dir.mkdirp("b");
+    dir.symlink_file("missing", "a");
+
+    let iter = WalkDir::new_glob(dir.join("*"))
+        .follow_links(true)
+        .sort(true)
+        .try_into_iter()
+        .unwrap();
+    let results: Vec<_> = iter.collect();
+    assert_eq!(2, results.len());
+    assert_eq!(
+        Some(&*dir.join("a")),
+        results[0].as_ref().unwrap_err().path()
+    );
+    assert_eq!(dir.join("b"), results[1].as_ref().unwrap().path());
+
+    let checkpoint = Checkpoint {
+        pending: vec![
+            PendingEntry {
+                path: dir.join("missing"),
+                depth: 0,
+                read_children: true,
+            },
+            PendingEntry {
+                path: dir.join("b"),
+                depth: 0,
+                read_children: true,
+            },
+        ],
+    };
+    let iter = WalkDir::new(dir.path())
+        .resume_from(checkpoint)
+        .try_into_iter()
+        .unwrap();
+    let results: Vec<_> = iter.collect();
+    assert_eq!(2, results.len());
+    assert!(results[0].is_err());
+    assert_eq!(dir.join("b"), results[1].as_ref().unwrap().path());
+}
+
 #[test]
 fn error_when_path_removed_durring_iteration() {
     let (test_dir, _temp_dir) = test_dir();
@@ -1079,6 +4020,60 @@ fn walk_relative_2() {
     assert_eq!(&root_dir_entry.file_name, ".");
 }
 
+#[test]
+fn walk_root_file_name() {
+    let _shared = RELATIVE_MUTEX.lock().unwrap();
+    let (test_dir, _temp_dir) = test_dir();
+    let canonical_dir = fs::canonicalize(&test_dir).unwrap();
+
+    env::set_current_dir(&test_dir).unwrap();
+
+    let root_entry = |root: &str, root_file_name| {
+        WalkDir::new(root)
+            .root_file_name(root_file_name)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap()
+    };
+    let entry = root_entry(".", RootName::FileName);
+    assert_eq!(canonical_dir.file_name().unwrap(), entry.file_name());
+    assert_eq!(PathBuf::from("."), entry.path());
+    let entry = root_entry(".", RootName::Canonical);
+    assert_eq!(canonical_dir.as_os_str(), entry.file_name());
+    assert_eq!(PathBuf::from("."), entry.path());
+    let entry = root_entry("group 1/..", RootName::AsGiven);
+    assert_eq!("group 1/..", entry.file_name());
+    assert_eq!(PathBuf::from("group 1/.."), entry.path());
+
+    for dir_entry in WalkDir::new("group 1/..").root_file_name(RootName::FileName) {
+        let dir_entry = dir_entry.unwrap();
+        assert!(dir_entry.path().strip_prefix("group 1/..").is_ok());
+        assert_eq!(Path::new("group 1/.."), dir_entry.root());
+    }
+}
+
+#[test]
+fn walk_canonicalize_root() {
+    let (test_dir, _temp_dir) = test_dir();
+    let canonical_dir = fs::canonicalize(&test_dir).unwrap();
+
+    let paths: Vec<_> = WalkDir::new(test_dir.join("group 1").join(".."))
+        .canonicalize_root(true)
+        .sort(true)
+        .into_iter()
+        .map(|each| each.unwrap().path())
+        .collect();
+    assert_eq!(paths[0], canonical_dir);
+    assert_eq!(paths[1], canonical_dir.join("a.txt"));
+
+    let mut iter = WalkDir::new(test_dir.join("path_does_not_exist"))
+        .canonicalize_root(true)
+        .into_iter();
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+}
+
 #[test]
 fn filter_groups_with_process_read_dir() {
     let (test_dir, _temp_dir) = test_dir();
@@ -1130,6 +4125,177 @@ fn filter_group_children_with_process_read_dir() {
     );
 }
 
+#[test]
+fn aggregate_sizes() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    fs::write(dir.join("a"), "1").unwrap();
+    fs::write(dir.join("foo/b"), "22").unwrap();
+    fs::write(dir.join("foo/bar/c"), "333").unwrap();
+
+    let entries: Vec<_> = WalkDirGeneric::<((), u64)>::new(dir.path())
+        .sort(true)
+        .process_read_dir(|_, _, _, children| {
+            for dir_entry in children.iter_mut().flatten() {
+                if dir_entry.file_type.is_file() {
+                    dir_entry.client_state = dir_entry.metadata().unwrap().len();
+                }
+            }
+        })
+        .into_iter()
+        .aggregate(|parent, child| parent.client_state += child.client_state)
+        .map(|each| {
+            let each = each.unwrap();
+            (each.path(), each.client_state)
+        })
+        .collect();
+
+    assert_eq!(
+        entries,
+        vec![
+            (dir.join("a"), 1),
+            (dir.join("foo/b"), 2),
+            (dir.join("foo/bar/c"), 3),
+            (dir.join("foo/bar"), 3),
+            (dir.join("foo"), 5),
+            (dir.path().to_path_buf(), 6),
+        ]
+    );
+}
+
+#[test]
+fn aggregate_relaxed() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    fs::write(dir.join("a"), "1").unwrap();
+    fs::write(dir.join("foo/bar/c"), "333").unwrap();
+
+    let folded = std::sync::atomic::AtomicUsize::new(0);
+    for walk_dir in [
+        WalkDir::new(dir.path()).yield_order(YieldOrder::Relaxed),
+        WalkDir::new(dir.path()).iterative_deepening(true),
+    ] {
+        let entries: Vec<_> = walk_dir
+            .into_iter()
+            .aggregate(|_, _| {
+                folded.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            })
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(5, entries.len());
+    }
+    // Descendants don't follow their directory, so nothing is folded
+    assert_eq!(0, folded.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[test]
+fn aggregate_accumulator() {
+    use jwalk::extras::Accumulator;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    fs::write(dir.join("a"), "1").unwrap();
+    fs::write(dir.join("foo/b"), "22").unwrap();
+    fs::write(dir.join("foo/bar/c"), "333").unwrap();
+
+    let entries: Vec<_> = Accumulator::walk_dir(dir.path())
+        .sort(true)
+        .into_iter()
+        .aggregate(Accumulator::fold)
+        .map(|each| each.unwrap())
+        .collect();
+    let totals: Vec<_> = entries
+        .iter()
+        .map(|each| (each.client_state.files, each.client_state.dirs))
+        .collect();
+    assert_eq!(totals, vec![(1, 0), (1, 0), (1, 0), (1, 1), (2, 2), (3, 3)]);
+
+    assert_eq!(1, entries[0].client_state.bytes);
+    assert_eq!(3, entries[2].client_state.bytes);
+    let root = &entries[5].client_state;
+    assert!(root.bytes >= 6);
+    let latest = entries.iter().filter_map(|each| each.client_state.modified);
+    assert_eq!(latest.max(), root.modified);
+    assert!(root.modified.is_some());
+}
+
+#[test]
+fn process_file_checksums() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo");
+    fs::write(dir.join("a"), "ab").unwrap();
+    fs::write(dir.join("foo/b"), "cd").unwrap();
+
+    let entries: Vec<_> = WalkDirGeneric::<((), u32)>::new(dir.path())
+        .sort(true)
+        .process_file(|dir_entry| {
+            let content = fs::read(dir_entry.path()).unwrap();
+            dir_entry.client_state = content.iter().map(|&byte| byte as u32).sum();
+        })
+        .into_iter()
+        .map(|each| {
+            let each = each.unwrap();
+            (each.path(), each.client_state)
+        })
+        .collect();
+
+    assert_eq!(
+        entries,
+        vec![
+            (dir.path().to_path_buf(), 0),
+            (dir.join("a"), 97 + 98),
+            (dir.join("foo"), 0),
+            (dir.join("foo/b"), 99 + 100),
+        ]
+    );
+}
+
+#[test]
+fn read_children_priority() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.mkdirp("b");
+    dir.mkdirp("c");
+
+    let read_order = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let walk_read_order = read_order.clone();
+    let paths: Vec<_> = WalkDir::new(dir.path())
+        .sort(true)
+        .parallelism(Parallelism::RayonNewPool(1))
+        .process_read_dir(move |depth, path, _, children| {
+            if depth.is_some() {
+                walk_read_order.lock().unwrap().push(path.to_owned());
+            }
+            for dir_entry in children.iter_mut().flatten() {
+                if dir_entry.file_name == "c" {
+                    dir_entry.read_children_priority = 1;
+                }
+            }
+        })
+        .into_iter()
+        .map(|each| each.unwrap().path())
+        .collect();
+
+    assert_eq!(
+        *read_order.lock().unwrap(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("c"),
+            dir.join("a"),
+            dir.join("b")
+        ]
+    );
+    assert_eq!(
+        paths,
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("a"),
+            dir.join("b"),
+            dir.join("c")
+        ]
+    );
+}
+
 #[test]
 fn test_read_linux() {
     // only run this test if linux_checkout present